@@ -0,0 +1,197 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::{Component, Path, PathBuf};
+
+use crate::fetcher::copy_dir;
+use crate::indexer::INDEX_FORMAT_VERSION;
+use crate::storage::{crates_dir, index_dir, lock_crate_path, split_crate_key, Database, SCHEMA_VERSION};
+
+/// Layout version of the bundle archive itself
+const BUNDLE_FORMAT: u32 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+const INDEX_FILE: &str = "index.db";
+const SOURCES_DIR: &str = "crates";
+
+/// First entry of every bundle, checked before anything else is unpacked
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format: u32,
+    schema_version: usize,
+    index_format: i64,
+    crates: Vec<BundledCrate>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundledCrate {
+    key: String,
+    sources: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportOutcome {
+    pub imported: Vec<String>,
+    pub skipped: Vec<String>,  // Already indexed locally
+    pub no_sources: Vec<String>, // Imported without sources, which aren't on disk here either
+}
+
+/// Scratch file or directory in the index directory, deleted on drop
+struct TempPath(PathBuf);
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0).or_else(|_| fs::remove_file(&self.0));
+    }
+}
+
+fn temp_path(prefix: &str) -> Result<TempPath> {
+    fs::create_dir_all(index_dir())?;
+    let path = index_dir().join(format!("{}-{}", prefix, std::process::id()));
+    let _ = fs::remove_dir_all(&path).or_else(|_| fs::remove_file(&path));
+    Ok(TempPath(path))
+}
+
+/// Write a `.tar.zst` bundle with the index rows of `keys` and, if `with_sources`, their sources
+pub fn export(db: &Database, keys: &[String], output: &Path, with_sources: bool) -> Result<()> {
+    let index = temp_path("export.db")?;
+    db.export_crates(keys, &index.0)?;
+
+    let mut crates = Vec::new();
+    let mut sources = Vec::new();
+    for key in keys {
+        if with_sources {
+            let path = db.get_crate_path(key)?
+                .filter(|p| p.is_dir())
+                .with_context(|| format!("Sources of {} are missing; fetch it again or export without --sources", key))?;
+            sources.push((key, path));
+        }
+        crates.push(BundledCrate { key: key.clone(), sources: with_sources });
+    }
+    let manifest = Manifest {
+        format: BUNDLE_FORMAT,
+        schema_version: SCHEMA_VERSION,
+        index_format: INDEX_FORMAT_VERSION,
+        crates,
+    };
+
+    let file = File::create(output).with_context(|| format!("Failed to create {:?}", output))?;
+    let mut archive = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, MANIFEST_FILE, manifest.as_slice())?;
+    archive.append_path_with_name(&index.0, INDEX_FILE)?;
+    for (key, path) in sources {
+        archive.append_dir_all(format!("{}/{}", SOURCES_DIR, key), &path)
+            .with_context(|| format!("Failed to add sources of {}", key))?;
+    }
+    archive.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Merge the crates of a bundle into the index. Crates already indexed are
+/// skipped unless `overwrite` is set.
+pub fn import(db: &Database, bundle: &Path, overwrite: bool) -> Result<ImportOutcome> {
+    let staging = temp_path("import")?;
+    let manifest = unpack(bundle, &staging.0)?;
+    let bundle_index = staging.0.join(INDEX_FILE);
+
+    let mut outcome = ImportOutcome::default();
+    for c in &manifest.crates {
+        if !overwrite && db.get_crate_id(&c.key)?.is_some() {
+            outcome.skipped.push(c.key.clone());
+            continue;
+        }
+
+        let dest = crates_dir().join(&c.key);
+        let _lock = lock_crate_path(&c.key)?;
+        if c.sources {
+            let src = staging.0.join(SOURCES_DIR).join(&c.key);
+            if dest.exists() {
+                fs::remove_dir_all(&dest).with_context(|| format!("Failed to replace {:?}", dest))?;
+            }
+            fs::create_dir_all(crates_dir())?;
+            if fs::rename(&src, &dest).is_err() {
+                copy_dir(&src, &dest)?;
+            }
+        } else if !dest.exists() {
+            outcome.no_sources.push(c.key.clone());
+        }
+
+        let path = dest.exists().then_some(dest.as_path());
+        db.import_crate(&bundle_index, &c.key, path)
+            .with_context(|| format!("Failed to import {}", c.key))?;
+        outcome.imported.push(c.key.clone());
+    }
+    Ok(outcome)
+}
+
+/// Keys become directory names under the crates directory, so a bundle may only
+/// name crates as one plain `name-version` path component
+fn check_key(key: &str) -> Result<()> {
+    let mut components = Path::new(key).components();
+    let plain = matches!((components.next(), components.next()), (Some(Component::Normal(name)), None) if name == key);
+    if !plain || split_crate_key(key).1.is_none() {
+        bail!("Invalid crate key {:?} in bundle manifest", key);
+    }
+    Ok(())
+}
+
+/// Unpack a bundle into `dir`, checking its manifest before anything else
+fn unpack(bundle: &Path, dir: &Path) -> Result<Manifest> {
+    let file = File::open(bundle).with_context(|| format!("Failed to open {:?}", bundle))?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut entries = archive.entries().context("Not a bundle (expected a .tar.zst archive)")?;
+
+    let mut first = entries.next().context("Bundle is empty")??;
+    if first.path()?.as_os_str() != MANIFEST_FILE {
+        bail!("Not a crate-indexer bundle (missing {})", MANIFEST_FILE);
+    }
+    let manifest: Manifest = serde_json::from_reader(&mut first).context("Invalid bundle manifest")?;
+    for c in &manifest.crates {
+        check_key(&c.key)?;
+    }
+    if manifest.format != BUNDLE_FORMAT {
+        bail!("Unsupported bundle format {} (this build reads format {})", manifest.format, BUNDLE_FORMAT);
+    }
+    if manifest.schema_version > SCHEMA_VERSION {
+        bail!(
+            "Bundle was written with schema version {}, but this build only supports up to {}. Upgrade crate-indexer to import it.",
+            manifest.schema_version,
+            SCHEMA_VERSION
+        );
+    }
+    if manifest.index_format < INDEX_FORMAT_VERSION {
+        eprintln!(
+            "Note: bundle was indexed with an older format ({} < {}); crates with sources are re-indexed when first queried.",
+            manifest.index_format, INDEX_FORMAT_VERSION
+        );
+    }
+
+    fs::create_dir_all(dir)?;
+    for entry in entries {
+        // unpack_in refuses paths escaping `dir`
+        entry?.unpack_in(dir)?;
+    }
+    if !dir.join(INDEX_FILE).exists() {
+        bail!("Bundle has no {}", INDEX_FILE);
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_key() {
+        for key in ["serde-1.0.200", "foo-bar-1.0.0-alpha.1", "demo-0.1.0+git.abc1234"] {
+            assert!(check_key(key).is_ok(), "{}", key);
+        }
+        for key in ["../../x-1.0.0", "/tmp/x-1.0.0", "a/b-1.0.0", "x-1.0.0/", ".", "", "serde", "C:x-1.0.0/.."] {
+            assert!(check_key(key).is_err(), "{}", key);
+        }
+    }
+}
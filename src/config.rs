@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const DEFAULT_HOME_DIR: &str = ".crate-indexer";
+const CONFIG_FILE: &str = "config.toml";
+const DEFAULT_VERSION_TTL_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_READ_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_MAX_RETRIES: u32 = 4;
+const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 8;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// User settings read from `config.toml` in the home directory (`$CRATE_INDEXER_HOME`
+/// or `~/.crate-indexer`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// Never touch the network; crates are resolved from the index and the local Cargo registry
+    pub offline: bool,
+    /// How long a resolved "latest" version is trusted before crates.io is asked again
+    pub version_ttl_secs: u64,
+    /// Timeout for establishing a connection to crates.io
+    pub connect_timeout_secs: u64,
+    /// Timeout for a whole request, including reading the response body
+    pub read_timeout_secs: u64,
+    /// Retries for timeouts, 429 and 5xx responses
+    pub max_retries: u32,
+    /// Maximum number of HTTP requests in flight at once
+    pub max_concurrent_downloads: usize,
+    /// Directory holding the database and downloaded crates (defaults to the home directory)
+    pub index_dir: Option<PathBuf>,
+    /// Read-only indexes that queries fall through to, e.g. a team index on a network mount
+    pub shared_indexes: Vec<PathBuf>,
+    /// Profile used when none is selected with `--profile` or `CRATE_INDEXER_PROFILE`
+    pub default_profile: Option<String>,
+    /// Named indexes, e.g. one per project
+    pub profiles: BTreeMap<String, Profile>,
+    /// Keep compressed copies of crate sources, manifests and READMEs in the index,
+    /// so reading and searching them doesn't depend on the `crates/` directory
+    pub store_file_contents: bool,
+    /// Ignore cached versions for this run (set by `--refresh`, not read from the file)
+    #[serde(skip)]
+    pub refresh: bool,
+}
+
+/// Index settings of a named profile; unset values fall back to the top-level ones
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Profile {
+    /// Defaults to `profiles/<name>` in the home directory
+    pub index_dir: Option<PathBuf>,
+    pub shared_indexes: Option<Vec<PathBuf>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            offline: false,
+            version_ttl_secs: DEFAULT_VERSION_TTL_SECS,
+            connect_timeout_secs: DEFAULT_CONNECT_TIMEOUT_SECS,
+            read_timeout_secs: DEFAULT_READ_TIMEOUT_SECS,
+            max_retries: DEFAULT_MAX_RETRIES,
+            max_concurrent_downloads: DEFAULT_MAX_CONCURRENT_DOWNLOADS,
+            index_dir: None,
+            shared_indexes: Vec::new(),
+            default_profile: None,
+            profiles: BTreeMap::new(),
+            store_file_contents: true,
+            refresh: false,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file and apply `profile` (or `CRATE_INDEXER_PROFILE`, or the
+    /// configured default profile)
+    pub fn load(profile: Option<&str>) -> Result<Self> {
+        let path = home_dir().join(CONFIG_FILE);
+        let mut config: Config = if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read config file {:?}", path))?;
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse config file {:?}", path))?
+        } else {
+            Config::default()
+        };
+
+        if env_flag("CRATE_INDEXER_OFFLINE") {
+            config.offline = true;
+        }
+
+        let profile = profile
+            .map(String::from)
+            .or_else(|| std::env::var("CRATE_INDEXER_PROFILE").ok().filter(|p| !p.is_empty()))
+            .or_else(|| config.default_profile.clone());
+        if let Some(name) = profile {
+            config.apply_profile(&name)?;
+        }
+
+        config.index_dir = config.index_dir.map(|dir| resolve_path(&dir));
+        config.shared_indexes = config.shared_indexes.iter().map(|dir| resolve_path(dir)).collect();
+        Ok(config)
+    }
+
+    fn apply_profile(&mut self, name: &str) -> Result<()> {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            anyhow::bail!("Unknown profile '{}' (configured: {})", name, if known.is_empty() { "none".to_string() } else { known.join(", ") });
+        };
+        self.index_dir = Some(profile.index_dir.unwrap_or_else(|| home_dir().join("profiles").join(name)));
+        if let Some(shared) = profile.shared_indexes {
+            self.shared_indexes = shared;
+        }
+        Ok(())
+    }
+
+    /// Writable index directory: `--index-dir`, the profile's or configured one, or the home directory
+    pub fn index_dir(&self) -> PathBuf {
+        self.index_dir.clone().unwrap_or_else(home_dir)
+    }
+}
+
+/// Directory holding `config.toml` and, by default, the index: `$CRATE_INDEXER_HOME` or `~/.crate-indexer`
+pub fn home_dir() -> PathBuf {
+    match std::env::var_os("CRATE_INDEXER_HOME").filter(|h| !h.is_empty()) {
+        Some(home) => std::path::absolute(&home).unwrap_or_else(|_| PathBuf::from(home)),
+        None => dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(DEFAULT_HOME_DIR),
+    }
+}
+
+/// Expand a leading `~` and make relative paths relative to the home directory
+fn resolve_path(path: &Path) -> PathBuf {
+    if let Ok(rest) = path.strip_prefix("~") {
+        if let Some(user_home) = dirs::home_dir() {
+            return user_home.join(rest);
+        }
+    }
+    if path.is_relative() {
+        return home_dir().join(path);
+    }
+    path.to_path_buf()
+}
+
+/// Install the configuration for this process. Must be called before the first `get()`.
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+/// Get the active configuration, loading it from disk if `init` was never called
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(|| {
+        if cfg!(test) {
+            // Tests never see the user's config file or index
+            let index_dir = std::env::temp_dir().join(format!("crate-indexer-test-{}", std::process::id()));
+            return Config { index_dir: Some(index_dir), ..Config::default() };
+        }
+        Config::load(None).unwrap_or_else(|e| {
+            eprintln!("Warning: {}", e);
+            Config::default()
+        })
+    })
+}
+
+fn env_flag(name: &str) -> bool {
+    std::env::var(name)
+        .map(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        let mut config: Config = toml::from_str(
+            r#"
+            shared-indexes = ["/mnt/team/crate-index"]
+
+            [profiles.work]
+            index-dir = "/data/work-index"
+
+            [profiles.solo]
+            shared-indexes = []
+            "#,
+        )
+        .unwrap();
+
+        let mut work = config.clone();
+        work.apply_profile("work").unwrap();
+        assert_eq!(work.index_dir(), PathBuf::from("/data/work-index"));
+        assert_eq!(work.shared_indexes, [PathBuf::from("/mnt/team/crate-index")]);
+
+        config.apply_profile("solo").unwrap();
+        assert_eq!(config.index_dir(), home_dir().join("profiles").join("solo"));
+        assert!(config.shared_indexes.is_empty());
+
+        assert!(config.apply_profile("missing").unwrap_err().to_string().contains("work"));
+    }
+}
@@ -4,6 +4,8 @@ use embed_anything::embeddings::embed::{Embedder, EmbedderBuilder};
 use embed_anything::embeddings::local::text_embedding::ONNXModel;
 use std::sync::Arc;
 
+use crate::storage::{ConstantInfo, Database, EnumInfo, FunctionInfo, MacroInfo, StructInfo, TraitInfo, TypeAliasInfo};
+
 const BATCH_SIZE: usize = 32;
 
 pub struct EmbeddingManager {
@@ -55,6 +57,120 @@ impl EmbeddingManager {
     }
 }
 
+/// Items of a crate without an up-to-date embedding, as (id, item type, text):
+/// new items and those whose text changed since their embedding was generated
+pub fn pending_items(db: &Database, crate_key: &str) -> Result<Vec<(String, String, String)>> {
+    let mut items: Vec<(String, &str, String)> = Vec::new();
+    for f in db.get_functions(crate_key)? {
+        let text = function_text(&f);
+        items.push((f.id, "function", text));
+    }
+    for s in db.get_structs(crate_key)? {
+        let text = struct_text(&s);
+        items.push((s.id, "struct", text));
+    }
+    for e in db.get_enums(crate_key)? {
+        let text = enum_text(&e);
+        items.push((e.id, "enum", text));
+    }
+    for t in db.get_traits(crate_key)? {
+        let text = trait_text(&t);
+        items.push((t.id, "trait", text));
+    }
+    for m in db.get_macros(crate_key)? {
+        let text = macro_text(&m);
+        items.push((m.id, "macro", text));
+    }
+    for t in db.get_type_aliases(crate_key)? {
+        let text = type_alias_text(&t);
+        items.push((t.id, "type_alias", text));
+    }
+    for c in db.get_constants(crate_key)? {
+        let text = constant_text(&c);
+        items.push((c.id, "constant", text));
+    }
+
+    let embedded = db.get_embedded_texts(crate_key)?;
+    Ok(items
+        .into_iter()
+        .filter(|(id, _, text)| embedded.get(id) != Some(text))
+        .map(|(id, item_type, text)| (id, item_type.to_string(), text))
+        .collect())
+}
+
+fn function_text(func: &FunctionInfo) -> String {
+    let mut text = func.signature.clone();
+    if let Some(docs) = &func.docs {
+        text.push_str(". ");
+        text.push_str(docs);
+    }
+    text
+}
+
+fn struct_text(s: &StructInfo) -> String {
+    let mut text = format!("struct {}", s.name);
+    if !s.fields.is_empty() {
+        let field_names: Vec<&str> = s.fields.iter().map(|f| f.name.as_str()).collect();
+        text.push_str(" with fields: ");
+        text.push_str(&field_names.join(", "));
+    }
+    if let Some(docs) = &s.docs {
+        text.push_str(". ");
+        text.push_str(docs);
+    }
+    text
+}
+
+fn enum_text(e: &EnumInfo) -> String {
+    let mut text = format!("enum {}", e.name);
+    if !e.variants.is_empty() {
+        let variant_names: Vec<&str> = e.variants.iter().map(|v| v.name.as_str()).collect();
+        text.push_str(" with variants: ");
+        text.push_str(&variant_names.join(", "));
+    }
+    if let Some(docs) = &e.docs {
+        text.push_str(". ");
+        text.push_str(docs);
+    }
+    text
+}
+
+fn trait_text(t: &TraitInfo) -> String {
+    let mut text = format!("trait {}", t.name);
+    if let Some(docs) = &t.docs {
+        text.push_str(". ");
+        text.push_str(docs);
+    }
+    text
+}
+
+fn macro_text(m: &MacroInfo) -> String {
+    let mut text = format!("macro {}!", m.name);
+    if let Some(docs) = &m.docs {
+        text.push_str(". ");
+        text.push_str(docs);
+    }
+    text
+}
+
+fn type_alias_text(t: &TypeAliasInfo) -> String {
+    let mut text = format!("type {} = {}", t.name, t.type_str);
+    if let Some(docs) = &t.docs {
+        text.push_str(". ");
+        text.push_str(docs);
+    }
+    text
+}
+
+fn constant_text(c: &ConstantInfo) -> String {
+    let mut text = format!("{} {}: {}", c.kind, c.name, c.type_str);
+    if let Some(docs) = &c.docs {
+        text.push_str(". ");
+        text.push_str(docs);
+    }
+    text
+}
+
 pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() {
         return 0.0;
@@ -0,0 +1,348 @@
+use anyhow::Result;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::fetcher::{self, Fetcher};
+use crate::git;
+use crate::indexer::{self, index_crate};
+use crate::storage::{self, Database};
+
+/// A crate that could not be resolved, downloaded or indexed
+pub struct FetchFailure {
+    pub crate_name: String,
+    pub error: anyhow::Error,
+}
+
+/// A crate that was downloaded and added to the index
+pub struct IndexedCrate {
+    pub key: String,
+    pub functions: usize,
+    pub structs: usize,
+    pub enums: usize,
+    pub traits: usize,
+}
+
+/// What a `fetch_crates` run indexed and what it could not
+#[derive(Default)]
+pub struct FetchReport {
+    pub indexed: Vec<IndexedCrate>,
+    pub failures: Vec<FetchFailure>,
+}
+
+pub fn fetch_single_crate(db: &Database, name: &str, version: Option<&str>, progress: bool) -> Result<FetchReport> {
+    fetch_crates(db, vec![(name.to_string(), version.map(String::from))], 0, progress)
+}
+
+/// Which version of a queued crate to fetch
+enum VersionSpec {
+    Latest,
+    Exact(String),
+    /// Requirement from a dependent's Cargo.toml
+    Req(semver::VersionReq),
+}
+
+struct PendingCrate {
+    name: String,
+    spec: VersionSpec,
+    /// Remaining levels of normal dependencies to follow from this crate
+    dep_depth: usize,
+}
+
+/// Fetch and index a set of crates (and their re-exports) in parallel batches.
+/// With `dep_depth > 0`, normal non-optional dependencies are followed that many
+/// levels deep, each resolved to the highest version matching its requirement.
+/// Crates that fail are reported back instead of aborting the whole batch.
+/// With `progress`, each step is printed as it happens; the MCP server turns it
+/// off because stdout carries its protocol.
+pub fn fetch_crates(
+    db: &Database,
+    roots: Vec<(String, Option<String>)>,
+    dep_depth: usize,
+    progress: bool,
+) -> Result<FetchReport> {
+    let fetcher = Fetcher::new()?;
+    let mut fetched: HashSet<String> = db.list_crate_keys()?.into_iter().collect();
+    let mut queued: HashSet<String> = roots.iter().map(|(name, _)| name.clone()).collect(); // Track crates already queued
+    let mut expanded: HashSet<String> = HashSet::new(); // Crates whose dependencies were already queued
+    let mut known_versions: HashMap<String, Vec<semver::Version>> = HashMap::new();
+    let mut to_fetch: Vec<PendingCrate> = roots
+        .into_iter()
+        .map(|(name, version)| PendingCrate {
+            name,
+            spec: version.map_or(VersionSpec::Latest, VersionSpec::Exact),
+            dep_depth,
+        })
+        .collect();
+    let mut report = FetchReport::default();
+
+    while !to_fetch.is_empty() {
+        // Take current batch
+        let batch: Vec<_> = std::mem::take(&mut to_fetch);
+
+        // Resolve versions: cached lookups first, then crates.io in parallel
+        if progress && batch.len() > 1 {
+            println!("Resolving {} crate(s)...", batch.len());
+        }
+        let mut latest: HashMap<String, String> = HashMap::new();
+        for pending in &batch {
+            if matches!(pending.spec, VersionSpec::Latest) {
+                if let Some(v) = fetcher.cached_latest_version(db, &pending.name)? {
+                    latest.insert(pending.name.clone(), v);
+                }
+            }
+        }
+        let mut unresolved_reqs: HashSet<&str> = HashSet::new();
+        for pending in &batch {
+            if let VersionSpec::Req(req) = &pending.spec {
+                if indexed_matching_version(db, &pending.name, req)?.is_none()
+                    && !known_versions.contains_key(&pending.name)
+                {
+                    unresolved_reqs.insert(&pending.name);
+                }
+            }
+        }
+        let latest_lookups: Vec<(String, Result<String>)> = batch
+            .par_iter()
+            .filter(|p| matches!(p.spec, VersionSpec::Latest) && !latest.contains_key(&p.name))
+            .map(|p| (p.name.clone(), fetcher.get_latest_version(&p.name)))
+            .collect();
+        let version_lookups: Vec<(String, Result<Vec<semver::Version>>)> = unresolved_reqs
+            .par_iter()
+            .map(|name| (name.to_string(), fetcher.get_versions(name)))
+            .collect();
+
+        let mut lookup_errors: HashMap<String, anyhow::Error> = HashMap::new();
+        for (crate_name, lookup) in latest_lookups {
+            match lookup {
+                Ok(version) => {
+                    fetcher.remember_latest_version(db, &crate_name, &version)?;
+                    latest.insert(crate_name, version);
+                }
+                Err(error) => {
+                    lookup_errors.insert(crate_name, error);
+                }
+            }
+        }
+        for (crate_name, lookup) in version_lookups {
+            match lookup {
+                Ok(versions) => {
+                    known_versions.insert(crate_name, versions);
+                }
+                Err(error) => {
+                    lookup_errors.insert(crate_name, error);
+                }
+            }
+        }
+
+        let mut resolved: Vec<(String, String, usize)> = Vec::new();
+        for pending in batch {
+            let version = match &pending.spec {
+                VersionSpec::Exact(v) => Some(v.clone()),
+                VersionSpec::Latest => latest.get(&pending.name).cloned(),
+                VersionSpec::Req(req) => match indexed_matching_version(db, &pending.name, req)? {
+                    Some(v) => Some(v),
+                    None => known_versions
+                        .get(&pending.name)
+                        .and_then(|versions| fetcher::matching_version(versions, req))
+                        .map(|v| v.to_string()),
+                },
+            };
+
+            let Some(version) = version else {
+                let error = lookup_errors.remove(&pending.name).unwrap_or_else(|| match &pending.spec {
+                    VersionSpec::Req(req) => anyhow::anyhow!("No published version matches {}", req),
+                    _ => anyhow::anyhow!("Could not resolve a version"),
+                });
+                if progress {
+                    eprintln!("Warning: Could not fetch {}: {:#}", pending.name, error);
+                }
+                report.failures.push(FetchFailure { crate_name: pending.name, error });
+                continue;
+            };
+
+            let key = format!("{}-{}", pending.name, version);
+            if fetched.contains(&key) {
+                // Already indexed, but its own dependencies may still be missing
+                if pending.dep_depth > 0 && expanded.insert(key.clone()) {
+                    let deps = indexed_dependencies(db, &key)?;
+                    queue_dependencies(&deps, pending.dep_depth - 1, &mut queued, &mut to_fetch, progress);
+                }
+            } else {
+                resolved.push((pending.name, version, pending.dep_depth));
+            }
+        }
+
+        if resolved.is_empty() {
+            continue;
+        }
+
+        // Deduplicate resolved crates (same crate could be queued from multiple sources),
+        // keeping the deepest dependency walk requested for each
+        let mut deduped: HashMap<(String, String), usize> = HashMap::new();
+        for (crate_name, version, depth) in resolved {
+            let entry = deduped.entry((crate_name, version)).or_default();
+            *entry = (*entry).max(depth);
+        }
+        let resolved: Vec<_> = deduped
+            .into_iter()
+            .map(|((crate_name, version), depth)| (crate_name, version, depth))
+            .collect();
+
+        // Download and index in parallel (HTTP concurrency is capped by the fetcher)
+        if progress && resolved.len() > 1 {
+            println!("Downloading and indexing {} crate(s) in parallel...", resolved.len());
+        }
+        let results: Vec<_> = resolved
+            .par_iter()
+            .map(|(crate_name, version, depth)| {
+                let key = format!("{}-{}", crate_name, version);
+
+                let crate_path = match fetcher.fetch_crate(crate_name, version) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        if progress {
+                            eprintln!("Warning: Could not download {}: {:#}", key, e);
+                        }
+                        return Err(FetchFailure { crate_name: key, error: e });
+                    }
+                };
+
+                if progress {
+                    println!("Indexing {}...", key);
+                }
+                match index_crate(&crate_path, &key) {
+                    Ok(result) => {
+                        if progress {
+                            println!("  {} fns, {} structs, {} enums, {} traits, {} macros, {} types, {} consts, {} impls",
+                                result.items.functions.len(),
+                                result.items.structs.len(),
+                                result.items.enums.len(),
+                                result.items.traits.len(),
+                                result.items.macros.len(),
+                                result.items.type_aliases.len(),
+                                result.items.constants.len(),
+                                result.items.impls.len());
+                        }
+                        Ok((key, crate_path, result, *depth))
+                    }
+                    Err(e) => {
+                        if progress {
+                            eprintln!("Warning: Failed to index {}: {:#}", key, e);
+                        }
+                        Err(FetchFailure { crate_name: key, error: e })
+                    }
+                }
+            })
+            .collect();
+
+        // Store results and collect re-exports and dependencies (sequential for DB writes)
+        for result in results {
+            let (key, crate_path, result, depth) = match result {
+                Ok(r) => r,
+                Err(failure) => {
+                    report.failures.push(failure);
+                    continue;
+                }
+            };
+
+            if !result.reexported_crates.is_empty() {
+                if progress {
+                    println!("  {} re-exports: {:?}", key, result.reexported_crates);
+                }
+
+                for reexport in &result.reexported_crates {
+                    let already_have = fetched.iter().any(|k| k.starts_with(&format!("{}-", reexport)));
+                    if !already_have && !queued.contains(reexport) {
+                        queued.insert(reexport.clone());
+                        to_fetch.push(PendingCrate {
+                            name: reexport.clone(),
+                            spec: VersionSpec::Latest,
+                            dep_depth: 0,
+                        });
+                    }
+                }
+            }
+
+            if depth > 0 && expanded.insert(key.clone()) {
+                queue_dependencies(&result.items.dependencies, depth - 1, &mut queued, &mut to_fetch, progress);
+            }
+
+            db.add_crate(&key, &crate_path, &result.items, &result.reexported_crates)?;
+            report.indexed.push(IndexedCrate {
+                key: key.clone(),
+                functions: result.items.functions.len(),
+                structs: result.items.structs.len(),
+                enums: result.items.enums.len(),
+                traits: result.items.traits.len(),
+            });
+            fetched.insert(key);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Queue the normal, non-optional dependencies of a crate for `fetch_crates`
+fn queue_dependencies(
+    deps: &[storage::DependencyInfo],
+    dep_depth: usize,
+    queued: &mut HashSet<String>,
+    to_fetch: &mut Vec<PendingCrate>,
+    progress: bool,
+) {
+    for dep in deps.iter().filter(|d| d.kind == "normal" && !d.optional) {
+        if !queued.insert(format!("{}@{}", dep.name, dep.req)) {
+            continue;
+        }
+        match semver::VersionReq::parse(&dep.req) {
+            Ok(req) => to_fetch.push(PendingCrate {
+                name: dep.name.clone(),
+                spec: VersionSpec::Req(req),
+                dep_depth,
+            }),
+            Err(e) => {
+                if progress {
+                    eprintln!("Warning: Skipping {} (invalid requirement '{}': {})", dep.name, dep.req, e);
+                }
+            }
+        }
+    }
+}
+
+/// Highest indexed version of a crate that satisfies `req`
+pub fn indexed_matching_version(db: &Database, name: &str, req: &semver::VersionReq) -> Result<Option<String>> {
+    let prefix = format!("{}-", name);
+    let versions: Vec<semver::Version> = db
+        .find_all_crate_keys(name)?
+        .iter()
+        .filter(|key| !git::is_git_key(key))
+        .filter_map(|key| key.strip_prefix(&prefix))
+        .filter_map(|v| semver::Version::parse(v).ok())
+        .collect();
+    Ok(fetcher::matching_version(&versions, req).map(|v| v.to_string()))
+}
+
+/// Package metadata of an indexed crate, reading its Cargo.toml for crates indexed
+/// before metadata was recorded
+pub fn indexed_metadata(db: &Database, key: &str) -> Result<storage::PackageMetadata> {
+    if let Some(meta) = db.get_metadata(key)? {
+        return Ok(meta);
+    }
+    Ok(db
+        .get_crate_path(key)?
+        .map(|path| indexer::parse_package_metadata(&path))
+        .unwrap_or_default())
+}
+
+/// Dependencies of an indexed crate, reading its Cargo.toml for crates indexed
+/// before dependencies were recorded
+pub fn indexed_dependencies(db: &Database, key: &str) -> Result<Vec<storage::DependencyInfo>> {
+    let deps = db.get_dependencies(key)?;
+    if !deps.is_empty() {
+        return Ok(deps);
+    }
+    Ok(db
+        .get_crate_path(key)?
+        .map(|path| indexer::parse_manifest_dependencies(&path))
+        .unwrap_or_default())
+}
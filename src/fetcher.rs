@@ -1,18 +1,33 @@
 use anyhow::{bail, Context, Result};
 use flate2::read::GzDecoder;
 use reqwest::blocking::Client;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use semver::{Version, VersionReq};
 use serde::Deserialize;
 use std::fs;
 use std::io::Cursor;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::Duration;
 use tar::Archive;
+use walkdir::WalkDir;
 
-use crate::storage::crate_path;
+use crate::config;
+use crate::storage::{crate_path, lock_crate_path, split_crate_key, Database};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Caps concurrent HTTP requests across all fetchers and rayon threads
+static REQUEST_PERMITS: OnceLock<Semaphore> = OnceLock::new();
 
 #[derive(Debug, Deserialize)]
 struct CrateResponse {
     #[serde(rename = "crate")]
     crate_info: CrateMetadata,
+    #[serde(default)]
+    versions: Vec<VersionMetadata>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -21,42 +36,185 @@ struct CrateMetadata {
     max_version: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct VersionMetadata {
+    num: String,
+    yanked: bool,
+}
+
 pub struct Fetcher {
     client: Client,
+    offline: bool,
+    refresh: bool,          // Ignore cached "latest" versions
+    version_ttl_secs: u64,  // How long a cached "latest" version stays fresh
+    max_retries: u32,
+    permits: &'static Semaphore,
 }
 
 impl Fetcher {
     pub fn new() -> Result<Self> {
+        Self::with_config(config::get())
+    }
+
+    fn with_config(settings: &config::Config) -> Result<Self> {
         let client = Client::builder()
             .user_agent("crate-indexer/0.1.0")
+            .connect_timeout(Duration::from_secs(settings.connect_timeout_secs))
+            .timeout(Duration::from_secs(settings.read_timeout_secs))
             .build()?;
-        Ok(Self { client })
+        let permits = REQUEST_PERMITS
+            .get_or_init(|| Semaphore::new(settings.max_concurrent_downloads.max(1)));
+        Ok(Self {
+            client,
+            offline: settings.offline,
+            refresh: settings.refresh,
+            version_ttl_secs: settings.version_ttl_secs,
+            max_retries: settings.max_retries,
+            permits,
+        })
     }
 
-    pub fn get_latest_version(&self, crate_name: &str) -> Result<String> {
+    /// GET a URL and read the body, retrying timeouts, 429 and 5xx responses
+    /// with exponential backoff (honoring `Retry-After`)
+    fn get_bytes(&self, url: &str) -> Result<Vec<u8>> {
+        let mut attempt = 0;
+        loop {
+            let outcome = {
+                let _permit = self.permits.acquire();
+                self.client.get(url).send().and_then(|response| {
+                    let status = response.status();
+                    let retry_after = parse_retry_after(response.headers());
+                    response.bytes().map(|body| (status, retry_after, body))
+                })
+            };
+
+            let can_retry = attempt < self.max_retries;
+            let delay = match outcome {
+                Ok((status, _, body)) if status.is_success() => return Ok(body.to_vec()),
+                Ok((status, retry_after, _)) if can_retry && is_retryable_status(status) => {
+                    retry_after.unwrap_or_else(|| backoff(attempt))
+                }
+                Ok((status, _, _)) => bail!("HTTP {} from {}", status.as_u16(), url),
+                Err(e) if can_retry && (e.is_timeout() || e.is_connect() || e.is_body()) => {
+                    backoff(attempt)
+                }
+                Err(e) => return Err(e).with_context(|| format!("Request to {} failed", url)),
+            };
+
+            attempt += 1;
+            eprintln!(
+                "Retrying {} in {:.1}s (attempt {}/{})",
+                url,
+                delay.as_secs_f32(),
+                attempt,
+                self.max_retries
+            );
+            std::thread::sleep(delay);
+        }
+    }
+
+    fn get_crate_metadata(&self, crate_name: &str) -> Result<CrateResponse> {
         let url = format!("https://crates.io/api/v1/crates/{}", crate_name);
-        let response: CrateResponse = self
-            .client
-            .get(&url)
-            .send()
-            .with_context(|| format!("Failed to fetch crate info for {}", crate_name))?
-            .json()
-            .with_context(|| "Failed to parse crate metadata")?;
+        let body = self
+            .get_bytes(&url)
+            .with_context(|| format!("Failed to fetch crate info for {}", crate_name))?;
+        serde_json::from_slice(&body).with_context(|| "Failed to parse crate metadata")
+    }
 
+    /// Latest version of a crate, served from the index cache while it is fresh
+    pub fn latest_version_cached(&self, db: &Database, crate_name: &str) -> Result<String> {
+        self.latest_version_with(db, crate_name, |name| self.get_latest_version(name))
+    }
+
+    fn latest_version_with(
+        &self,
+        db: &Database,
+        crate_name: &str,
+        lookup: impl FnOnce(&str) -> Result<String>,
+    ) -> Result<String> {
+        if let Some(version) = self.cached_latest_version(db, crate_name)? {
+            return Ok(version);
+        }
+        let version = lookup(crate_name)?;
+        self.remember_latest_version(db, crate_name, &version)?;
+        Ok(version)
+    }
+
+    /// Cached "latest" version of a crate, unless `--refresh` was given or the entry expired
+    pub fn cached_latest_version(&self, db: &Database, crate_name: &str) -> Result<Option<String>> {
+        if self.refresh {
+            return Ok(None);
+        }
+        db.get_cached_latest_version(crate_name, self.version_ttl_secs)
+    }
+
+    /// Remember a version resolved from crates.io. Offline resolutions only reflect
+    /// the local Cargo registry, so they are not cached.
+    pub fn remember_latest_version(&self, db: &Database, crate_name: &str, version: &str) -> Result<()> {
+        if self.offline {
+            return Ok(());
+        }
+        db.set_cached_latest_version(crate_name, version)
+    }
+
+    pub fn get_latest_version(&self, crate_name: &str) -> Result<String> {
+        if self.offline {
+            return local_latest_version(crate_name).with_context(|| {
+                format!(
+                    "Offline mode: no local copy of '{}' in the Cargo registry",
+                    crate_name
+                )
+            });
+        }
+
+        let response = self.get_crate_metadata(crate_name)?;
         Ok(response
             .crate_info
             .max_stable_version
             .unwrap_or(response.crate_info.max_version))
     }
 
+    /// All non-yanked versions of a crate (offline: the versions in the local Cargo registry)
+    pub fn get_versions(&self, crate_name: &str) -> Result<Vec<Version>> {
+        if self.offline {
+            let versions = local_versions(crate_name);
+            if versions.is_empty() {
+                bail!("Offline mode: no local copy of '{}' in the Cargo registry", crate_name);
+            }
+            return Ok(versions);
+        }
+
+        let response = self.get_crate_metadata(crate_name)?;
+        Ok(response
+            .versions
+            .into_iter()
+            .filter(|v| !v.yanked)
+            .filter_map(|v| Version::parse(&v.num).ok())
+            .collect())
+    }
+
     pub fn fetch_crate(&self, crate_name: &str, version: &str) -> Result<PathBuf> {
         let dest_path = crate_path(crate_name, version);
 
+        // Another process may be extracting the same crate; wait for it to finish
+        let _lock = lock_crate_path(&format!("{}-{}", crate_name, version))?;
         if dest_path.exists() {
             println!("Crate {} v{} already downloaded", crate_name, version);
             return Ok(dest_path);
         }
 
+        if self.fetch_from_local_registry(crate_name, version, &dest_path)? {
+            return Ok(dest_path);
+        }
+
+        if self.offline {
+            bail!(
+                "Offline mode: {} v{} not found in the local Cargo registry",
+                crate_name,
+                version
+            );
+        }
+
         let url = format!(
             "https://static.crates.io/crates/{}/{}-{}.crate",
             crate_name, crate_name, version
@@ -64,29 +222,46 @@ impl Fetcher {
 
         println!("Downloading {} v{} from crates.io...", crate_name, version);
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
+        let bytes = self
+            .get_bytes(&url)
             .with_context(|| format!("Failed to download crate from {}", url))?;
 
-        if !response.status().is_success() {
-            bail!(
-                "Failed to download crate: HTTP {}",
-                response.status().as_u16()
-            );
-        }
-
-        let bytes = response
-            .bytes()
-            .with_context(|| "Failed to read response body")?;
-
         println!("Extracting to {:?}...", dest_path);
         self.extract_crate(&bytes, &dest_path, crate_name, version)?;
 
         Ok(dest_path)
     }
 
+    /// Copy or extract a crate already present in `~/.cargo/registry`.
+    /// Returns false if no local copy exists.
+    fn fetch_from_local_registry(
+        &self,
+        crate_name: &str,
+        version: &str,
+        dest_path: &PathBuf,
+    ) -> Result<bool> {
+        let dir_name = format!("{}-{}", crate_name, version);
+
+        if let Some(src) = find_in_registry("src", &dir_name) {
+            println!("Copying {} v{} from {:?}...", crate_name, version, src);
+            if let Err(e) = copy_dir(&src, dest_path) {
+                let _ = fs::remove_dir_all(dest_path);
+                return Err(e);
+            }
+            return Ok(true);
+        }
+
+        if let Some(cached) = find_in_registry("cache", &format!("{}.crate", dir_name)) {
+            println!("Extracting {} v{} from {:?}...", crate_name, version, cached);
+            let bytes = fs::read(&cached)
+                .with_context(|| format!("Failed to read {:?}", cached))?;
+            self.extract_crate(&bytes, dest_path, crate_name, version)?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
     fn extract_crate(
         &self,
         bytes: &[u8],
@@ -121,3 +296,250 @@ impl Fetcher {
         Ok(())
     }
 }
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// `Retry-After` in delta-seconds form (HTTP dates fall back to regular backoff)
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds: u64 = headers.get(RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds).min(MAX_RETRY_DELAY))
+}
+
+fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_RETRY_DELAY)
+}
+
+struct Semaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+struct Permit<'a>(&'a Semaphore);
+
+impl Semaphore {
+    fn new(permits: usize) -> Self {
+        Self {
+            available: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) -> Permit<'_> {
+        let mut available = self.available.lock().unwrap_or_else(|e| e.into_inner());
+        while *available == 0 {
+            available = self.released.wait(available).unwrap_or_else(|e| e.into_inner());
+        }
+        *available -= 1;
+        Permit(self)
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut available = self.0.available.lock().unwrap_or_else(|e| e.into_inner());
+        *available += 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// Root of the local Cargo installation (`$CARGO_HOME` or `~/.cargo`)
+fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|h| h.join(".cargo")))
+}
+
+/// Per-registry directories under `~/.cargo/registry/<kind>` (e.g. `index.crates.io-<hash>`)
+fn registry_dirs(kind: &str) -> Vec<PathBuf> {
+    let Some(root) = cargo_home().map(|h| h.join("registry").join(kind)) else {
+        return vec![];
+    };
+    fs::read_dir(root)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn find_in_registry(kind: &str, entry_name: &str) -> Option<PathBuf> {
+    registry_dirs(kind)
+        .into_iter()
+        .map(|dir| dir.join(entry_name))
+        .find(|p| p.exists())
+}
+
+/// Highest version of a crate present in the local Cargo registry (stable preferred)
+pub fn local_latest_version(crate_name: &str) -> Option<String> {
+    latest_version(local_versions(crate_name)).map(|v| v.to_string())
+}
+
+/// Names of the crates with a copy in the local Cargo registry
+pub fn local_crate_names() -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for kind in ["src", "cache"] {
+        for dir in registry_dirs(kind) {
+            let Ok(entries) = fs::read_dir(&dir) else { continue };
+            for entry in entries.filter_map(|e| e.ok()) {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let (name, _) = split_crate_key(file_name.strip_suffix(".crate").unwrap_or(&file_name));
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn local_versions(crate_name: &str) -> Vec<Version> {
+    let prefix = format!("{}-", crate_name);
+    let mut versions: Vec<Version> = Vec::new();
+
+    for kind in ["src", "cache"] {
+        for dir in registry_dirs(kind) {
+            let Ok(entries) = fs::read_dir(&dir) else { continue };
+            for entry in entries.filter_map(|e| e.ok()) {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let name = file_name.strip_suffix(".crate").unwrap_or(&file_name);
+                if let Some(version) = name.strip_prefix(&prefix).and_then(|v| Version::parse(v).ok()) {
+                    versions.push(version);
+                }
+            }
+        }
+    }
+
+    versions.sort();
+    versions.dedup();
+    versions
+}
+
+/// Highest version satisfying a Cargo version requirement, as Cargo would pick it
+pub fn matching_version(versions: &[Version], req: &VersionReq) -> Option<Version> {
+    versions.iter().filter(|v| req.matches(v)).max().cloned()
+}
+
+fn latest_version(versions: Vec<Version>) -> Option<Version> {
+    let max_stable = versions.iter().filter(|v| v.pre.is_empty()).max().cloned();
+    max_stable.or_else(|| versions.into_iter().max())
+}
+
+pub fn copy_dir(src: &Path, dest: &Path) -> Result<()> {
+    for entry in WalkDir::new(src) {
+        let entry = entry.with_context(|| format!("Failed to walk {:?}", src))?;
+        let relative = entry.path().strip_prefix(src)?;
+        let target = dest.join(relative);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target).with_context(|| format!("Failed to create {:?}", target))?;
+        } else if entry.file_type().is_file() {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("Failed to copy {:?}", entry.path()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latest_version_prefers_stable() {
+        let versions = ["1.0.0", "1.2.0", "2.0.0-beta.1"]
+            .iter()
+            .map(|v| Version::parse(v).unwrap())
+            .collect();
+        assert_eq!(latest_version(versions).unwrap().to_string(), "1.2.0");
+
+        let prereleases = vec![Version::parse("0.1.0-alpha").unwrap()];
+        assert_eq!(latest_version(prereleases).unwrap().to_string(), "0.1.0-alpha");
+    }
+
+    #[test]
+    fn test_matching_version() {
+        let versions: Vec<Version> = ["1.0.3", "1.4.0", "2.0.0", "2.1.0-rc.1"]
+            .iter()
+            .map(|v| Version::parse(v).unwrap())
+            .collect();
+        let pick = |req: &str| matching_version(&versions, &VersionReq::parse(req).unwrap()).map(|v| v.to_string());
+
+        assert_eq!(pick("1.0").as_deref(), Some("1.4.0"));
+        assert_eq!(pick("~1.0.1").as_deref(), Some("1.0.3"));
+        assert_eq!(pick("=1.0.3").as_deref(), Some("1.0.3"));
+        assert_eq!(pick(">=1, <3").as_deref(), Some("2.0.0"));
+        assert_eq!(pick("3"), None);
+    }
+
+    #[test]
+    fn test_copy_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("src");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::write(src.join("nested/lib.rs"), "pub fn f() {}\n").unwrap();
+        copy_dir(&src, &tmp.path().join("dest")).unwrap();
+        assert_eq!(fs::read_to_string(tmp.path().join("dest/nested/lib.rs")).unwrap(), "pub fn f() {}\n");
+
+        // A directory that can't be walked is an error, not an empty copy
+        assert!(copy_dir(&tmp.path().join("missing"), &tmp.path().join("dest2")).is_err());
+    }
+
+    #[test]
+    fn test_latest_version_cache_ttl() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("index.db");
+        let db = Database::open_at(&db_path).unwrap();
+        // Explicit settings, so neither config.toml nor CRATE_INDEXER_OFFLINE apply
+        let settings = config::Config { version_ttl_secs: 3600, ..Default::default() };
+        let mut fetcher = Fetcher::with_config(&settings).unwrap();
+
+        let lookups = std::cell::Cell::new(0);
+        let lookup = |version: &'static str| {
+            let lookups = &lookups;
+            move |_: &str| {
+                lookups.set(lookups.get() + 1);
+                Ok(version.to_string())
+            }
+        };
+
+        // A fresh entry is served from the cache
+        assert_eq!(fetcher.latest_version_with(&db, "demo", lookup("1.0.0")).unwrap(), "1.0.0");
+        assert_eq!(fetcher.latest_version_with(&db, "demo", lookup("1.1.0")).unwrap(), "1.0.0");
+        assert_eq!(lookups.get(), 1);
+
+        // A stale one is looked up again and replaced
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        conn.execute("UPDATE latest_versions SET checked_at = checked_at - 7200", []).unwrap();
+        assert_eq!(fetcher.latest_version_with(&db, "demo", lookup("1.1.0")).unwrap(), "1.1.0");
+        assert_eq!(lookups.get(), 2);
+        assert_eq!(fetcher.latest_version_with(&db, "demo", lookup("1.2.0")).unwrap(), "1.1.0");
+
+        // --refresh bypasses even a fresh entry
+        fetcher.refresh = true;
+        assert_eq!(fetcher.latest_version_with(&db, "demo", lookup("1.2.0")).unwrap(), "1.2.0");
+        assert_eq!(lookups.get(), 3);
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable_status(StatusCode::NOT_FOUND));
+
+        assert_eq!(backoff(0), INITIAL_BACKOFF);
+        assert_eq!(backoff(2), INITIAL_BACKOFF * 4);
+        assert_eq!(backoff(30), MAX_RETRY_DELAY);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(7)));
+        headers.insert(RETRY_AFTER, "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+}
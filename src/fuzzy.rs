@@ -0,0 +1,146 @@
+//! Fuzzy matching of crate and item names, for "did you mean" hints when a
+//! name finds nothing. Names are ranked by edit distance (with transpositions,
+//! so `spwan` is one edit from `spawn`) and, failing that, by whether the query's
+//! characters appear in order in the name (`hmap` in `HashMap`).
+
+use anyhow::Result;
+
+use crate::fetcher;
+use crate::storage::{split_crate_key, Database};
+
+/// Suggestions listed in a hint
+const MAX_SUGGESTIONS: usize = 5;
+
+/// How far a name is from the query, lower is closer; `None` if it is too far off
+/// to be what was meant. Case is ignored.
+fn distance(query: &str, name: &str) -> Option<usize> {
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    if query == name {
+        return Some(0);
+    }
+
+    let max_edits = match query.len() {
+        0..=2 => 0,
+        3..=5 => 1,
+        6..=8 => 2,
+        _ => 3,
+    };
+    let edits = edit_distance(&query, &name);
+    let by_edits = (edits <= max_edits).then_some(edits * 4);
+
+    // Unfinished names, then abbreviations: every query character in order. Each
+    // extra name character costs 1.
+    let by_prefix = (query.len() >= 3 && name.starts_with(&query)).then(|| 2 + name.len() - query.len());
+    let mut rest = name.iter();
+    let subsequence = query.len() >= 3 && query.iter().all(|c| rest.any(|n| n == c));
+    let by_subsequence = subsequence.then(|| 5 + name.len() - query.len());
+
+    [by_edits, by_prefix, by_subsequence].into_iter().flatten().min()
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions and
+/// swaps of adjacent characters
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The names closest to the query, best first; among equally close names, those
+/// nearer the query's length (`spawn` rather than `span` for `spwan`)
+pub fn suggest(query: &str, names: impl IntoIterator<Item = String>) -> Vec<String> {
+    let len = query.chars().count();
+    let mut ranked: Vec<(usize, usize, String)> = names
+        .into_iter()
+        .filter_map(|name| distance(query, &name).map(|d| (d, name.chars().count().abs_diff(len), name)))
+        .collect();
+    ranked.sort();
+    ranked.dedup_by(|a, b| a.2 == b.2);
+    ranked.into_iter().take(MAX_SUGGESTIONS).map(|(_, _, name)| name).collect()
+}
+
+/// ` Did you mean 'a', 'b' or 'c'?`, or nothing without suggestions
+pub fn did_you_mean(suggestions: &[String]) -> String {
+    let quoted: Vec<String> = suggestions.iter().map(|s| format!("'{}'", s)).collect();
+    match quoted.split_last() {
+        None => String::new(),
+        Some((last, [])) => format!(" Did you mean {}?", last),
+        Some((last, rest)) => format!(" Did you mean {} or {}?", rest.join(", "), last),
+    }
+}
+
+/// Hint for a crate name that is neither indexed nor fetchable, from the indexed
+/// crates, crates looked up on crates.io before and those in the local Cargo registry
+pub fn crate_hint(db: &Database, name: &str) -> String {
+    let (name, _) = split_crate_key(name);
+    let mut names: Vec<String> = db.list_crate_keys().unwrap_or_default()
+        .iter()
+        .map(|key| split_crate_key(key).0.to_string())
+        .collect();
+    names.extend(db.cached_crate_names().unwrap_or_default());
+    names.extend(fetcher::local_crate_names());
+    names.retain(|n| n != name);
+    did_you_mean(&suggest(name, names))
+}
+
+/// Hint for a name pattern that matched no items of a kind (any kind with `None`).
+/// Only plain names get one; regexes are left alone.
+pub fn item_hint(db: &Database, crate_keys: &[String], kind: Option<&str>, pattern: &str) -> Result<String> {
+    if pattern.is_empty() || !pattern.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Ok(String::new());
+    }
+    let mut names = Vec::new();
+    for crate_key in crate_keys {
+        let spans = db.get_item_spans(crate_key)?;
+        names.extend(spans.into_iter().filter(|s| kind.is_none_or(|k| s.kind == k)).map(|s| s.name));
+    }
+    Ok(did_you_mean(&suggest(pattern, names)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggest() {
+        let names = ["spawn", "spawn_blocking", "spawn_local", "sleep", "HashMap", "span"].map(String::from);
+        assert_eq!(suggest("spwan", names.clone()), ["spawn", "span"]);
+        assert_eq!(suggest("SPAWN_BLOKING", names.clone()), ["spawn_blocking"]);
+        assert_eq!(suggest("hmap", names.clone()), ["HashMap"]);
+        assert_eq!(suggest("spawn_", names.clone()), ["spawn", "spawn_local", "span", "spawn_blocking"]);
+        assert!(suggest("zz", names).is_empty());
+
+        assert_eq!(did_you_mean(&[]), "");
+        assert_eq!(did_you_mean(&["a".to_string()]), " Did you mean 'a'?");
+        assert_eq!(did_you_mean(&["a", "b", "c"].map(String::from)), " Did you mean 'a', 'b' or 'c'?");
+    }
+
+    #[test]
+    fn test_unknown_crate_name() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Database::open_at(&tmp.path().join("index.db")).unwrap();
+        for key in ["serde-1.0.100", "serde-1.0.200", "tokio-1.40.0"] {
+            db.add_crate(key, &tmp.path().join(key), &Default::default(), &[]).unwrap();
+        }
+
+        assert!(db.find_crate_key("sered").unwrap().is_none());
+        assert!(crate_hint(&db, "sered").contains("Did you mean 'serde'"));
+        assert!(crate_hint(&db, "tokoi-1.40.0").contains("'tokio'"));
+        let err = db.find_crate_key("serde").unwrap_err().to_string();
+        assert!(err.ends_with("Did you mean 'serde-1.0.100' or 'serde-1.0.200'?"), "{}", err);
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tar::Archive;
+use walkdir::WalkDir;
+
+use crate::config;
+use crate::fetcher::copy_dir;
+use crate::indexer;
+use crate::storage::{crates_dir, index_dir, lock_crate_path};
+
+/// Marker separating the package version from the commit in crate keys,
+/// e.g. `mycrate-0.3.1+git.1a2b3c4d5e6f`
+const GIT_KEY_MARKER: &str = "+git.";
+const SHORT_COMMIT_LEN: usize = 12;
+/// Extension of a checkout that is still being exported
+pub const PARTIAL_EXTENSION: &str = "partial";
+
+#[derive(Debug, Clone)]
+pub enum GitReference {
+    Rev(String),
+    Branch(String),
+    Tag(String),
+    DefaultBranch,
+}
+
+impl GitReference {
+    pub fn from_args(rev: Option<&str>, branch: Option<&str>, tag: Option<&str>) -> Self {
+        match (rev, branch, tag) {
+            (Some(r), _, _) => GitReference::Rev(r.to_string()),
+            (_, Some(b), _) => GitReference::Branch(b.to_string()),
+            (_, _, Some(t)) => GitReference::Tag(t.to_string()),
+            _ => GitReference::DefaultBranch,
+        }
+    }
+
+    /// The branch, tag or revision name given, if any
+    fn name(&self) -> Option<&str> {
+        match self {
+            GitReference::Rev(name) | GitReference::Branch(name) | GitReference::Tag(name) => Some(name),
+            GitReference::DefaultBranch => None,
+        }
+    }
+
+    fn rev_spec(&self) -> String {
+        match self {
+            GitReference::Rev(r) => r.clone(),
+            GitReference::Branch(b) => format!("refs/heads/{}", b),
+            GitReference::Tag(t) => format!("refs/tags/{}", t),
+            GitReference::DefaultBranch => "HEAD".to_string(),
+        }
+    }
+}
+
+pub struct GitCrate {
+    pub key: String,
+    pub path: PathBuf,
+    pub commit: String,
+}
+
+/// Directory holding one exported tree per checked out commit
+pub fn checkouts_dir() -> PathBuf {
+    index_dir().join("git").join("checkouts")
+}
+
+/// Lock key guarding the checkout of `commit`. Crate names can't contain dots,
+/// so it never collides with a crate key.
+pub fn checkout_lock_key(commit: &str) -> String {
+    format!("git.{}", commit)
+}
+
+/// Returns true for crate keys produced by `fetch_git_crate`
+pub fn is_git_key(key: &str) -> bool {
+    key.contains(GIT_KEY_MARKER)
+}
+
+/// Clone (or update) a git repository, locate `crate_name` in it and extract
+/// the crate sources into the crates directory.
+pub fn fetch_git_crate(url: &str, reference: &GitReference, crate_name: &str) -> Result<GitCrate> {
+    // Both end up on git's command line, where a leading dash would read as an option
+    if url.starts_with('-') {
+        bail!("Invalid repository URL {:?}", url);
+    }
+    if let Some(name) = reference.name().filter(|name| name.starts_with('-')) {
+        bail!("Invalid git reference {:?}", name);
+    }
+    let git_dir = index_dir().join("git");
+    let mirror = git_dir.join("db").join(url_dir_name(url));
+
+    sync_mirror(url, &mirror, config::get().offline)?;
+    let commit = resolve_commit(&mirror, reference)?;
+    let short_commit = &commit[..SHORT_COMMIT_LEN];
+
+    // Held until the crate is copied out, so `gc` leaves the checkout alone meanwhile
+    let _checkout_lock = lock_crate_path(&checkout_lock_key(&commit))?;
+    let checkout = checkouts_dir().join(&commit);
+    if !checkout.exists() {
+        println!("Checking out {} at {}...", url, short_commit);
+        // Export beside the final directory and move it into place once complete,
+        // so a crashed run never leaves a partial tree under the commit's name
+        let partial = checkout.with_extension(PARTIAL_EXTENSION);
+        let _ = fs::remove_dir_all(&partial);
+        if let Err(e) = export_tree(&mirror, &commit, &partial) {
+            let _ = fs::remove_dir_all(&partial);
+            return Err(e);
+        }
+        fs::rename(&partial, &checkout)
+            .with_context(|| format!("Failed to move checkout into {:?}", checkout))?;
+    }
+
+    let (member_dir, version) = find_workspace_member(&checkout, crate_name)?;
+    let key = format!("{}-{}{}{}", crate_name, version, GIT_KEY_MARKER, short_commit);
+    let path = crates_dir().join(&key);
+
+    let _lock = lock_crate_path(&key)?;
+    if path.exists() {
+        println!("Crate {} already extracted", key);
+    } else if let Err(e) = copy_dir(&member_dir, &path) {
+        let _ = fs::remove_dir_all(&path);
+        return Err(e);
+    }
+
+    Ok(GitCrate { key, path, commit })
+}
+
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .context("Failed to run git (is it installed?)")?;
+
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Keep a bare mirror of the repository so later fetches only transfer new objects
+fn sync_mirror(url: &str, mirror: &Path, offline: bool) -> Result<()> {
+    let mirror_str = mirror.to_string_lossy();
+
+    if mirror.exists() {
+        if offline {
+            return Ok(());
+        }
+        println!("Updating {}...", url);
+        git(&["--git-dir", &mirror_str, "fetch", "--prune", "--tags", "--", "origin",
+            "+refs/heads/*:refs/heads/*"])?;
+        return Ok(());
+    }
+
+    if offline {
+        bail!("Offline mode: repository {} has not been cloned yet", url);
+    }
+
+    if let Some(parent) = mirror.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    println!("Cloning {}...", url);
+    git(&["clone", "--bare", "--", url, &mirror_str])?;
+    Ok(())
+}
+
+fn resolve_commit(mirror: &Path, reference: &GitReference) -> Result<String> {
+    let mirror_str = mirror.to_string_lossy();
+    let spec = format!("{}^{{commit}}", reference.rev_spec());
+
+    git(&["--git-dir", &mirror_str, "rev-parse", "--verify", "--end-of-options", &spec])
+        .with_context(|| format!("Could not resolve {:?} in {:?}", reference, mirror))
+}
+
+fn export_tree(mirror: &Path, commit: &str, dest: &Path) -> Result<()> {
+    let output = Command::new("git")
+        .args(["--git-dir", &mirror.to_string_lossy(), "archive", "--format=tar", commit])
+        .output()
+        .context("Failed to run git archive")?;
+
+    if !output.status.success() {
+        bail!("git archive failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    fs::create_dir_all(dest)?;
+    Archive::new(Cursor::new(output.stdout))
+        .unpack(dest)
+        .with_context(|| format!("Failed to unpack {} into {:?}", commit, dest))?;
+    Ok(())
+}
+
+/// Find the package named `crate_name` anywhere in a checkout. Returns its
+/// directory and version (resolving `version.workspace = true`).
+fn find_workspace_member(root: &Path, crate_name: &str) -> Result<(PathBuf, String)> {
+    let workspace_version = read_manifest(&root.join("Cargo.toml"))
+        .and_then(|toml| {
+            toml.get("workspace")?
+                .get("package")?
+                .get("version")?
+                .as_str()
+                .map(String::from)
+        });
+
+    let mut found = Vec::new();
+    for entry in WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|e| !indexer::is_skipped_dir(e))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name() == "Cargo.toml")
+    {
+        let Some(toml) = read_manifest(entry.path()) else { continue };
+        let Some(package) = toml.get("package") else { continue };
+        if package.get("name").and_then(|n| n.as_str()) != Some(crate_name) {
+            continue;
+        }
+
+        let version = match package.get("version") {
+            Some(toml::Value::String(v)) => v.clone(),
+            Some(toml::Value::Table(t)) if t.get("workspace").and_then(|w| w.as_bool()) == Some(true) => {
+                workspace_version.clone().unwrap_or_else(|| "0.0.0".to_string())
+            }
+            _ => "0.0.0".to_string(),
+        };
+
+        if let Some(dir) = entry.path().parent() {
+            found.push((dir.to_path_buf(), version));
+        }
+    }
+
+    match found.len() {
+        0 => bail!("No package named '{}' found in the repository", crate_name),
+        1 => Ok(found.remove(0)),
+        _ => bail!(
+            "Multiple packages named '{}' found: {:?}",
+            crate_name,
+            found.iter().map(|(p, _)| p).collect::<Vec<_>>()
+        ),
+    }
+}
+
+fn read_manifest(path: &Path) -> Option<toml::Table> {
+    fs::read_to_string(path).ok()?.parse::<toml::Table>().ok()
+}
+
+/// Stable directory name for a repository URL: readable repo name plus a hash suffix
+fn url_dir_name(url: &str) -> String {
+    let trimmed = url.trim_end_matches('/').trim_end_matches(".git");
+    let name: String = trimmed
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or("repo")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+
+    let digest = Sha256::digest(trimmed.as_bytes());
+    format!("{}-{}", name, &hex::encode(digest)[..16])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    /// A bare repository with one commit, tagged v0.4.2, of a workspace holding
+    /// the `forked-dep` crate
+    fn bare_repo(tmp: &Path) -> PathBuf {
+        let work = tmp.join("work");
+        let member = work.join("crates").join("forked-dep");
+        fs::create_dir_all(member.join("src")).unwrap();
+        fs::write(
+            work.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n\n[workspace.package]\nversion = \"0.4.2\"\n",
+        )
+        .unwrap();
+        fs::write(
+            member.join("Cargo.toml"),
+            "[package]\nname = \"forked-dep\"\nversion.workspace = true\n",
+        )
+        .unwrap();
+        fs::write(member.join("src/lib.rs"), "pub fn patched() {}\n").unwrap();
+
+        run(tmp, &["init", "-q", "-b", "main", "work"]);
+        run(&work, &["add", "."]);
+        run(&work, &["commit", "-q", "-m", "initial"]);
+        run(&work, &["tag", "v0.4.2"]);
+        let bare = tmp.join("origin.git");
+        run(tmp, &["clone", "-q", "--bare", "work", &bare.to_string_lossy()]);
+        bare
+    }
+
+    #[test]
+    fn test_fetch_from_local_bare_repo() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bare = bare_repo(tmp.path());
+
+        let mirror = tmp.path().join("mirror");
+        sync_mirror(&bare.to_string_lossy(), &mirror, false).unwrap();
+        // A second sync fetches into the existing mirror
+        sync_mirror(&bare.to_string_lossy(), &mirror, false).unwrap();
+
+        let head = resolve_commit(&mirror, &GitReference::DefaultBranch).unwrap();
+        assert_eq!(head.len(), 40);
+        let branch = resolve_commit(&mirror, &GitReference::Branch("main".into())).unwrap();
+        let tag = resolve_commit(&mirror, &GitReference::Tag("v0.4.2".into())).unwrap();
+        let rev = resolve_commit(&mirror, &GitReference::Rev(head[..7].to_string())).unwrap();
+        assert_eq!(head, branch);
+        assert_eq!(head, tag);
+        assert_eq!(head, rev);
+        assert!(resolve_commit(&mirror, &GitReference::Branch("missing".into())).is_err());
+
+        let checkout = tmp.path().join("checkout");
+        export_tree(&mirror, &head, &checkout).unwrap();
+        let (dir, version) = find_workspace_member(&checkout, "forked-dep").unwrap();
+        assert_eq!(version, "0.4.2");
+        assert!(dir.join("src/lib.rs").exists());
+        assert!(find_workspace_member(&checkout, "other").is_err());
+    }
+
+    #[test]
+    fn test_fetch_git_crate() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bare = bare_repo(tmp.path());
+        let url = bare.to_string_lossy();
+        let head = git(&["--git-dir", &url, "rev-parse", "HEAD"]).unwrap();
+
+        let by_rev = fetch_git_crate(&url, &GitReference::Rev(head[..7].to_string()), "forked-dep").unwrap();
+        assert_eq!(by_rev.commit, head);
+        assert_eq!(by_rev.key, format!("forked-dep-0.4.2+git.{}", &head[..12]));
+        assert!(is_git_key(&by_rev.key));
+        assert_eq!(by_rev.path, crates_dir().join(&by_rev.key));
+
+        // The branch resolves to the same commit, so the extracted crate is reused
+        let by_branch = fetch_git_crate(&url, &GitReference::Branch("main".into()), "forked-dep").unwrap();
+        assert_eq!(by_branch.key, by_rev.key);
+        assert!(checkouts_dir().join(&head).is_dir());
+        assert!(!checkouts_dir().join(&head).with_extension(PARTIAL_EXTENSION).exists());
+
+        let result = indexer::index_crate(&by_branch.path, &by_branch.key).unwrap();
+        let names: Vec<&str> = result.items.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["patched"]);
+
+        // Leave the test index as it was
+        let mirror = index_dir().join("git").join("db").join(url_dir_name(&url));
+        for dir in [by_rev.path, checkouts_dir().join(&head), mirror] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reject_option_like_arguments() {
+        let Err(err) = fetch_git_crate("--upload-pack=touch /tmp/pwned", &GitReference::DefaultBranch, "x") else {
+            panic!("option-like URL accepted");
+        };
+        assert!(err.to_string().contains("Invalid repository URL"));
+        let Err(err) = fetch_git_crate("https://example.com/a.git", &GitReference::Rev("--output=/tmp/x".into()), "x") else {
+            panic!("option-like reference accepted");
+        };
+        assert!(err.to_string().contains("Invalid git reference"));
+    }
+
+    #[test]
+    fn test_git_keys() {
+        assert!(is_git_key("forked-dep-0.4.2+git.1a2b3c4d5e6f"));
+        assert!(!is_git_key("serde-1.0.200"));
+        assert_eq!(url_dir_name("https://github.com/a/b.git"), url_dir_name("https://github.com/a/b"));
+        assert!(url_dir_name("git@github.com:a/b.git").starts_with("b-"));
+    }
+}
@@ -1,6 +1,7 @@
 use anyhow::{Context, Result};
 use rayon::prelude::*;
-use std::collections::HashSet;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use syn::{
@@ -10,10 +11,41 @@ use syn::{
 };
 use walkdir::WalkDir;
 
+use crate::config;
 use crate::storage::{
-    ConstantInfo, CrateItems, EnumInfo, FieldInfo, FunctionInfo, ImplInfo, MacroInfo,
-    StructInfo, TraitInfo, TypeAliasInfo, VariantInfo,
+    self, ConstantInfo, CrateItems, Database, DependencyInfo, EnumInfo, FieldInfo, FunctionInfo, ImplInfo, ItemPath,
+    MacroInfo, PackageMetadata, SourceFile, StructInfo, TraitInfo, TypeAliasInfo, VariantInfo,
 };
+use crate::signature::TypeSignature;
+use crate::structural::{Bindings, Pattern, StructuralMatch};
+use crate::trigram;
+
+/// Version of the indexer's output. Bump it whenever indexing produces different
+/// rows for the same source (new item kinds, changed IDs or signatures, ...):
+/// crates indexed with an older version are re-indexed from their sources.
+///
+/// 2: items are added to the full-text search table
+/// 3: stable item IDs derived from qualified paths, which are stored for selectors
+/// 4: every file of the crate is recorded, with its contents
+/// 5: trigram index over the Rust files for regex search
+/// 6: normalized parameter and return types of functions
+/// 7: IDs of impls and the items in them include the impl's generics and trait
+/// 8: `target/` and version control directories are skipped, and only sources,
+///    manifests and READMEs have their contents stored
+/// 9: dev-dependencies, and the platform and rename of each dependency
+pub const INDEX_FORMAT_VERSION: i64 = 9;
+
+/// Hex digits kept from the SHA-256 item hash (64 bits)
+const ITEM_ID_LEN: usize = 16;
+
+/// Larger files are listed but their contents are left on disk
+const MAX_STORED_FILE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Version control directories, which aren't part of a crate's sources
+const VCS_DIRS: &[&str] = &[".git", ".hg", ".svn", ".jj"];
+
+/// Languages whose files have their contents stored: sources and manifests
+const STORED_LANGUAGES: &[&str] = &["rust", "toml", "c", "c++", "assembly"];
 
 pub struct IndexResult {
     pub items: CrateItems,
@@ -21,14 +53,36 @@ pub struct IndexResult {
 }
 
 pub fn index_crate(crate_path: &Path, crate_name: &str) -> Result<IndexResult> {
+    index_changed_files(crate_path, crate_name, &HashMap::new())
+}
+
+/// Index a crate that may already be in the database, parsing only the files whose
+/// contents changed since it was last indexed; `add_crate` keeps the stored items
+/// of the others. Crates indexed by an older format are parsed in full.
+pub fn index_crate_incremental(db: &Database, crate_path: &Path, key: &str) -> Result<IndexResult> {
+    let previous = if db.is_stale(key)? {
+        HashMap::new()
+    } else {
+        db.local_files(key)?.into_iter().map(|f| (f.path.clone(), f)).collect()
+    };
+    index_changed_files(crate_path, key, &previous)
+}
+
+fn index_changed_files(
+    crate_path: &Path,
+    crate_name: &str,
+    previous: &HashMap<String, SourceFile>,
+) -> Result<IndexResult> {
     // Parse Cargo.toml to get actual dependencies
     let dependencies = parse_cargo_dependencies(crate_path);
 
-    // Collect all .rs files first
+    // Collect all files first (sorted, so duplicate IDs are resolved the same way every time)
     let files: Vec<(PathBuf, String)> = WalkDir::new(crate_path)
+        .sort_by_file_name()
         .into_iter()
+        .filter_entry(|e| !is_skipped_dir(e))
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().is_some_and(|ext| ext == "rs"))
+        .filter(|e| e.file_type().is_file())
         .map(|entry| {
             let file_path = entry.path().to_path_buf();
             let relative_path = file_path
@@ -40,17 +94,82 @@ pub fn index_crate(crate_path: &Path, crate_name: &str) -> Result<IndexResult> {
         })
         .collect();
 
-    // Process files in parallel
+    // Process files in parallel, skipping those whose contents are unchanged
+    let store_contents = config::get().store_file_contents;
     let results: Vec<_> = files
         .par_iter()
         .filter_map(|(file_path, relative_path)| {
-            match index_file(file_path, relative_path, crate_name) {
-                Ok(result) => Some(result),
+            let language = file_language(relative_path);
+            // Files of unknown types (images, binaries, ...) are only listed
+            if language.is_none() {
+                let size = fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+                let file = SourceFile {
+                    path: relative_path.clone(),
+                    hash: String::new(),
+                    size,
+                    lines: 0,
+                    language: None,
+                    target: None,
+                    reexports: Vec::new(),
+                    contents: None,
+                    trigrams: Vec::new(),
+                    reused: false,
+                };
+                return Some((CrateItems::default(), file));
+            }
+
+            let bytes = match fs::read(file_path) {
+                Ok(bytes) => bytes,
                 Err(e) => {
-                    eprintln!("Warning: Failed to parse {:?}: {}", file_path, e);
-                    None
+                    eprintln!("Warning: Failed to read {:?}: {}", file_path, e);
+                    return None;
                 }
+            };
+            let hash = hex::encode(Sha256::digest(&bytes));
+            // The trigram index is rebuilt for every file, changed or not
+            let trigrams = if language == Some("rust") { trigram::trigrams(&bytes) } else { Vec::new() };
+            if let Some(known) = previous.get(relative_path).filter(|f| f.hash == hash) {
+                return Some((CrateItems::default(), SourceFile { reused: true, trigrams, ..known.clone() }));
+            }
+
+            let mut file = SourceFile {
+                path: relative_path.clone(),
+                hash,
+                size: bytes.len() as u64,
+                lines: 0,
+                language: language.map(String::from),
+                target: None,
+                reexports: Vec::new(),
+                contents: None,
+                trigrams,
+                reused: false,
+            };
+            let Ok(text) = String::from_utf8(bytes) else {
+                return Some((CrateItems::default(), file));
+            };
+            file.lines = text.lines().count();
+            if store_contents && keeps_contents(relative_path, language) && text.len() <= MAX_STORED_FILE_SIZE {
+                file.contents = storage::pack_contents(&text)
+                    .map_err(|e| eprintln!("Warning: {:#} ({:?})", e, file_path))
+                    .ok();
+            }
+            if language != Some("rust") {
+                return Some((CrateItems::default(), file));
             }
+
+            // A file that fails to parse is still recorded, so it isn't retried until it changes
+            file.target = target_kind(relative_path).map(String::from);
+            let file_items = match index_file(&text, relative_path, crate_name) {
+                Ok((file_items, reexports)) => {
+                    file.reexports = reexports;
+                    file_items
+                }
+                Err(e) => {
+                    eprintln!("Warning: Failed to parse {:?}: {}", file_path, e);
+                    CrateItems::default()
+                }
+            };
+            Some((file_items, file))
         })
         .collect();
 
@@ -58,7 +177,7 @@ pub fn index_crate(crate_path: &Path, crate_name: &str) -> Result<IndexResult> {
     let mut items = CrateItems::default();
     let mut reexported_modules = HashSet::new();
 
-    for (file_items, file_reexports) in results {
+    for (file_items, file) in results {
         items.functions.extend(file_items.functions);
         items.structs.extend(file_items.structs);
         items.enums.extend(file_items.enums);
@@ -67,9 +186,20 @@ pub fn index_crate(crate_path: &Path, crate_name: &str) -> Result<IndexResult> {
         items.type_aliases.extend(file_items.type_aliases);
         items.constants.extend(file_items.constants);
         items.impls.extend(file_items.impls);
-        reexported_modules.extend(file_reexports);
+        items.paths.extend(file_items.paths);
+        reexported_modules.extend(file.reexports.iter().cloned());
+        items.files.push(file);
+    }
+
+    // Different files can't produce the same ID unless the hash collides
+    let mut ids = HashSet::new();
+    if let Some(dup) = items.paths.iter().find(|p| !ids.insert(p.id.as_str())) {
+        anyhow::bail!("Item ID collision in {}: {} ({} {})", crate_name, dup.id, dup.kind, dup.path);
     }
 
+    items.dependencies = parse_manifest_dependencies(crate_path);
+    items.metadata = parse_package_metadata(crate_path);
+
     // Filter re-exports to only include actual dependencies
     let reexported_crates: Vec<String> = reexported_modules
         .into_iter()
@@ -101,23 +231,160 @@ fn parse_cargo_dependencies(crate_path: &Path) -> HashSet<String> {
     deps
 }
 
-fn index_file(
-    file_path: &Path,
-    relative_path: &str,
-    crate_name: &str,
-) -> Result<(CrateItems, Vec<String>)> {
-    let content = fs::read_to_string(file_path)
-        .with_context(|| format!("Failed to read file {:?}", file_path))?;
+/// Re-index a crate from its sources on disk if it was indexed by an older
+/// indexer format. Returns true if the crate was re-indexed.
+pub fn reindex_if_stale(db: &Database, key: &str) -> Result<bool> {
+    if !db.is_stale(key)? {
+        return Ok(false);
+    }
+    eprintln!("Re-indexing {} (index format changed)...", key);
+    reindex_crate(db, key)?;
+    Ok(true)
+}
 
-    let syntax: File = syn::parse_file(&content)
-        .with_context(|| format!("Failed to parse {:?}", file_path))?;
+/// Re-index all stale crates, or only the stale ones among `keys`. Crates that
+/// cannot be re-indexed are reported and keep their old rows.
+pub fn reindex_stale_crates(db: &Database, keys: Option<&[String]>) -> Result<()> {
+    let stale: Vec<String> = db
+        .stale_crate_keys()?
+        .into_iter()
+        .filter(|key| keys.is_none_or(|keys| keys.contains(key)))
+        .collect();
 
-    let mut visitor = ItemVisitor {
-        items: CrateItems::default(),
-        file_path: relative_path.to_string(),
-        crate_name: crate_name.to_string(),
+    if stale.len() > 1 {
+        eprintln!("Re-indexing {} crate(s) indexed by an older version...", stale.len());
+    }
+    for key in &stale {
+        if let Err(e) = reindex_if_stale(db, key) {
+            eprintln!("Warning: Could not re-index {}: {:#}", key, e);
+        }
+    }
+    Ok(())
+}
+
+/// Index a crate again from the sources it was indexed from, parsing only changed files
+pub fn reindex_crate(db: &Database, key: &str) -> Result<()> {
+    let path = db
+        .get_crate_path(key)?
+        .with_context(|| format!("Crate path not found for '{}'", key))?;
+    if !path.exists() {
+        anyhow::bail!("Sources of {} are missing at {:?}; fetch the crate again", key, path);
+    }
+
+    let result = index_crate_incremental(db, &path, key)?;
+    db.add_crate(key, &path, &result.items, &result.reexported_crates)
+}
+
+/// `[package]` metadata and `[features]` from Cargo.toml. Fields inherited from a
+/// workspace (`license.workspace = true`) are left empty.
+pub fn parse_package_metadata(crate_path: &Path) -> PackageMetadata {
+    let Some(toml) = fs::read_to_string(crate_path.join("Cargo.toml"))
+        .ok()
+        .and_then(|content| content.parse::<toml::Table>().ok())
+    else {
+        return PackageMetadata::default();
+    };
+    let Some(package) = toml.get("package").and_then(|p| p.as_table()) else {
+        return PackageMetadata::default();
+    };
+
+    let string = |key: &str| package.get(key).and_then(|v| v.as_str()).map(String::from);
+    let strings = |value: Option<&toml::Value>| -> Vec<String> {
+        value
+            .and_then(|v| v.as_array())
+            .map(|a| a.iter().filter_map(|v| v.as_str()).map(String::from).collect())
+            .unwrap_or_default()
+    };
+
+    let features = toml
+        .get("features")
+        .and_then(|f| f.as_table())
+        .map(|f| f.iter().map(|(name, enables)| (name.clone(), strings(Some(enables)))).collect())
+        .unwrap_or_default();
+
+    PackageMetadata {
+        description: string("description").map(|d| d.split_whitespace().collect::<Vec<_>>().join(" ")),
+        license: string("license"),
+        repository: string("repository"),
+        homepage: string("homepage"),
+        documentation: string("documentation"),
+        edition: string("edition"),
+        rust_version: string("rust-version"),
+        authors: strings(package.get("authors")),
+        keywords: strings(package.get("keywords")),
+        categories: strings(package.get("categories")),
+        features,
+    }
+}
+
+/// Registry dependencies declared in Cargo.toml (normal, build and dev, including
+/// target-specific tables). Path-only and git-only dependencies are skipped.
+pub fn parse_manifest_dependencies(crate_path: &Path) -> Vec<DependencyInfo> {
+    let Ok(content) = fs::read_to_string(crate_path.join("Cargo.toml")) else {
+        return vec![];
+    };
+    let Ok(toml) = content.parse::<toml::Table>() else {
+        return vec![];
     };
 
+    let mut tables = vec![(None, &toml)];
+    if let Some(targets) = toml.get("target").and_then(|t| t.as_table()) {
+        tables.extend(targets.iter().filter_map(|(target, t)| Some((Some(target), t.as_table()?))));
+    }
+
+    let mut deps = Vec::new();
+    for (target, table) in tables {
+        for (section, kind) in [
+            ("dependencies", "normal"),
+            ("build-dependencies", "build"),
+            ("build_dependencies", "build"),
+            ("dev-dependencies", "dev"),
+            ("dev_dependencies", "dev"),
+        ] {
+            let Some(entries) = table.get(section).and_then(|d| d.as_table()) else { continue };
+            for (key, spec) in entries {
+                if let Some(mut dep) = parse_dependency(key, spec, kind) {
+                    dep.target = target.cloned();
+                    deps.push(dep);
+                }
+            }
+        }
+    }
+
+    deps
+}
+
+fn parse_dependency(key: &str, spec: &toml::Value, kind: &str) -> Option<DependencyInfo> {
+    let (name, req, optional) = match spec {
+        toml::Value::String(req) => (key.to_string(), req.clone(), false),
+        toml::Value::Table(t) => {
+            let version = t.get("version").and_then(|v| v.as_str());
+            let is_registry = t.get("registry").is_none()
+                && (version.is_some() || (t.get("path").is_none() && t.get("git").is_none()));
+            if !is_registry {
+                return None;
+            }
+            let name = t.get("package").and_then(|p| p.as_str()).unwrap_or(key);
+            let optional = t.get("optional").and_then(|o| o.as_bool()).unwrap_or(false);
+            (name.to_string(), version.unwrap_or("*").to_string(), optional)
+        }
+        _ => return None,
+    };
+
+    Some(DependencyInfo {
+        alias: (name != key).then(|| key.to_string()),
+        name,
+        req,
+        kind: kind.to_string(),
+        optional,
+        target: None,
+    })
+}
+
+fn index_file(content: &str, relative_path: &str, crate_name: &str) -> Result<(CrateItems, Vec<String>)> {
+    let syntax: File = syn::parse_file(content)?;
+
+    let mut visitor = ItemVisitor::new(relative_path, crate_name, None);
     visitor.visit_file(&syntax);
 
     // Extract re-exported external crates
@@ -126,6 +393,19 @@ fn index_file(
     Ok((visitor.items, reexports))
 }
 
+/// Items of a file matching a structural pattern, with the IDs indexing gives them
+pub fn find_structural(content: &str, relative_path: &str, crate_name: &str, pattern: &Pattern) -> Result<Vec<StructuralMatch>> {
+    let syntax: File = syn::parse_file(content)?;
+    let mut visitor = ItemVisitor::new(relative_path, crate_name, Some(pattern));
+    visitor.visit_file(&syntax);
+
+    let lines: Vec<&str> = content.lines().collect();
+    for m in &mut visitor.matches {
+        m.text = lines.get(m.line.saturating_sub(1)).map(|l| l.trim().to_string()).unwrap_or_default();
+    }
+    Ok(visitor.matches)
+}
+
 fn extract_reexports(syntax: &File) -> Vec<String> {
     let mut crates = Vec::new();
 
@@ -167,26 +447,179 @@ fn extract_crate_from_use_tree(tree: &UseTree, crates: &mut Vec<String>) {
     }
 }
 
-struct ItemVisitor {
+/// Sources and manifests have their contents stored, and the README `readme` shows
+fn keeps_contents(relative_path: &str, language: Option<&str>) -> bool {
+    language.is_some_and(|l| STORED_LANGUAGES.contains(&l))
+        || (!relative_path.contains(['/', '\\']) && relative_path.to_ascii_uppercase().starts_with("README"))
+}
+
+/// Whether a walk of a crate should leave out this directory: version control
+/// data, and build output in `target/` at the top (`src/target/` may be a module)
+pub fn is_skipped_dir(entry: &walkdir::DirEntry) -> bool {
+    let Some(name) = entry.file_name().to_str() else {
+        return false;
+    };
+    entry.file_type().is_dir()
+        && entry.depth() > 0
+        && (VCS_DIRS.contains(&name) || (name == "target" && entry.depth() == 1))
+}
+
+/// Language of a file, from its extension (or name, for README and license files)
+fn file_language(relative_path: &str) -> Option<&'static str> {
+    let path = Path::new(relative_path);
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    let language = match extension.as_str() {
+        "rs" => "rust",
+        "toml" => "toml",
+        "md" | "markdown" => "markdown",
+        "json" => "json",
+        "yml" | "yaml" => "yaml",
+        "txt" => "text",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "c++",
+        "s" | "asm" => "assembly",
+        "py" => "python",
+        "sh" => "shell",
+        "proto" => "protobuf",
+        "js" => "javascript",
+        "html" => "html",
+        "css" => "css",
+        _ => {
+            let name = path.file_name()?.to_str()?.to_ascii_uppercase();
+            if ["README", "LICENSE", "LICENCE", "COPYING", "COPYRIGHT", "NOTICE"].iter().any(|n| name.starts_with(n)) {
+                "text"
+            } else {
+                return None;
+            }
+        }
+    };
+    Some(language)
+}
+
+/// Cargo target a Rust file belongs to under the conventional package layout
+fn target_kind(relative_path: &str) -> Option<&'static str> {
+    let path = relative_path.replace('\\', "/");
+    if path == "build.rs" {
+        Some("build")
+    } else if path == "src/main.rs" || path.starts_with("src/bin/") {
+        Some("bin")
+    } else if path.starts_with("src/") {
+        Some("lib")
+    } else if path.starts_with("examples/") {
+        Some("example")
+    } else if path.starts_with("tests/") {
+        Some("test")
+    } else if path.starts_with("benches/") {
+        Some("bench")
+    } else {
+        None
+    }
+}
+
+/// Module path implied by a source file's location, e.g. `src/de/mod.rs` -> `de`
+fn module_path_for_file(relative_path: &str) -> Vec<String> {
+    let path = relative_path.replace('\\', "/");
+    let path = path.strip_prefix("src/").unwrap_or(&path);
+    let mut segments: Vec<String> = path
+        .trim_end_matches(".rs")
+        .split('/')
+        .map(String::from)
+        .collect();
+    if segments.last().is_some_and(|s| s == "mod") || matches!(segments.as_slice(), [s] if s == "lib" || s == "main") {
+        segments.pop();
+    }
+    segments
+}
+
+/// Name a type is referred to by in item paths (`Vec<T>` -> `Vec`, `&Foo` -> `Foo`)
+fn type_name(ty: &syn::Type) -> String {
+    match ty {
+        syn::Type::Path(p) => p.path.segments.last().map(|s| s.ident.to_string()).unwrap_or_default(),
+        syn::Type::Reference(r) => type_name(&r.elem),
+        _ => quote::quote!(#ty).to_string().replace(' ', ""),
+    }
+}
+
+/// `impl<T> Trait for Type<T>`, as written apart from the where clause
+fn impl_header(item: &ItemImpl) -> String {
+    let generics = &item.generics;
+    let self_ty = &item.self_ty;
+    let trait_path = item.trait_.as_ref().map(|(_, path, _)| format!("{} for ", quote::quote!(#path))).unwrap_or_default();
+    format!("impl{} {}{}", quote::quote!(#generics), trait_path, quote::quote!(#self_ty))
+}
+
+struct ItemVisitor<'p> {
     items: CrateItems,
     file_path: String,
     crate_name: String,
+    /// Modules, types and traits enclosing the current item
+    scope: Vec<String>,
+    /// Header of the impl enclosing the current item, which tells apart items of the
+    /// same name in different impls of a type (`fmt` in `Display` and `Debug`)
+    impl_header: Option<String>,
+    /// Occurrences of each (kind, path) so far, to tell apart items sharing a path
+    seen: HashMap<(String, String), usize>,
+    /// Structural search pattern, and the items matching it
+    pattern: Option<&'p Pattern>,
+    matches: Vec<StructuralMatch>,
 }
 
-impl ItemVisitor {
-    fn generate_id(&self, name: &str, line: usize, kind: &str) -> String {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
+impl<'p> ItemVisitor<'p> {
+    fn new(relative_path: &str, crate_name: &str, pattern: Option<&'p Pattern>) -> Self {
+        ItemVisitor {
+            items: CrateItems::default(),
+            file_path: relative_path.to_string(),
+            crate_name: crate_name.to_string(),
+            scope: module_path_for_file(relative_path),
+            impl_header: None,
+            seen: HashMap::new(),
+            pattern,
+            matches: Vec::new(),
+        }
+    }
+
+    /// Record the item if the pattern matched it
+    fn record_match(&mut self, bindings: Option<Bindings>, id: &str, kind: &str, line: usize, end_line: Option<usize>) {
+        if let Some(bindings) = bindings {
+            self.matches.push(StructuralMatch {
+                id: id.to_string(),
+                kind: kind.to_string(),
+                file: self.file_path.clone(),
+                line,
+                end_line,
+                text: String::new(),
+                bindings,
+            });
+        }
+    }
 
-        let mut hasher = DefaultHasher::new();
-        self.crate_name.hash(&mut hasher);
-        self.file_path.hash(&mut hasher);
-        name.hash(&mut hasher);
-        line.hash(&mut hasher);
-        kind.hash(&mut hasher);
+    /// Derive an item's ID from its crate, file, kind and qualified path, which
+    /// keeps it stable across re-indexing and unaffected by edits elsewhere in
+    /// the file. `salt` distinguishes items with the same path (e.g. trait impls),
+    /// as does the enclosing impl's header.
+    fn generate_id(&mut self, name: &str, kind: &str, salt: &str) -> String {
+        let path = self.scope.iter().map(String::as_str).chain([name]).collect::<Vec<_>>().join("::");
+        let salt = match &self.impl_header {
+            Some(header) => format!("{} in {}", salt, header),
+            None => salt.to_string(),
+        };
+        let seen = self.seen.entry((kind.to_string(), format!("{}{}", path, salt))).or_insert(0);
+        let occurrence = *seen;
+        *seen += 1;
+
+        let mut hasher = Sha256::new();
+        for part in [self.crate_name.as_str(), &self.file_path, kind, &path, &salt, &occurrence.to_string()] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        let id = hex::encode(hasher.finalize())[..ITEM_ID_LEN].to_string();
 
-        // Use full 64-bit hash for lower collision probability
-        format!("{:016x}", hasher.finish())[..8].to_string()
+        self.items.paths.push(ItemPath {
+            id: id.clone(),
+            kind: kind.to_string(),
+            path,
+        });
+        id
     }
 
     fn visibility_str(vis: &Visibility) -> String {
@@ -201,7 +634,9 @@ impl ItemVisitor {
         let signature = format_signature(sig);
         let docs = extract_docs(attrs);
         let name = sig.ident.to_string();
-        let id = self.generate_id(&name, start_line, "fn");
+        let id = self.generate_id(&name, "function", "");
+        let matched = self.pattern.and_then(|p| p.match_fn(sig));
+        self.record_match(matched, &id, "function", start_line, end_line);
 
         self.items.functions.push(FunctionInfo {
             id,
@@ -211,6 +646,7 @@ impl ItemVisitor {
             end_line,
             signature,
             docs,
+            type_signature: Some(TypeSignature::from_sig(sig)),
         });
     }
 
@@ -254,8 +690,11 @@ impl ItemVisitor {
         };
 
         let name = item.ident.to_string();
+        let id = self.generate_id(&name, "struct", "");
+        let matched = self.pattern.and_then(|p| p.match_struct(item));
+        self.record_match(matched, &id, "struct", start_line, end_line);
         self.items.structs.push(StructInfo {
-            id: self.generate_id(&name, start_line, "struct"),
+            id,
             name,
             file: self.file_path.clone(),
             line: start_line,
@@ -310,8 +749,11 @@ impl ItemVisitor {
             .collect();
 
         let name = item.ident.to_string();
+        let id = self.generate_id(&name, "enum", "");
+        let matched = self.pattern.and_then(|p| p.match_enum(item));
+        self.record_match(matched, &id, "enum", start_line, end_line);
         self.items.enums.push(EnumInfo {
-            id: self.generate_id(&name, start_line, "enum"),
+            id,
             name,
             file: self.file_path.clone(),
             line: start_line,
@@ -327,8 +769,11 @@ impl ItemVisitor {
         let end_line = Some(item.brace_token.span.close().end().line);
 
         let name = item.ident.to_string();
+        let id = self.generate_id(&name, "trait", "");
+        let matched = self.pattern.and_then(|p| p.match_trait(item));
+        self.record_match(matched, &id, "trait", start_line, end_line);
         self.items.traits.push(TraitInfo {
-            id: self.generate_id(&name, start_line, "trait"),
+            id,
             name,
             file: self.file_path.clone(),
             line: start_line,
@@ -345,8 +790,9 @@ impl ItemVisitor {
                 .unwrap_or(1);
             let name = ident.to_string();
 
+            let id = self.generate_id(&name, "macro", "");
             self.items.macros.push(MacroInfo {
-                id: self.generate_id(&name, start_line, "macro"),
+                id,
                 name,
                 file: self.file_path.clone(),
                 line: start_line,
@@ -362,8 +808,11 @@ impl ItemVisitor {
         let ty = &item.ty;
         let name = item.ident.to_string();
 
+        let id = self.generate_id(&name, "type_alias", "");
+        let matched = self.pattern.and_then(|p| p.match_type_alias(item));
+        self.record_match(matched, &id, "type_alias", start_line, None);
         self.items.type_aliases.push(TypeAliasInfo {
-            id: self.generate_id(&name, start_line, "type"),
+            id,
             name,
             file: self.file_path.clone(),
             line: start_line,
@@ -378,8 +827,11 @@ impl ItemVisitor {
         let ty = &item.ty;
         let name = item.ident.to_string();
 
+        let id = self.generate_id(&name, "constant", "");
+        let matched = self.pattern.and_then(|p| p.match_const(false, &item.ident, ty));
+        self.record_match(matched, &id, "constant", start_line, None);
         self.items.constants.push(ConstantInfo {
-            id: self.generate_id(&name, start_line, "const"),
+            id,
             name,
             file: self.file_path.clone(),
             line: start_line,
@@ -395,8 +847,11 @@ impl ItemVisitor {
         let ty = &item.ty;
         let name = item.ident.to_string();
 
+        let id = self.generate_id(&name, "constant", "");
+        let matched = self.pattern.and_then(|p| p.match_const(true, &item.ident, ty));
+        self.record_match(matched, &id, "constant", start_line, None);
         self.items.constants.push(ConstantInfo {
-            id: self.generate_id(&name, start_line, "static"),
+            id,
             name,
             file: self.file_path.clone(),
             line: start_line,
@@ -417,14 +872,13 @@ impl ItemVisitor {
             quote::quote!(#path).to_string()
         });
 
-        // Use self_type + trait_name for ID uniqueness
-        let id_name = match &trait_name {
-            Some(t) => format!("{}_{}", self_type, t),
-            None => self_type.clone(),
-        };
+        // Impls are found by the type's path; the trait and generics tell impls of one type apart
+        let id = self.generate_id(&type_name(self_ty), "impl", &impl_header(item));
+        let matched = self.pattern.and_then(|p| p.match_impl(item));
+        self.record_match(matched, &id, "impl", start_line, end_line);
 
         self.items.impls.push(ImplInfo {
-            id: self.generate_id(&id_name, start_line, "impl"),
+            id,
             file: self.file_path.clone(),
             line: start_line,
             end_line,
@@ -434,7 +888,7 @@ impl ItemVisitor {
     }
 }
 
-impl<'ast> Visit<'ast> for ItemVisitor {
+impl<'ast> Visit<'ast> for ItemVisitor<'_> {
     fn visit_item(&mut self, item: &'ast Item) {
         match item {
             Item::Fn(func) => {
@@ -452,7 +906,27 @@ impl<'ast> Visit<'ast> for ItemVisitor {
             Item::Impl(i) => self.add_impl(i),
             _ => {}
         }
-        syn::visit::visit_item(self, item);
+
+        // Items inside modules, impls and traits are named relative to them
+        let scope = match item {
+            Item::Mod(m) => Some(m.ident.to_string()),
+            Item::Impl(i) => Some(type_name(&i.self_ty)),
+            Item::Trait(t) => Some(t.ident.to_string()),
+            _ => None,
+        };
+        match scope {
+            Some(name) => {
+                self.scope.push(name);
+                let outer_impl = match item {
+                    Item::Impl(i) => self.impl_header.replace(impl_header(i)),
+                    _ => self.impl_header.take(),
+                };
+                syn::visit::visit_item(self, item);
+                self.impl_header = outer_impl;
+                self.scope.pop();
+            }
+            None => syn::visit::visit_item(self, item),
+        }
     }
 
     fn visit_impl_item(&mut self, item: &'ast ImplItem) {
@@ -548,3 +1022,64 @@ fn format_signature(sig: &Signature) -> String {
         output
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_source(lib: &str) -> CrateItems {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("src/de")).unwrap();
+        fs::write(tmp.path().join("src/lib.rs"), lib).unwrap();
+        fs::write(
+            tmp.path().join("src/de/mod.rs"),
+            "pub struct Deserializer;\nimpl Deserializer {\n    pub fn new() -> Self { Deserializer }\n}\n\
+             impl Default for Deserializer {\n    fn default() -> Self { Deserializer }\n}\n",
+        )
+        .unwrap();
+        index_crate(tmp.path(), "demo-0.1.0").unwrap().items
+    }
+
+    #[test]
+    fn test_stable_item_ids() {
+        let items = index_source("pub mod de;\nmod inner {\n    pub fn helper() {}\n}\n");
+        let mut paths: Vec<(&str, &str)> = items.paths.iter().map(|p| (p.kind.as_str(), p.path.as_str())).collect();
+        paths.sort();
+        assert_eq!(paths, [
+            ("function", "de::Deserializer::default"),
+            ("function", "de::Deserializer::new"),
+            ("function", "inner::helper"),
+            ("impl", "de::Deserializer"),
+            ("impl", "de::Deserializer"),
+            ("struct", "de::Deserializer"),
+        ]);
+
+        // Moving an item within its file doesn't change its ID
+        let shifted = index_source("\n\n// moved down\npub mod de;\nmod inner {\n    pub fn helper() {}\n}\n");
+        let id = |items: &CrateItems, name: &str| items.functions.iter().find(|f| f.name == name).unwrap().id.clone();
+        assert_eq!(id(&items, "helper"), id(&shifted, "helper"));
+        assert_eq!(id(&items, "helper").len(), ITEM_ID_LEN);
+    }
+
+    #[test]
+    fn test_ids_in_trait_impls() {
+        let display = "impl std::fmt::Display for Foo {\n    fn fmt(&self, f: &mut Formatter) -> Result { todo!() }\n}\n";
+        let debug = "impl std::fmt::Debug for Foo {\n    fn fmt(&self, f: &mut Formatter) -> Result { todo!() }\n}\n";
+        let ids = |source: String| {
+            let items = index_source(&source);
+            let mut ids: Vec<(String, String)> = items.functions.iter()
+                .filter(|f| f.name == "fmt")
+                .map(|f| (source.lines().nth(f.line - 2).unwrap().to_string(), f.id.clone()))
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        // Swapping the impls keeps each method's ID
+        let before = ids(format!("pub struct Foo;\n{}{}", display, debug));
+        let after = ids(format!("pub struct Foo;\n{}{}", debug, display));
+        assert_eq!(before.len(), 2);
+        assert_ne!(before[0].1, before[1].1);
+        assert_eq!(before, after);
+    }
+}
@@ -0,0 +1,250 @@
+//! A light tokenizer pass over Rust source that tells code apart from comments,
+//! doc comments and string literals, so text searches can be limited to one of them.
+//! It only finds the boundaries of comments and literals; everything else is code.
+
+use std::str::FromStr;
+
+/// The kind of text a byte of Rust source belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Code,
+    /// `//` and `/* */` comments that aren't docs
+    Comment,
+    /// `///`, `//!`, `/** */` and `/*! */` comments
+    Doc,
+    /// String, byte string, C string and char literals, raw or not
+    String,
+}
+
+impl Class {
+    pub const NAMES: [&'static str; 4] = ["code", "comments", "strings", "docs"];
+}
+
+impl FromStr for Class {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Class, String> {
+        match s {
+            "code" => Ok(Class::Code),
+            "comments" => Ok(Class::Comment),
+            "strings" => Ok(Class::String),
+            "docs" => Ok(Class::Doc),
+            _ => Err(format!("Unknown kind of text '{}' (expected one of: {})", s, Class::NAMES.join(", "))),
+        }
+    }
+}
+
+/// The comments and literals of a file as sorted, non-overlapping byte ranges
+pub struct Classes {
+    spans: Vec<(usize, usize, Class)>,
+}
+
+impl Classes {
+    pub fn of(source: &str) -> Classes {
+        Classes { spans: Lexer { bytes: source.as_bytes(), pos: 0, spans: Vec::new() }.run() }
+    }
+
+    pub fn at(&self, offset: usize) -> Class {
+        let i = self.spans.partition_point(|&(start, _, _)| start <= offset);
+        match i.checked_sub(1).map(|i| self.spans[i]) {
+            Some((_, end, class)) if offset < end => class,
+            _ => Class::Code,
+        }
+    }
+}
+
+struct Lexer<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    spans: Vec<(usize, usize, Class)>,
+}
+
+impl Lexer<'_> {
+    fn run(mut self) -> Vec<(usize, usize, Class)> {
+        while let Some(&b) = self.bytes.get(self.pos) {
+            let start = self.pos;
+            match b {
+                b'/' if self.peek(1) == Some(b'/') => {
+                    let doc = matches!((self.peek(2), self.peek(3)), (Some(b'/'), next) if next != Some(b'/'))
+                        || self.peek(2) == Some(b'!');
+                    self.pos = self.find(b'\n').unwrap_or(self.bytes.len());
+                    self.push(start, if doc { Class::Doc } else { Class::Comment });
+                }
+                b'/' if self.peek(1) == Some(b'*') => {
+                    let doc = matches!((self.peek(2), self.peek(3)), (Some(b'*'), Some(next)) if next != b'*' && next != b'/')
+                        || self.peek(2) == Some(b'!');
+                    self.block_comment();
+                    self.push(start, if doc { Class::Doc } else { Class::Comment });
+                }
+                b'"' => {
+                    self.string();
+                    self.push(start, Class::String);
+                }
+                b'\'' => {
+                    if self.char_literal() {
+                        self.push(start, Class::String);
+                    }
+                }
+                b if is_word(b) => self.word(),
+                _ => self.pos += 1,
+            }
+        }
+        self.spans
+    }
+
+    fn peek(&self, ahead: usize) -> Option<u8> {
+        self.bytes.get(self.pos + ahead).copied()
+    }
+
+    fn find(&self, byte: u8) -> Option<usize> {
+        self.bytes[self.pos..].iter().position(|&b| b == byte).map(|i| self.pos + i)
+    }
+
+    fn push(&mut self, start: usize, class: Class) {
+        self.spans.push((start, self.pos, class));
+    }
+
+    /// Block comments nest; an unterminated one runs to the end of the file
+    fn block_comment(&mut self) {
+        let mut depth = 0;
+        while self.pos < self.bytes.len() {
+            match (self.bytes[self.pos], self.peek(1)) {
+                (b'/', Some(b'*')) => {
+                    depth += 1;
+                    self.pos += 2;
+                }
+                (b'*', Some(b'/')) => {
+                    depth -= 1;
+                    self.pos += 2;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    /// A quoted string starting at `pos`, with escapes
+    fn string(&mut self) {
+        self.pos += 1;
+        while let Some(&b) = self.bytes.get(self.pos) {
+            self.pos += if b == b'\\' { 2 } else { 1 };
+            if b == b'"' {
+                break;
+            }
+        }
+        self.pos = self.pos.min(self.bytes.len());
+    }
+
+    /// `r"..."` or `r#"..."#` with `pos` after the `r`; false for raw identifiers like `r#type`
+    fn raw_string(&mut self) -> bool {
+        let hashes = self.bytes[self.pos..].iter().take_while(|&&b| b == b'#').count();
+        if self.peek(hashes) != Some(b'"') {
+            return false;
+        }
+        self.pos += hashes + 1;
+        let closing: Vec<u8> = std::iter::once(b'"').chain(std::iter::repeat_n(b'#', hashes)).collect();
+        self.pos = self.bytes[self.pos..]
+            .windows(closing.len())
+            .position(|w| w == closing)
+            .map_or(self.bytes.len(), |i| self.pos + i + closing.len());
+        true
+    }
+
+    /// A char literal starting at `pos`, or a lifetime or label, which are code
+    fn char_literal(&mut self) -> bool {
+        let len = match self.peek(1) {
+            Some(b'\\') => {
+                // The escaped character itself may be a quote
+                let rest = self.bytes.get(self.pos + 3..).unwrap_or_default();
+                match rest.iter().position(|&b| b == b'\'' || b == b'\n') {
+                    Some(i) if rest[i] == b'\'' => i + 4,
+                    _ => 0,
+                }
+            }
+            Some(b) => {
+                let width = utf8_width(b);
+                if self.peek(1 + width) == Some(b'\'') { width + 2 } else { 0 }
+            }
+            None => 0,
+        };
+        self.pos += len.max(1);
+        len > 0
+    }
+
+    /// An identifier, keyword or number, or the prefix of a literal (`b"`, `r#"`, `c"`, `b'`)
+    fn word(&mut self) {
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(|&b| is_word(b)) {
+            self.pos += 1;
+        }
+        let literal = match (&self.bytes[start..self.pos], self.peek(0)) {
+            (b"b" | b"c", Some(b'"')) => {
+                self.string();
+                true
+            }
+            (b"r" | b"br" | b"cr", Some(b'"' | b'#')) => self.raw_string(),
+            (b"b", Some(b'\'')) => self.char_literal(),
+            _ => false,
+        };
+        if literal {
+            self.push(start, Class::String);
+        }
+    }
+}
+
+fn is_word(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
+
+fn utf8_width(first: u8) -> usize {
+    match first {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(source: &str) -> Vec<(&str, Class)> {
+        Classes::of(source).spans.iter().map(|&(start, end, class)| (&source[start..end], class)).collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        let source = r####"//! Crate docs
+/// Item docs with "quotes"
+//// not docs
+fn f<'a>(x: &'a str) -> char { /* a /* nested */ comment */
+    let r#type = r#"raw "string""#; // trailing
+    let (c, e, b) = ('"', '\'', b'x');
+    unsafe { g("unsafe", br"\", c"ok", 'é') } /**/ /** block docs */
+}"####;
+        assert_eq!(spans(source), [
+            ("//! Crate docs", Class::Doc),
+            ("/// Item docs with \"quotes\"", Class::Doc),
+            ("//// not docs", Class::Comment),
+            ("/* a /* nested */ comment */", Class::Comment),
+            ("r#\"raw \"string\"\"#", Class::String),
+            ("// trailing", Class::Comment),
+            ("'\"'", Class::String),
+            ("'\\''", Class::String),
+            ("b'x'", Class::String),
+            ("\"unsafe\"", Class::String),
+            ("br\"\\\"", Class::String),
+            ("c\"ok\"", Class::String),
+            ("'é'", Class::String),
+            ("/**/", Class::Comment),
+            ("/** block docs */", Class::Doc),
+        ]);
+
+        let classes = Classes::of(source);
+        let unsafe_at: Vec<Class> = source.match_indices("unsafe").map(|(i, _)| classes.at(i)).collect();
+        assert_eq!(unsafe_at, [Class::Code, Class::String]);
+    }
+}
@@ -1,107 +1,231 @@
+mod bundle;
+mod config;
 mod embeddings;
+mod fetch;
 mod fetcher;
+mod fuzzy;
+mod git;
 mod indexer;
+mod lexer;
 mod mcp;
 mod search;
+mod signature;
 mod storage;
+mod structural;
+mod trigram;
+mod vendor;
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
 use rayon::prelude::*;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::embeddings::{embedding_to_bytes, EmbeddingManager};
+use crate::fetch::{
+    fetch_crates, fetch_single_crate, indexed_dependencies, indexed_matching_version, indexed_metadata,
+    FetchFailure,
+};
 use crate::fetcher::Fetcher;
+use crate::git::GitReference;
 use crate::indexer::index_crate;
-use crate::search::{build_regex, search_functions, search_regex};
+use crate::lexer::Class;
+use crate::search::{build_regex, search_functions, search_regex, search_signatures, ItemFilter, SearchOptions};
+use crate::signature::TypeQuery;
+use crate::structural::Pattern;
 use crate::storage::{
     ConstantInfo, Database, EnumInfo, ImplInfo, MacroInfo, StructInfo, TraitInfo, TypeAliasInfo,
 };
 
+/// How long `gc` leaves a freshly fetched, not yet indexed crate directory alone
+const GC_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
 #[derive(Parser)]
 #[command(name = "crate-indexer")]
 #[command(about = "Index and search Rust crates from crates.io")]
 struct Cli {
+    /// Never access the network; use the index and the local Cargo registry only
+    #[arg(long, global = true)]
+    offline: bool,
+    /// Ignore cached "latest" versions and check crates.io again
+    #[arg(long, global = true)]
+    refresh: bool,
+    /// Directory holding the index database and downloaded crates
+    #[arg(long, global = true, value_name = "DIR")]
+    index_dir: Option<std::path::PathBuf>,
+    /// Use a named index profile from the config file
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Fetch and index a crate from crates.io
+    /// Fetch and index a crate from crates.io or a git repository
     Fetch {
         /// Name of the crate to fetch
         crate_name: String,
         /// Specific version to fetch (defaults to latest)
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with = "git")]
         version: Option<String>,
+        /// Git repository to fetch the crate from instead of crates.io
+        #[arg(long)]
+        git: Option<String>,
+        /// Commit to check out (git only)
+        #[arg(long, requires = "git", conflicts_with_all = ["branch", "tag"])]
+        rev: Option<String>,
+        /// Branch to check out (git only)
+        #[arg(long, requires = "git", conflicts_with = "tag")]
+        branch: Option<String>,
+        /// Tag to check out (git only)
+        #[arg(long, requires = "git")]
+        tag: Option<String>,
+        /// Also index normal dependencies, this many levels deep
+        #[arg(long, value_name = "DEPTH", conflicts_with = "git")]
+        deps: Option<usize>,
+    },
+    /// Show package metadata from a crate's Cargo.toml (license, MSRV, features, ...)
+    Info {
+        /// Name of the crate (e.g., "anyhow" or "anyhow-1.0.100")
+        crate_name: String,
+    },
+    /// List the dependencies declared by a crate and which versions are indexed
+    Deps {
+        /// Name of the crate (e.g., "anyhow" or "anyhow-1.0.100")
+        crate_name: String,
+    },
+    /// List indexed crates that depend on a crate
+    Rdeps {
+        /// Name of the crate; with a version (e.g., "syn-2.0.114"), only dependents
+        /// whose requirement matches that version are listed
+        crate_name: String,
+    },
+    /// Index every crate in a `cargo vendor` directory (no network access)
+    ImportVendor {
+        /// Path to the vendor directory
+        dir: std::path::PathBuf,
     },
     /// Search a crate with a regex pattern
     Search {
-        /// Name of the crate to search (e.g., "anyhow" or "anyhow-1.0.100")
-        crate_name: String,
-        /// Regex pattern to search for
-        pattern: String,
+        /// Name of the crate to search, e.g. "anyhow" or "anyhow-1.0.100" (omit with --all or --crates)
+        crate_name: Option<String>,
+        /// Regex pattern to search for (a literal string with --fixed-strings)
+        pattern: Option<String>,
+        #[command(flatten)]
+        scope: CrateScope,
+        #[command(flatten)]
+        flags: SearchFlags,
+    },
+    /// Search a crate for items matching a structural pattern
+    #[command(after_help = "Patterns: fn NAME(PARAMS) -> TYPE, impl TRAIT for TYPE, impl TYPE, struct NAME { FIELDS }, \
+        enum NAME { VARIANTS }, trait NAME, type NAME = TYPE, const NAME: TYPE, static NAME: TYPE. \
+        `$x` matches one identifier, type or expression (the same one wherever `$x` appears), `$_` any one, \
+        `..` any tokens; left-out parts match anything.\n\n\
+        Examples:\n  crate-indexer ssearch tokio 'fn $name(..) -> Result<$_, io::Error>'\n  \
+        crate-indexer ssearch bytes 'impl From<$t> for $_'")]
+    Ssearch {
+        /// Name of the crate to search, e.g. "anyhow" or "anyhow-1.0.100" (omit with --all or --crates)
+        crate_name: Option<String>,
+        /// Item pattern, e.g. 'async fn $_(&self, ..)'
+        pattern: Option<String>,
+        #[command(flatten)]
+        scope: CrateScope,
+    },
+    /// Full-text search item names, signatures, fields and docs across indexed crates
+    Find {
+        /// Words to search for; `word*` matches a prefix, "quoted words" a phrase
+        query: String,
+        /// Only search these crates (comma-separated, e.g. "serde,tokio")
+        #[arg(long, value_delimiter = ',')]
+        crates: Vec<String>,
+        /// Only return one kind of item (function, struct, enum, trait, macro, type_alias, constant, impl)
+        #[arg(short, long)]
+        kind: Option<String>,
+        /// Maximum results
+        #[arg(short, long, default_value = "20")]
+        limit: usize,
     },
     /// List or search function definitions in a crate
     Functions {
-        /// Name of the crate to search (e.g., "anyhow" or "anyhow-1.0.100")
-        crate_name: String,
+        /// Name of the crate to search, e.g. "anyhow" or "anyhow-1.0.100" (omit with --all or --crates)
+        crate_name: Option<String>,
         /// Optional regex pattern to filter functions
         pattern: Option<String>,
+        /// Rank functions by type signature, e.g. "(&str) -> Result<_, _>" ("_" matches any type)
+        #[arg(long, value_name = "QUERY", allow_hyphen_values = true)]
+        sig: Option<String>,
+        /// Maximum results with --sig
+        #[arg(short, long, default_value = "20")]
+        limit: usize,
+        #[command(flatten)]
+        scope: CrateScope,
     },
     /// List or search struct definitions in a crate
     Structs {
-        /// Name of the crate to search
-        crate_name: String,
+        /// Name of the crate to search (omit with --all or --crates)
+        crate_name: Option<String>,
         /// Optional regex pattern to filter structs
         pattern: Option<String>,
+        #[command(flatten)]
+        scope: CrateScope,
     },
     /// List or search enum definitions in a crate
     Enums {
-        /// Name of the crate to search
-        crate_name: String,
+        /// Name of the crate to search (omit with --all or --crates)
+        crate_name: Option<String>,
         /// Optional regex pattern to filter enums
         pattern: Option<String>,
+        #[command(flatten)]
+        scope: CrateScope,
     },
     /// List or search trait definitions in a crate
     Traits {
-        /// Name of the crate to search
-        crate_name: String,
+        /// Name of the crate to search (omit with --all or --crates)
+        crate_name: Option<String>,
         /// Optional regex pattern to filter traits
         pattern: Option<String>,
+        #[command(flatten)]
+        scope: CrateScope,
     },
     /// List or search macro definitions in a crate
     Macros {
-        /// Name of the crate to search
-        crate_name: String,
+        /// Name of the crate to search (omit with --all or --crates)
+        crate_name: Option<String>,
         /// Optional regex pattern to filter macros
         pattern: Option<String>,
+        #[command(flatten)]
+        scope: CrateScope,
     },
     /// List or search type alias definitions in a crate
     Types {
-        /// Name of the crate to search
-        crate_name: String,
+        /// Name of the crate to search (omit with --all or --crates)
+        crate_name: Option<String>,
         /// Optional regex pattern to filter type aliases
         pattern: Option<String>,
+        #[command(flatten)]
+        scope: CrateScope,
     },
     /// List or search constant/static definitions in a crate
     Consts {
-        /// Name of the crate to search
-        crate_name: String,
+        /// Name of the crate to search (omit with --all or --crates)
+        crate_name: Option<String>,
         /// Optional regex pattern to filter constants
         pattern: Option<String>,
+        #[command(flatten)]
+        scope: CrateScope,
     },
     /// List or search impl blocks in a crate
     Impls {
-        /// Name of the crate to search
-        crate_name: String,
+        /// Name of the crate to search (omit with --all or --crates)
+        crate_name: Option<String>,
         /// Optional regex pattern to filter by type or trait name
         pattern: Option<String>,
+        #[command(flatten)]
+        scope: CrateScope,
     },
-    /// Show full details of an item by ID, including source code
+    /// Show full details of an item by ID or path, including source code
     Show {
-        /// Item ID (8-character hex)
+        /// Item ID or a unique prefix of it (e.g. "3f2a9c01"), or a path like "serde::de::Deserializer"
         id: String,
     },
     /// Get the latest version of a crate from crates.io
@@ -109,6 +233,64 @@ enum Commands {
         /// Name of the crate
         crate_name: String,
     },
+    /// Check every indexed crate for a newer version and fetch updates in one batch
+    RefreshAll,
+    /// Re-index crates from their downloaded sources (by default only those indexed by an older version)
+    Reindex {
+        /// Re-index every crate, not only outdated ones
+        #[arg(long)]
+        all: bool,
+    },
+    /// List indexed crates with their size on disk, item count, embedding status and fetch date
+    List,
+    /// Remove indexed crates and their downloaded sources
+    Remove {
+        /// Crate keys as shown by `list` (e.g., "anyhow-1.0.100")
+        #[arg(required = true)]
+        keys: Vec<String>,
+    },
+    /// Remove all but the newest versions of each crates.io crate (git crates are kept)
+    Prune {
+        /// Number of versions of each crate to keep (at least 1)
+        #[arg(long, default_value = "1")]
+        keep_latest: usize,
+        /// Only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Delete orphaned crate directories, git checkouts and database rows, then compact the database
+    Gc,
+    /// Package indexed crates into a .tar.zst bundle that `import` can load elsewhere
+    Export {
+        /// Crates to export (e.g., "serde" or "serde-1.0.200")
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        crates: Vec<String>,
+        /// Export every crate in the index
+        #[arg(long)]
+        all: bool,
+        /// Bundle file to write (e.g., "index.tar.zst")
+        #[arg(short, long)]
+        output: std::path::PathBuf,
+        /// Include the crates' sources, so they can be re-indexed after import (file contents
+        /// stored in the index are exported either way)
+        #[arg(long)]
+        sources: bool,
+    },
+    /// Merge the crates in a bundle created by `export` into the index
+    Import {
+        /// Bundle file
+        bundle: std::path::PathBuf,
+        /// Replace crates that are already indexed
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// List the files of an indexed crate with their size, line count, language and target
+    Ls {
+        /// Name of the crate (e.g., "anyhow" or "anyhow-1.0.100")
+        crate_name: String,
+        /// Only list files under this directory (e.g., "src/de")
+        dir: Option<String>,
+    },
     /// Read a file from an indexed crate
     Read {
         /// Name of the crate (e.g., "anyhow" or "anyhow-1.0.100")
@@ -146,23 +328,222 @@ enum Commands {
     },
 }
 
+/// Crates covered by a search or listing command besides a single named crate
+#[derive(Args)]
+struct CrateScope {
+    /// Search every indexed crate (newest indexed version of each)
+    #[arg(long, conflicts_with = "crates")]
+    all: bool,
+    /// Search these crates (comma-separated, e.g. "serde,tokio")
+    #[arg(long, value_delimiter = ',')]
+    crates: Vec<String>,
+}
+
+/// Matching, context and paging options of `search`
+#[derive(Args)]
+struct SearchFlags {
+    /// Let matches span lines; the first line of each match shows its line:column range
+    #[arg(short = 'U', long)]
+    multiline: bool,
+    /// Treat the pattern as a literal string instead of a regex
+    #[arg(short = 'F', long)]
+    fixed_strings: bool,
+    /// Case-insensitive matching
+    #[arg(short, long)]
+    ignore_case: bool,
+    /// Only match whole words
+    #[arg(short, long)]
+    word_regexp: bool,
+    /// Lines of context after each match
+    #[arg(short = 'A', long, value_name = "N")]
+    after_context: Option<usize>,
+    /// Lines of context before each match
+    #[arg(short = 'B', long, value_name = "N")]
+    before_context: Option<usize>,
+    /// Lines of context before and after each match
+    #[arg(short = 'C', long, value_name = "N")]
+    context: Option<usize>,
+    /// Only search files matching this glob, e.g. 'src/de/**' or '*_test.rs' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    glob: Vec<String>,
+    /// Skip files matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Stop searching a file after this many matches
+    #[arg(short, long, value_name = "N")]
+    max_count: Option<usize>,
+    /// Only match in code, comments, strings or docs (comma-separated, e.g. "comments,docs")
+    #[arg(long = "in", value_name = "WHERE", value_delimiter = ',')]
+    within: Vec<Class>,
+    /// Only match inside items of this kind (function, struct, enum, trait, macro, type_alias, constant, impl)
+    #[arg(long, value_name = "KIND")]
+    kind: Option<String>,
+    /// Only match inside items whose name matches this regex (the self type for impls)
+    #[arg(long, value_name = "REGEX")]
+    item: Option<String>,
+    /// Only match inside impls of this trait, e.g. "Drop"; with --kind, inside those items in them
+    #[arg(long = "impl", value_name = "TRAIT")]
+    impl_trait: Option<String>,
+    /// Show at most this many matches
+    #[arg(long)]
+    limit: Option<usize>,
+    /// Skip this many matches (for paging with --limit)
+    #[arg(long, default_value = "0")]
+    offset: usize,
+}
+
+impl SearchFlags {
+    fn options(&self) -> SearchOptions {
+        SearchOptions {
+            multiline: self.multiline,
+            fixed_strings: self.fixed_strings,
+            ignore_case: self.ignore_case,
+            whole_word: self.word_regexp,
+            before_context: self.before_context.or(self.context).unwrap_or(0),
+            after_context: self.after_context.or(self.context).unwrap_or(0),
+            globs: self.glob.clone(),
+            excludes: self.exclude.clone(),
+            max_count: self.max_count,
+            within: self.within.clone(),
+            items: (self.kind.is_some() || self.item.is_some() || self.impl_trait.is_some()).then(|| ItemFilter {
+                kind: self.kind.clone(),
+                name: self.item.clone(),
+                impl_trait: self.impl_trait.clone(),
+            }),
+        }
+    }
+}
+
+enum CrateTarget {
+    /// One crate plus the crates it re-exports
+    Named(String),
+    List(Vec<String>),
+    All,
+}
+
+impl CrateScope {
+    /// Work out the target crates and the pattern. With `--all`/`--crates` no crate
+    /// name is given, so the first positional argument is the pattern.
+    fn target(self, crate_name: Option<String>, pattern: Option<String>) -> Result<(CrateTarget, Option<String>)> {
+        if !self.all && self.crates.is_empty() {
+            let crate_name = crate_name
+                .ok_or_else(|| anyhow::anyhow!("Missing crate name (or pass --all / --crates)"))?;
+            return Ok((CrateTarget::Named(crate_name), pattern));
+        }
+        if pattern.is_some() {
+            anyhow::bail!("Pass either a crate name or --all / --crates, not both");
+        }
+        let target = if self.all { CrateTarget::All } else { CrateTarget::List(self.crates) };
+        Ok((target, crate_name))
+    }
+}
+
+impl CrateTarget {
+    fn crate_keys(&self, db: &Database) -> Result<Vec<String>> {
+        match self {
+            CrateTarget::Named(name) => find_crate_keys_with_reexports(db, name),
+            CrateTarget::List(names) => {
+                let mut keys: Vec<String> = Vec::new();
+                for name in names {
+                    let key = find_crate_key(db, name)?;
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+                Ok(keys)
+            }
+            CrateTarget::All => {
+                let keys = db.latest_crate_keys()?;
+                indexer::reindex_stale_crates(db, Some(&keys))?;
+                Ok(keys)
+            }
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    let mut settings = config::Config::load(cli.profile.as_deref())?;
+    if cli.offline {
+        settings.offline = true;
+    }
+    if let Some(dir) = cli.index_dir {
+        settings.index_dir = Some(std::path::absolute(&dir)?);
+    }
+    settings.refresh = cli.refresh;
+    config::init(settings);
+
     match cli.command {
-        Commands::Fetch { crate_name, version } => cmd_fetch(&crate_name, version.as_deref())?,
-        Commands::Search { crate_name, pattern } => cmd_search(&crate_name, &pattern)?,
-        Commands::Functions { crate_name, pattern } => cmd_functions(&crate_name, pattern.as_deref())?,
-        Commands::Structs { crate_name, pattern } => cmd_structs(&crate_name, pattern.as_deref())?,
-        Commands::Enums { crate_name, pattern } => cmd_enums(&crate_name, pattern.as_deref())?,
-        Commands::Traits { crate_name, pattern } => cmd_traits(&crate_name, pattern.as_deref())?,
-        Commands::Macros { crate_name, pattern } => cmd_macros(&crate_name, pattern.as_deref())?,
-        Commands::Types { crate_name, pattern } => cmd_types(&crate_name, pattern.as_deref())?,
-        Commands::Consts { crate_name, pattern } => cmd_consts(&crate_name, pattern.as_deref())?,
-        Commands::Impls { crate_name, pattern } => cmd_impls(&crate_name, pattern.as_deref())?,
+        Commands::Fetch { crate_name, version, git, rev, branch, tag, deps } => match git {
+            Some(url) => {
+                let reference = GitReference::from_args(rev.as_deref(), branch.as_deref(), tag.as_deref());
+                cmd_fetch_git(&crate_name, &url, &reference)?
+            }
+            None => cmd_fetch(&crate_name, version.as_deref(), deps.unwrap_or(0))?,
+        },
+        Commands::Info { crate_name } => cmd_info(&crate_name)?,
+        Commands::Deps { crate_name } => cmd_deps(&crate_name)?,
+        Commands::Rdeps { crate_name } => cmd_rdeps(&crate_name)?,
+        Commands::ImportVendor { dir } => cmd_import_vendor(&dir)?,
+        Commands::Search { crate_name, pattern, scope, flags } => {
+            let (target, pattern) = scope.target(crate_name, pattern)?;
+            let pattern = pattern.ok_or_else(|| anyhow::anyhow!("Missing regex pattern to search for"))?;
+            cmd_search(&target, &pattern, &flags)?
+        }
+        Commands::Ssearch { crate_name, pattern, scope } => {
+            let (target, pattern) = scope.target(crate_name, pattern)?;
+            let pattern = pattern.ok_or_else(|| anyhow::anyhow!("Missing item pattern to search for"))?;
+            cmd_ssearch(&target, &pattern)?
+        }
+        Commands::Find { query, crates, kind, limit } => cmd_find(&query, &crates, kind.as_deref(), limit)?,
+        Commands::Functions { crate_name, pattern, sig, limit, scope } => {
+            let (target, pattern) = scope.target(crate_name, pattern)?;
+            match sig {
+                Some(sig) => cmd_functions_by_type(&target, pattern.as_deref(), &sig, limit)?,
+                None => cmd_functions(&target, pattern.as_deref())?,
+            }
+        }
+        Commands::Structs { crate_name, pattern, scope } => {
+            let (target, pattern) = scope.target(crate_name, pattern)?;
+            cmd_structs(&target, pattern.as_deref())?
+        }
+        Commands::Enums { crate_name, pattern, scope } => {
+            let (target, pattern) = scope.target(crate_name, pattern)?;
+            cmd_enums(&target, pattern.as_deref())?
+        }
+        Commands::Traits { crate_name, pattern, scope } => {
+            let (target, pattern) = scope.target(crate_name, pattern)?;
+            cmd_traits(&target, pattern.as_deref())?
+        }
+        Commands::Macros { crate_name, pattern, scope } => {
+            let (target, pattern) = scope.target(crate_name, pattern)?;
+            cmd_macros(&target, pattern.as_deref())?
+        }
+        Commands::Types { crate_name, pattern, scope } => {
+            let (target, pattern) = scope.target(crate_name, pattern)?;
+            cmd_types(&target, pattern.as_deref())?
+        }
+        Commands::Consts { crate_name, pattern, scope } => {
+            let (target, pattern) = scope.target(crate_name, pattern)?;
+            cmd_consts(&target, pattern.as_deref())?
+        }
+        Commands::Impls { crate_name, pattern, scope } => {
+            let (target, pattern) = scope.target(crate_name, pattern)?;
+            cmd_impls(&target, pattern.as_deref())?
+        }
         Commands::Show { id } => cmd_show(&id)?,
         Commands::Latest { crate_name } => cmd_latest(&crate_name)?,
+        Commands::RefreshAll => cmd_refresh_all()?,
+        Commands::Reindex { all } => cmd_reindex(all)?,
+        Commands::List => cmd_list()?,
+        Commands::Remove { keys } => cmd_remove(&keys)?,
+        Commands::Prune { keep_latest, dry_run } => cmd_prune(keep_latest, dry_run)?,
+        Commands::Gc => cmd_gc()?,
+        Commands::Export { crates, all, output, sources } => cmd_export(&crates, all, &output, sources)?,
+        Commands::Import { bundle, overwrite } => cmd_import(&bundle, overwrite)?,
+        Commands::Ls { crate_name, dir } => cmd_ls(&crate_name, dir.as_deref())?,
         Commands::Read { crate_name, file_path, start, end } => cmd_read(&crate_name, &file_path, start, end)?,
         Commands::Readme { crate_name } => cmd_readme(&crate_name)?,
         Commands::Mcp => {
@@ -179,52 +560,321 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn cmd_fetch(crate_name: &str, version: Option<&str>) -> Result<()> {
+fn cmd_fetch(crate_name: &str, version: Option<&str>, dep_depth: usize) -> Result<()> {
     let db = Database::open()?;
     let before_count = db.list_crate_keys()?.len();
-    fetch_single_crate(&db, crate_name, version)?;
+    let roots = vec![(crate_name.to_string(), version.map(String::from))];
+    let failures = fetch_crates(&db, roots, dep_depth, true)?.failures;
     let after_count = db.list_crate_keys()?.len();
     println!("\nDone! Indexed {} crates total.", after_count - before_count);
+
+    print_fetch_failures(&failures);
+    if !failures.is_empty() {
+        anyhow::bail!("{} crate(s) failed to fetch", failures.len());
+    }
     Ok(())
 }
 
-fn cmd_search(crate_name: &str, pattern: &str) -> Result<()> {
+fn cmd_fetch_git(crate_name: &str, url: &str, reference: &GitReference) -> Result<()> {
     let db = Database::open()?;
+    let git_crate = git::fetch_git_crate(url, reference, crate_name)?;
+
+    println!("Indexing {} (commit {})...", git_crate.key, git_crate.commit);
+    let result = indexer::index_crate_incremental(&db, &git_crate.path, &git_crate.key)?;
+    println!("  {} fns, {} structs, {} enums, {} traits, {} macros, {} types, {} consts, {} impls",
+        result.items.functions.len(),
+        result.items.structs.len(),
+        result.items.enums.len(),
+        result.items.traits.len(),
+        result.items.macros.len(),
+        result.items.type_aliases.len(),
+        result.items.constants.len(),
+        result.items.impls.len());
+    let reused = result.items.files.iter().filter(|f| f.reused).count();
+    if reused > 0 {
+        println!("  ({} unchanged file(s) kept from the previous index)", reused);
+    }
+    db.add_crate(&git_crate.key, &git_crate.path, &result.items, &result.reexported_crates)?;
+
+    // Re-exported dependencies still come from crates.io
+    let mut reexports = Vec::new();
+    for reexport in &result.reexported_crates {
+        if db.find_all_crate_keys(reexport)?.is_empty() {
+            println!("  re-exports {}", reexport);
+            reexports.push((reexport.clone(), None));
+        }
+    }
+    if !reexports.is_empty() {
+        print_fetch_failures(&fetch_crates(&db, reexports, 0, true)?.failures);
+    }
 
-    let crate_keys = find_crate_keys_with_reexports(&db, crate_name)?;
-    let mut total_matches = 0;
+    println!("\nDone! Indexed {}", git_crate.key);
+    Ok(())
+}
 
-    for crate_key in &crate_keys {
-        if let Some(crate_path) = db.get_crate_path(crate_key)? {
-            let matches = search_regex(&crate_path, pattern)?;
+fn cmd_info(crate_name: &str) -> Result<()> {
+    let db = Database::open()?;
+    let crate_key = find_crate_key(&db, crate_name)?;
+    let meta = indexed_metadata(&db, &crate_key)?;
+    let deps = indexed_dependencies(&db, &crate_key)?;
 
-            if !matches.is_empty() {
-                if total_matches == 0 {
-                    println!("Results for pattern '{}':\n", pattern);
-                }
-                println!("── {} ──", crate_key);
-                for m in &matches {
-                    println!("  {}:{}: {}", m.file, m.line, m.content);
-                }
-                println!();
-                total_matches += matches.len();
+    print!("{}", meta.summary(&crate_key, &deps));
+    Ok(())
+}
+
+fn cmd_deps(crate_name: &str) -> Result<()> {
+    let db = Database::open()?;
+    let crate_key = find_crate_key(&db, crate_name)?;
+    let deps = indexed_dependencies(&db, &crate_key)?;
+
+    if deps.is_empty() {
+        println!("{} has no registry dependencies", crate_key);
+        return Ok(());
+    }
+
+    println!("Dependencies of {}:\n", crate_key);
+    let mut indexed = 0;
+    for dep in &deps {
+        let resolved = match semver::VersionReq::parse(&dep.req) {
+            Ok(req) => indexed_matching_version(&db, &dep.name, &req)?,
+            Err(_) => None,
+        };
+
+        let flags = dep.flags();
+
+        match resolved {
+            Some(version) => {
+                indexed += 1;
+                println!("  {} {}{} -> {}-{}", dep.name, dep.req, flags, dep.name, version);
             }
+            None => println!("  {} {}{} (not indexed)", dep.name, dep.req, flags),
         }
     }
 
-    if total_matches == 0 {
+    println!("\n{} dependencies, {} indexed", deps.len(), indexed);
+    if indexed < deps.len() {
+        println!("Run `crate-indexer fetch {} --deps 1` to index the missing ones.", storage::split_crate_key(&crate_key).0);
+    }
+    Ok(())
+}
+
+fn cmd_rdeps(crate_name: &str) -> Result<()> {
+    let db = Database::open()?;
+
+    // "syn-2.0.114" narrows the result to requirements that accept that version
+    let (name, version) = storage::split_crate_key(crate_name);
+
+    let dependents: Vec<_> = db
+        .get_dependents(name)?
+        .into_iter()
+        .filter(|(_, dep)| match &version {
+            Some(v) => semver::VersionReq::parse(&dep.req).is_ok_and(|req| req.matches(v)),
+            None => true,
+        })
+        .collect();
+
+    if dependents.is_empty() {
+        println!("No indexed crate depends on {}", crate_name);
+        return Ok(());
+    }
+
+    println!("Indexed crates depending on {}:\n", crate_name);
+    for (key, dep) in &dependents {
+        println!("  {} requires {} {}{}", key, dep.name, dep.req, dep.flags());
+    }
+    println!("\n{} dependent(s)", dependents.len());
+    Ok(())
+}
+
+fn cmd_import_vendor(dir: &std::path::Path) -> Result<()> {
+    let db = Database::open()?;
+    let indexed: HashSet<String> = db.list_crate_keys()?.into_iter().collect();
+
+    let vendored: Vec<_> = vendor::scan_vendor_dir(dir)?
+        .into_iter()
+        .filter(|c| !indexed.contains(&c.key()))
+        .collect();
+
+    if vendored.is_empty() {
+        println!("All vendored crates are already indexed.");
+        return Ok(());
+    }
+
+    println!("Importing {} vendored crate(s)...", vendored.len());
+    let results: Vec<_> = vendored
+        .par_iter()
+        .map(|c| (c.key(), import_vendored_crate(c)))
+        .collect();
+
+    let mut imported = 0;
+    let mut failed = Vec::new();
+    for (key, result) in results {
+        match result {
+            Ok((path, result)) => {
+                println!("  {}: {} fns, {} structs, {} enums, {} traits",
+                    key,
+                    result.items.functions.len(),
+                    result.items.structs.len(),
+                    result.items.enums.len(),
+                    result.items.traits.len());
+                db.add_crate(&key, &path, &result.items, &result.reexported_crates)?;
+                imported += 1;
+            }
+            Err(e) => failed.push((key, e)),
+        }
+    }
+
+    println!("\nDone! Imported {} crate(s).", imported);
+    if !failed.is_empty() {
+        println!("\nFailed ({}):", failed.len());
+        for (key, e) in &failed {
+            println!("  {}: {:#}", key, e);
+        }
+    }
+
+    Ok(())
+}
+
+fn import_vendored_crate(c: &vendor::VendoredCrate) -> Result<(std::path::PathBuf, indexer::IndexResult)> {
+    vendor::verify_checksums(&c.path)?;
+
+    let dest = storage::crate_path(&c.name, &c.version);
+    {
+        let _lock = storage::lock_crate_path(&c.key())?;
+        // Reuse an earlier copy only if it passes the same checks; a partial copy
+        // or a registry extraction (which has no checksum file) is replaced
+        if !dest.exists() || vendor::verify_checksums(&dest).is_err() {
+            if dest.exists() {
+                remove_path(&dest)?;
+            }
+            if let Err(e) = fetcher::copy_dir(&c.path, &dest) {
+                let _ = std::fs::remove_dir_all(&dest);
+                return Err(e);
+            }
+        }
+    }
+
+    let result = index_crate(&dest, &c.key())?;
+    Ok((dest, result))
+}
+
+fn cmd_search(target: &CrateTarget, pattern: &str, flags: &SearchFlags) -> Result<()> {
+    if flags.limit == Some(0) {
+        anyhow::bail!("--limit must be at least 1");
+    }
+    let db = Database::open()?;
+    let options = flags.options();
+
+    let crate_keys = target.crate_keys(&db)?;
+    let mut matches = Vec::new();
+    for crate_key in &crate_keys {
+        matches.extend(search_regex(&db, crate_key, pattern, &options)?.into_iter().map(|m| (crate_key, m)));
+    }
+
+    if matches.is_empty() {
         println!("No matches found for pattern: {}", pattern);
+        return Ok(());
+    }
+    if flags.offset >= matches.len() {
+        println!("No matches past offset {} ({} in total)", flags.offset, matches.len());
+        return Ok(());
+    }
+    let end = flags.limit.map_or(matches.len(), |limit| flags.offset.saturating_add(limit).min(matches.len()));
+    let page = &matches[flags.offset..end];
+
+    println!("Results for pattern '{}':\n", pattern);
+    for group in page.chunk_by(|a, b| a.0 == b.0) {
+        println!("── {} ──", group[0].0);
+        for line in search::format_matches(group.iter().map(|(_, m)| m), &options) {
+            println!("  {}", line);
+        }
+        println!();
+    }
+
+    println!("Total: {} matches across {} crate(s)", matches.len(), crate_keys.len());
+    if flags.offset > 0 || end < matches.len() {
+        print!("Showing matches {}-{}", flags.offset + 1, end);
+        if end < matches.len() {
+            print!(" (use --offset {} for more)", end);
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+fn cmd_ssearch(target: &CrateTarget, pattern: &str) -> Result<()> {
+    let db = Database::open()?;
+    let parsed = Pattern::parse(pattern)?;
+
+    let crate_keys = target.crate_keys(&db)?;
+    let mut total_matches = 0;
+    for crate_key in &crate_keys {
+        let matches = search::search_structural(&db, crate_key, &parsed)?;
+        if matches.is_empty() {
+            continue;
+        }
+        if total_matches == 0 {
+            println!("Items matching '{}':\n", pattern);
+        }
+        println!("── {} ──", crate_key);
+        for m in &matches {
+            println!("  {}", search::format_structural_match(m).replace('\n', "\n  "));
+        }
+        println!();
+        total_matches += matches.len();
+    }
+
+    if total_matches == 0 {
+        println!("No items match: {}", pattern);
+    } else {
+        println!("Total: {} items across {} crate(s)", total_matches, crate_keys.len());
+    }
+    Ok(())
+}
+
+fn cmd_find(query: &str, crates: &[String], kind: Option<&str>, limit: usize) -> Result<()> {
+    if limit == 0 {
+        anyhow::bail!("--limit must be at least 1");
+    }
+    let db = Database::open()?;
+    let fts_query = search::fts_query(query)
+        .ok_or_else(|| anyhow::anyhow!("Search query '{}' contains no words", query))?;
+    if let Some(kind) = kind {
+        if !storage::ITEM_KINDS.contains(&kind) {
+            anyhow::bail!("Unknown item kind '{}' (expected one of: {})", kind, storage::ITEM_KINDS.join(", "));
+        }
+    }
+
+    let scope = if crates.is_empty() {
+        None
     } else {
-        println!("Total: {} matches across {} crate(s)", total_matches, crate_keys.len());
+        Some(crates.iter().map(|c| find_crate_key(&db, c)).collect::<Result<Vec<_>>>()?)
+    };
+    indexer::reindex_stale_crates(&db, scope.as_deref())?;
+
+    let hits = db.search_items(&fts_query, scope.as_deref(), kind, limit)?;
+    if hits.is_empty() {
+        let crate_keys = match scope {
+            Some(keys) => keys,
+            None => db.list_crate_keys()?,
+        };
+        println!("No items matching '{}'.{}", query, fuzzy::item_hint(&db, &crate_keys, kind, query.trim())?);
+        return Ok(());
     }
 
+    for hit in &hits {
+        println!("[{}] {} {} ({}) {}:{}", hit.id, hit.item_type, hit.name, hit.crate_key, hit.file, hit.line);
+        println!("    {}", hit.snippet.replace('\n', " "));
+    }
+    println!("\n{} result(s){}", hits.len(), if hits.len() == limit { " (limit reached, use --limit for more)" } else { "" });
     Ok(())
 }
 
-fn cmd_functions(crate_name: &str, pattern: Option<&str>) -> Result<()> {
+fn cmd_functions(target: &CrateTarget, pattern: Option<&str>) -> Result<()> {
     let db = Database::open()?;
 
-    let crate_keys = find_crate_keys_with_reexports(&db, crate_name)?;
+    let crate_keys = target.crate_keys(&db)?;
     let mut total_functions = 0;
 
     for crate_key in &crate_keys {
@@ -251,24 +901,52 @@ fn cmd_functions(crate_name: &str, pattern: Option<&str>) -> Result<()> {
         }
     }
 
-    if total_functions == 0 {
-        if let Some(p) = pattern {
-            println!("No functions matching '{}'", p);
-        } else {
-            println!("No functions found");
+    print_summary(&db, &crate_keys, "function", "functions", total_functions, pattern)?;
+
+    Ok(())
+}
+
+fn cmd_functions_by_type(target: &CrateTarget, pattern: Option<&str>, sig: &str, limit: usize) -> Result<()> {
+    if limit == 0 {
+        anyhow::bail!("--limit must be at least 1");
+    }
+    let db = Database::open()?;
+    let query = TypeQuery::parse(sig)?;
+
+    let crate_keys = target.crate_keys(&db)?;
+    let mut ranked = Vec::new();
+    for crate_key in &crate_keys {
+        let functions = search_functions(&db.get_functions(crate_key)?, pattern)?;
+        for (distance, func) in search_signatures(functions, &query) {
+            ranked.push((distance, crate_key, func));
         }
-    } else {
-        println!("Total: {} functions across {} crate(s)", total_functions, crate_keys.len());
     }
+    ranked.sort_by(|(a, _, f), (b, _, g)| a.cmp(b).then_with(|| f.name.cmp(&g.name)));
+
+    if ranked.is_empty() {
+        println!("No functions matching type '{}'", sig);
+        return Ok(());
+    }
+    for (distance, crate_key, func) in ranked.iter().take(limit) {
+        println!("[{}] {}", func.id, func.signature);
+        println!("  {} {}:{} (distance {})", crate_key, func.file, func.line, distance);
+        println!();
+    }
+    let shown = ranked.len().min(limit);
+    println!(
+        "{} of {} function(s) across {} crate(s){}",
+        shown, ranked.len(), crate_keys.len(),
+        if shown < ranked.len() { " (use --limit for more)" } else { "" }
+    );
 
     Ok(())
 }
 
-fn cmd_structs(crate_name: &str, pattern: Option<&str>) -> Result<()> {
+fn cmd_structs(target: &CrateTarget, pattern: Option<&str>) -> Result<()> {
     let db = Database::open()?;
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
+    let regex = pattern.map(build_regex).transpose()?;
 
-    let crate_keys = find_crate_keys_with_reexports(&db, crate_name)?;
+    let crate_keys = target.crate_keys(&db)?;
     let mut total = 0;
 
     for crate_key in &crate_keys {
@@ -299,15 +977,15 @@ fn cmd_structs(crate_name: &str, pattern: Option<&str>) -> Result<()> {
         }
     }
 
-    print_summary("structs", total, pattern, crate_keys.len());
+    print_summary(&db, &crate_keys, "struct", "structs", total, pattern)?;
     Ok(())
 }
 
-fn cmd_enums(crate_name: &str, pattern: Option<&str>) -> Result<()> {
+fn cmd_enums(target: &CrateTarget, pattern: Option<&str>) -> Result<()> {
     let db = Database::open()?;
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
+    let regex = pattern.map(build_regex).transpose()?;
 
-    let crate_keys = find_crate_keys_with_reexports(&db, crate_name)?;
+    let crate_keys = target.crate_keys(&db)?;
     let mut total = 0;
 
     for crate_key in &crate_keys {
@@ -335,15 +1013,15 @@ fn cmd_enums(crate_name: &str, pattern: Option<&str>) -> Result<()> {
         }
     }
 
-    print_summary("enums", total, pattern, crate_keys.len());
+    print_summary(&db, &crate_keys, "enum", "enums", total, pattern)?;
     Ok(())
 }
 
-fn cmd_traits(crate_name: &str, pattern: Option<&str>) -> Result<()> {
+fn cmd_traits(target: &CrateTarget, pattern: Option<&str>) -> Result<()> {
     let db = Database::open()?;
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
+    let regex = pattern.map(build_regex).transpose()?;
 
-    let crate_keys = find_crate_keys_with_reexports(&db, crate_name)?;
+    let crate_keys = target.crate_keys(&db)?;
     let mut total = 0;
 
     for crate_key in &crate_keys {
@@ -368,15 +1046,15 @@ fn cmd_traits(crate_name: &str, pattern: Option<&str>) -> Result<()> {
         }
     }
 
-    print_summary("traits", total, pattern, crate_keys.len());
+    print_summary(&db, &crate_keys, "trait", "traits", total, pattern)?;
     Ok(())
 }
 
-fn cmd_macros(crate_name: &str, pattern: Option<&str>) -> Result<()> {
+fn cmd_macros(target: &CrateTarget, pattern: Option<&str>) -> Result<()> {
     let db = Database::open()?;
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
+    let regex = pattern.map(build_regex).transpose()?;
 
-    let crate_keys = find_crate_keys_with_reexports(&db, crate_name)?;
+    let crate_keys = target.crate_keys(&db)?;
     let mut total = 0;
 
     for crate_key in &crate_keys {
@@ -401,15 +1079,15 @@ fn cmd_macros(crate_name: &str, pattern: Option<&str>) -> Result<()> {
         }
     }
 
-    print_summary("macros", total, pattern, crate_keys.len());
+    print_summary(&db, &crate_keys, "macro", "macros", total, pattern)?;
     Ok(())
 }
 
-fn cmd_types(crate_name: &str, pattern: Option<&str>) -> Result<()> {
+fn cmd_types(target: &CrateTarget, pattern: Option<&str>) -> Result<()> {
     let db = Database::open()?;
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
+    let regex = pattern.map(build_regex).transpose()?;
 
-    let crate_keys = find_crate_keys_with_reexports(&db, crate_name)?;
+    let crate_keys = target.crate_keys(&db)?;
     let mut total = 0;
 
     for crate_key in &crate_keys {
@@ -434,15 +1112,15 @@ fn cmd_types(crate_name: &str, pattern: Option<&str>) -> Result<()> {
         }
     }
 
-    print_summary("type aliases", total, pattern, crate_keys.len());
+    print_summary(&db, &crate_keys, "type_alias", "type aliases", total, pattern)?;
     Ok(())
 }
 
-fn cmd_consts(crate_name: &str, pattern: Option<&str>) -> Result<()> {
+fn cmd_consts(target: &CrateTarget, pattern: Option<&str>) -> Result<()> {
     let db = Database::open()?;
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
+    let regex = pattern.map(build_regex).transpose()?;
 
-    let crate_keys = find_crate_keys_with_reexports(&db, crate_name)?;
+    let crate_keys = target.crate_keys(&db)?;
     let mut total = 0;
 
     for crate_key in &crate_keys {
@@ -467,15 +1145,15 @@ fn cmd_consts(crate_name: &str, pattern: Option<&str>) -> Result<()> {
         }
     }
 
-    print_summary("constants", total, pattern, crate_keys.len());
+    print_summary(&db, &crate_keys, "constant", "constants", total, pattern)?;
     Ok(())
 }
 
-fn cmd_impls(crate_name: &str, pattern: Option<&str>) -> Result<()> {
+fn cmd_impls(target: &CrateTarget, pattern: Option<&str>) -> Result<()> {
     let db = Database::open()?;
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
+    let regex = pattern.map(build_regex).transpose()?;
 
-    let crate_keys = find_crate_keys_with_reexports(&db, crate_name)?;
+    let crate_keys = target.crate_keys(&db)?;
     let mut total = 0;
 
     for crate_key in &crate_keys {
@@ -504,12 +1182,16 @@ fn cmd_impls(crate_name: &str, pattern: Option<&str>) -> Result<()> {
         }
     }
 
-    print_summary("impls", total, pattern, crate_keys.len());
+    print_summary(&db, &crate_keys, "impl", "impls", total, pattern)?;
     Ok(())
 }
 
-fn cmd_show(id: &str) -> Result<()> {
+fn cmd_show(selector: &str) -> Result<()> {
     let db = Database::open()?;
+    let id = &match selector.split_once("::") {
+        Some((crate_name, path)) => db.resolve_item_path(&find_crate_key(&db, crate_name)?, path)?,
+        None => db.resolve_item_id(selector)?,
+    };
 
     // Try to find the item in each table
     if let Some((crate_key, func)) = db.get_function_by_id(id)? {
@@ -541,9 +1223,6 @@ fn cmd_show(id: &str) -> Result<()> {
 }
 
 fn show_function(db: &Database, crate_key: &str, func: &storage::FunctionInfo) -> Result<()> {
-    let crate_path = db.get_crate_path(crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found for '{}'", crate_key))?;
-
     println!("Function: {}", func.name);
     println!("Crate:    {}", crate_key);
     println!("File:     {}", func.file);
@@ -559,14 +1238,11 @@ fn show_function(db: &Database, crate_key: &str, func: &storage::FunctionInfo) -
         }
     }
 
-    show_source(&crate_path, &func.file, func.line, func.end_line)?;
+    show_source(db, crate_key, &func.file, func.line, func.end_line)?;
     Ok(())
 }
 
 fn show_struct(db: &Database, crate_key: &str, s: &storage::StructInfo) -> Result<()> {
-    let crate_path = db.get_crate_path(crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found for '{}'", crate_key))?;
-
     println!("Struct: {}", s.name);
     println!("Crate:  {}", crate_key);
     println!("File:   {}", s.file);
@@ -588,14 +1264,11 @@ fn show_struct(db: &Database, crate_key: &str, s: &storage::StructInfo) -> Resul
         }
     }
 
-    show_source(&crate_path, &s.file, s.line, s.end_line)?;
+    show_source(db, crate_key, &s.file, s.line, s.end_line)?;
     Ok(())
 }
 
 fn show_enum(db: &Database, crate_key: &str, e: &storage::EnumInfo) -> Result<()> {
-    let crate_path = db.get_crate_path(crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found for '{}'", crate_key))?;
-
     println!("Enum:   {}", e.name);
     println!("Crate:  {}", crate_key);
     println!("File:   {}", e.file);
@@ -618,14 +1291,11 @@ fn show_enum(db: &Database, crate_key: &str, e: &storage::EnumInfo) -> Result<()
         }
     }
 
-    show_source(&crate_path, &e.file, e.line, e.end_line)?;
+    show_source(db, crate_key, &e.file, e.line, e.end_line)?;
     Ok(())
 }
 
 fn show_trait(db: &Database, crate_key: &str, t: &storage::TraitInfo) -> Result<()> {
-    let crate_path = db.get_crate_path(crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found for '{}'", crate_key))?;
-
     println!("Trait:  {}", t.name);
     println!("Crate:  {}", crate_key);
     println!("File:   {}", t.file);
@@ -640,14 +1310,11 @@ fn show_trait(db: &Database, crate_key: &str, t: &storage::TraitInfo) -> Result<
         }
     }
 
-    show_source(&crate_path, &t.file, t.line, t.end_line)?;
+    show_source(db, crate_key, &t.file, t.line, t.end_line)?;
     Ok(())
 }
 
 fn show_macro(db: &Database, crate_key: &str, m: &storage::MacroInfo) -> Result<()> {
-    let crate_path = db.get_crate_path(crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found for '{}'", crate_key))?;
-
     println!("Macro:  {}!", m.name);
     println!("Crate:  {}", crate_key);
     println!("File:   {}", m.file);
@@ -664,14 +1331,11 @@ fn show_macro(db: &Database, crate_key: &str, m: &storage::MacroInfo) -> Result<
 
     // Macros often have no end_line, show more context
     let end = m.end_line.or(Some(m.line + 30));
-    show_source(&crate_path, &m.file, m.line, end)?;
+    show_source(db, crate_key, &m.file, m.line, end)?;
     Ok(())
 }
 
 fn show_type_alias(db: &Database, crate_key: &str, t: &storage::TypeAliasInfo) -> Result<()> {
-    let crate_path = db.get_crate_path(crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found for '{}'", crate_key))?;
-
     println!("Type:   {}", t.name);
     println!("Crate:  {}", crate_key);
     println!("File:   {}", t.file);
@@ -688,14 +1352,11 @@ fn show_type_alias(db: &Database, crate_key: &str, t: &storage::TypeAliasInfo) -
         }
     }
 
-    show_source(&crate_path, &t.file, t.line, Some(t.line + 5))?;
+    show_source(db, crate_key, &t.file, t.line, Some(t.line + 5))?;
     Ok(())
 }
 
 fn show_constant(db: &Database, crate_key: &str, c: &storage::ConstantInfo) -> Result<()> {
-    let crate_path = db.get_crate_path(crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found for '{}'", crate_key))?;
-
     println!("{}: {}", c.kind.to_uppercase(), c.name);
     println!("Crate:  {}", crate_key);
     println!("File:   {}", c.file);
@@ -711,14 +1372,11 @@ fn show_constant(db: &Database, crate_key: &str, c: &storage::ConstantInfo) -> R
         }
     }
 
-    show_source(&crate_path, &c.file, c.line, Some(c.line + 10))?;
+    show_source(db, crate_key, &c.file, c.line, Some(c.line + 10))?;
     Ok(())
 }
 
 fn show_impl(db: &Database, crate_key: &str, i: &storage::ImplInfo) -> Result<()> {
-    let crate_path = db.get_crate_path(crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found for '{}'", crate_key))?;
-
     let impl_desc = match &i.trait_name {
         Some(trait_name) => format!("impl {} for {}", trait_name, i.self_type),
         None => format!("impl {}", i.self_type),
@@ -730,40 +1388,392 @@ fn show_impl(db: &Database, crate_key: &str, i: &storage::ImplInfo) -> Result<()
     println!("Line:   {}-{}", i.line, i.end_line.map(|l| l.to_string()).unwrap_or("?".to_string()));
     println!("ID:     {}", i.id);
 
-    show_source(&crate_path, &i.file, i.line, i.end_line)?;
+    show_source(db, crate_key, &i.file, i.line, i.end_line)?;
     Ok(())
 }
 
-fn show_source(crate_path: &std::path::Path, file: &str, start_line: usize, end_line: Option<usize>) -> Result<()> {
-    let source_path = crate_path.join(file);
-    if source_path.exists() {
-        if let Ok(content) = std::fs::read_to_string(&source_path) {
-            let lines: Vec<&str> = content.lines().collect();
-            let start = start_line.saturating_sub(1);
-            let end = end_line.unwrap_or(start_line + 20).min(lines.len());
+fn show_source(db: &Database, crate_key: &str, file: &str, start_line: usize, end_line: Option<usize>) -> Result<()> {
+    if let Some(content) = db.read_file(crate_key, file)? {
+        let lines: Vec<&str> = content.lines().collect();
+        let start = start_line.saturating_sub(1);
+        let end = end_line.unwrap_or(start_line + 20).min(lines.len());
 
-            println!("\nSource:");
-            for (i, line) in lines[start..end].iter().enumerate() {
-                let line_num = start + i + 1;
-                println!("{:4} | {}", line_num, line);
-            }
+        println!("\nSource:");
+        for (i, line) in lines[start..end].iter().enumerate() {
+            let line_num = start + i + 1;
+            println!("{:4} | {}", line_num, line);
         }
     }
     Ok(())
 }
 
 fn cmd_latest(crate_name: &str) -> Result<()> {
+    let db = Database::open()?;
     let fetcher = Fetcher::new()?;
     let version = fetcher.get_latest_version(crate_name)?;
+    fetcher.remember_latest_version(&db, crate_name, &version)?;
     println!("{}", version);
     Ok(())
 }
 
+fn cmd_refresh_all() -> Result<()> {
+    let db = Database::open()?;
+    let fetcher = Fetcher::new()?;
+    let keys = db.list_crate_keys()?;
+    let indexed: HashSet<&String> = keys.iter().collect();
+
+    let names: std::collections::BTreeSet<String> = keys
+        .iter()
+        .filter(|k| !git::is_git_key(k))
+        .map(|k| storage::split_crate_key(k).0.to_string())
+        .collect();
+
+    println!("Checking {} crate(s) for updates...", names.len());
+    let latest: Vec<(String, Result<String>)> = names
+        .par_iter()
+        .map(|name| (name.clone(), fetcher.get_latest_version(name)))
+        .collect();
+
+    let mut outdated = Vec::new();
+    for (name, result) in latest {
+        match result {
+            Ok(version) => {
+                fetcher.remember_latest_version(&db, &name, &version)?;
+                if !indexed.contains(&format!("{}-{}", name, version)) {
+                    println!("  {} -> {}", name, version);
+                    outdated.push((name, Some(version)));
+                }
+            }
+            Err(e) => eprintln!("Warning: Could not check {}: {}", name, e),
+        }
+    }
+
+    if outdated.is_empty() {
+        println!("All crates are up to date.");
+        return Ok(());
+    }
+
+    let count = outdated.len();
+    let failures = fetch_crates(&db, outdated, 0, true)?.failures;
+    println!("\nDone! Updated {} crate(s).", count.saturating_sub(failures.len()));
+    print_fetch_failures(&failures);
+    Ok(())
+}
+
+fn cmd_reindex(all: bool) -> Result<()> {
+    let db = Database::open()?;
+    let keys = if all { db.local_crate_keys()? } else { db.stale_crate_keys()? };
+
+    if keys.is_empty() {
+        println!("All crates are indexed with the current format.");
+        return Ok(());
+    }
+
+    println!("Re-indexing {} crate(s)...", keys.len());
+    let mut failed = Vec::new();
+    for key in &keys {
+        println!("  {}", key);
+        if let Err(e) = indexer::reindex_crate(&db, key) {
+            failed.push((key, e));
+        }
+    }
+
+    println!("\nDone! Re-indexed {} crate(s).", keys.len() - failed.len());
+    if !failed.is_empty() {
+        println!("\nFailed ({}):", failed.len());
+        for (key, e) in &failed {
+            println!("  {}: {:#}", key, e);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_list() -> Result<()> {
+    let db = Database::open()?;
+    let crates = db.list_crates()?;
+    if crates.is_empty() {
+        println!("No crates indexed.");
+        return Ok(());
+    }
+
+    let sizes: Vec<u64> = crates.par_iter().map(|c| dir_size(&c.path)).collect();
+    let key_width = crates.iter().map(|c| c.key.len()).max().unwrap_or(0).max(5);
+
+    println!("{:<key_width$}  {:>9}  {:>6}  {:<10}  FETCHED", "CRATE", "SIZE", "ITEMS", "EMBEDDINGS");
+    for (c, size) in crates.iter().zip(&sizes) {
+        // Like `gc`, crates whose files' contents are in the index aren't missing anything
+        let size = if c.path.exists() {
+            format_size(*size)
+        } else if c.path.as_os_str().is_empty() || db.has_stored_contents(&c.key)? {
+            "-".to_string()
+        } else {
+            "missing".to_string()
+        };
+        let fetched = c.fetched_at.map(format_date).unwrap_or_else(|| "-".to_string());
+        let embedded = if c.has_embeddings { "yes" } else { "no" };
+        let shared = if c.shared { "  (shared)" } else { "" };
+        println!("{:<key_width$}  {:>9}  {:>6}  {:<10}  {}{}", c.key, size, c.item_count, embedded, fetched, shared);
+    }
+    println!("\n{} crate(s), {} on disk", crates.len(), format_size(sizes.iter().sum()));
+    Ok(())
+}
+
+fn cmd_remove(keys: &[String]) -> Result<()> {
+    let db = Database::open()?;
+    for key in keys {
+        if db.get_crate_id(key)?.is_none() {
+            if db.list_crate_keys()?.contains(key) {
+                anyhow::bail!("Crate {} is in a read-only shared index and can't be removed", key);
+            }
+            anyhow::bail!("Crate {} is not indexed (run `crate-indexer list` to see crate keys).{}", key, fuzzy::crate_hint(&db, key));
+        }
+    }
+    for key in keys {
+        remove_crate(&db, key)?;
+        println!("Removed {}", key);
+    }
+    Ok(())
+}
+
+fn cmd_prune(keep_latest: usize, dry_run: bool) -> Result<()> {
+    if keep_latest == 0 {
+        anyhow::bail!("--keep-latest must be at least 1");
+    }
+    let db = Database::open()?;
+
+    let mut versions: HashMap<String, Vec<(semver::Version, String)>> = HashMap::new();
+    for key in db.local_crate_keys()? {
+        if git::is_git_key(&key) {
+            continue;
+        }
+        if let (name, Some(version)) = storage::split_crate_key(&key) {
+            versions.entry(name.to_string()).or_default().push((version, key));
+        }
+    }
+
+    let mut to_remove: Vec<String> = versions
+        .into_values()
+        .flat_map(|mut v| {
+            v.sort_by(|a, b| b.0.cmp(&a.0));
+            v.into_iter().skip(keep_latest).map(|(_, key)| key)
+        })
+        .collect();
+    to_remove.sort();
+
+    if to_remove.is_empty() {
+        println!("Nothing to prune.");
+        return Ok(());
+    }
+
+    for key in &to_remove {
+        if dry_run {
+            println!("Would remove {}", key);
+        } else {
+            remove_crate(&db, key)?;
+            println!("Removed {}", key);
+        }
+    }
+    if !dry_run {
+        println!("\nPruned {} crate(s).", to_remove.len());
+    }
+    Ok(())
+}
+
+fn cmd_gc() -> Result<()> {
+    let db = Database::open()?;
+    let crates: Vec<_> = db.list_crates()?.into_iter().filter(|c| !c.shared).collect();
+    let mut freed = 0;
+
+    // Crate rows whose sources are gone can't be read or re-indexed, unless the index
+    // holds the files' contents (crates imported without sources have no path at all)
+    let mut missing = 0;
+    for c in crates.iter().filter(|c| !c.path.exists()) {
+        if c.path.as_os_str().is_empty() || db.has_stored_contents(&c.key)? {
+            continue;
+        }
+        db.remove_crate(&c.key)?;
+        println!("Removed {} (sources missing)", c.key);
+        missing += 1;
+    }
+
+    // Directories no indexed crate points at (interrupted fetches, removed crates)
+    let known: HashSet<&std::path::Path> = crates.iter().map(|c| c.path.as_path()).collect();
+    let mut orphaned = 0;
+    if let Ok(entries) = std::fs::read_dir(storage::crates_dir()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if known.contains(path.as_path()) {
+                continue;
+            }
+            // Skip directories a fetch is still extracting or has just extracted and
+            // may still be indexing
+            let key = entry.file_name().to_string_lossy().into_owned();
+            if storage::crate_lock_age(&key).is_some_and(|age| age < GC_GRACE_PERIOD) {
+                continue;
+            }
+            let Some(_lock) = storage::try_lock_crate_path(&key)? else {
+                continue;
+            };
+            if db.get_crate_id(&key)?.is_some() {
+                continue;
+            }
+            freed += dir_size(&path);
+            remove_path(&path)?;
+            println!("Deleted {}", path.display());
+            orphaned += 1;
+        }
+    }
+
+    // Git checkouts are only needed while copying a crate out; the mirrors are kept.
+    // Like crate directories, ones a `fetch --git` may still be using are skipped.
+    if let Ok(entries) = std::fs::read_dir(git::checkouts_dir()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(commit) = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()) else {
+                continue;
+            };
+            let key = git::checkout_lock_key(&commit);
+            if storage::crate_lock_age(&key).is_some_and(|age| age < GC_GRACE_PERIOD) {
+                continue;
+            }
+            let Some(_lock) = storage::try_lock_crate_path(&key)? else {
+                continue;
+            };
+            freed += dir_size(&path);
+            remove_path(&path)?;
+        }
+    }
+
+    let rows = db.delete_orphaned_rows()?;
+    db.compact()?;
+
+    println!(
+        "\nRemoved {} crate(s) with missing sources, {} orphaned director{} and {} orphaned row(s); freed {}.",
+        missing,
+        orphaned,
+        if orphaned == 1 { "y" } else { "ies" },
+        rows,
+        format_size(freed)
+    );
+    Ok(())
+}
+
+fn cmd_export(crates: &[String], all: bool, output: &std::path::Path, sources: bool) -> Result<()> {
+    let db = Database::open()?;
+    let keys = if all {
+        let mut keys = db.local_crate_keys()?;
+        keys.sort();
+        keys
+    } else {
+        crates
+            .iter()
+            .map(|name| {
+                db.find_crate_key(name)?
+                    .ok_or_else(|| anyhow::anyhow!("Crate {} is not indexed.{}", name, fuzzy::crate_hint(&db, name)))
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+    if keys.is_empty() {
+        anyhow::bail!("No crates to export");
+    }
+
+    println!("Exporting {} crate(s){}...", keys.len(), if sources { " with sources" } else { "" });
+    bundle::export(&db, &keys, output, sources)?;
+    let size = std::fs::metadata(output).map(|m| m.len()).unwrap_or(0);
+    println!("Wrote {} ({})", output.display(), format_size(size));
+    Ok(())
+}
+
+fn cmd_import(bundle_path: &std::path::Path, overwrite: bool) -> Result<()> {
+    let db = Database::open()?;
+    let outcome = bundle::import(&db, bundle_path, overwrite)?;
+
+    for key in &outcome.imported {
+        println!("Imported {}", key);
+    }
+    if !outcome.skipped.is_empty() {
+        println!("\nSkipped {} crate(s) already indexed (use --overwrite to replace them):", outcome.skipped.len());
+        for key in &outcome.skipped {
+            println!("  {}", key);
+        }
+    }
+    if !outcome.no_sources.is_empty() {
+        println!(
+            "\nThe bundle has no sources for {} crate(s), so `read` and `search` won't work for them (export with --sources to include them):",
+            outcome.no_sources.len()
+        );
+        for key in &outcome.no_sources {
+            println!("  {}", key);
+        }
+    }
+    println!("\nImported {} crate(s).", outcome.imported.len());
+    Ok(())
+}
+
+/// Remove a crate from the index and delete its sources if they live in the crates directory
+fn remove_crate(db: &Database, key: &str) -> Result<()> {
+    // Wait out a fetch extracting into the same directory
+    let _lock = storage::lock_crate_path(key)?;
+    let path = db.get_crate_path(key)?;
+    db.remove_crate(key)?;
+    if let Some(path) = path.filter(|p| p.starts_with(storage::crates_dir()) && p.exists()) {
+        remove_path(&path)?;
+    }
+    Ok(())
+}
+
+fn remove_path(path: &std::path::Path) -> Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+    .with_context(|| format!("Failed to delete {}", path.display()))
+}
+
+fn dir_size(path: &std::path::Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Format a Unix timestamp as a UTC `YYYY-MM-DD` date
+fn format_date(secs: i64) -> String {
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let z = secs.div_euclid(86_400) + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
 fn cmd_readme(crate_name: &str) -> Result<()> {
     let db = Database::open()?;
     let crate_key = find_crate_key(&db, crate_name)?;
-    let crate_path = db.get_crate_path(&crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found for '{}'", crate_key))?;
 
     // Look for README files in order of preference
     let readme_names = [
@@ -778,9 +1788,7 @@ fn cmd_readme(crate_name: &str) -> Result<()> {
     ];
 
     for name in &readme_names {
-        let readme_path = crate_path.join(name);
-        if readme_path.exists() {
-            let content = std::fs::read_to_string(&readme_path)?;
+        if let Some(content) = db.read_file(&crate_key, name)? {
             println!("── {} ({}) ──\n", crate_key, name);
             println!("{}", content);
             return Ok(());
@@ -790,11 +1798,47 @@ fn cmd_readme(crate_name: &str) -> Result<()> {
     anyhow::bail!("No README found in {}", crate_key)
 }
 
+fn cmd_ls(crate_name: &str, dir: Option<&str>) -> Result<()> {
+    let db = Database::open()?;
+    let crate_key = find_crate_key(&db, crate_name)?;
+    let prefix = dir.map(|d| format!("{}/", d.trim_end_matches('/')));
+    let files: Vec<_> = db
+        .list_files(&crate_key)?
+        .into_iter()
+        .filter(|f| prefix.as_ref().is_none_or(|p| f.path.starts_with(p.as_str())))
+        .collect();
+
+    if files.is_empty() {
+        match dir {
+            Some(dir) => println!("No files under '{}' in {}", dir, crate_key),
+            None => println!("No files recorded for {}", crate_key),
+        }
+        return Ok(());
+    }
+
+    let path_width = files.iter().map(|f| f.path.len()).max().unwrap_or(0);
+    println!("── {} ({} files) ──\n", crate_key, files.len());
+    for f in &files {
+        let row = format!(
+            "  {:<path_width$}  {:>9}  {:>6}  {:<10}  {}",
+            f.path,
+            format_size(f.size),
+            f.lines,
+            f.language.as_deref().unwrap_or("-"),
+            f.target.as_deref().unwrap_or(""),
+        );
+        println!("{}", row.trim_end());
+    }
+
+    let total: u64 = files.iter().map(|f| f.size).sum();
+    let lines: usize = files.iter().map(|f| f.lines).sum();
+    println!("\n{} file(s), {}, {} lines", files.len(), format_size(total), lines);
+    Ok(())
+}
+
 fn cmd_read(crate_name: &str, file_path: &str, start: Option<usize>, end: Option<usize>) -> Result<()> {
     let db = Database::open()?;
     let crate_key = find_crate_key(&db, crate_name)?;
-    let crate_path = db.get_crate_path(&crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found for '{}'", crate_key))?;
 
     // Security: prevent path traversal attacks
     let file_path = std::path::Path::new(file_path);
@@ -810,36 +1854,28 @@ fn cmd_read(crate_name: &str, file_path: &str, start: Option<usize>, end: Option
         }
     }
 
-    let full_path = crate_path.join(file_path);
-
-    // Double-check: ensure resolved path is within crate directory
-    let canonical_crate = crate_path.canonicalize().unwrap_or_else(|_| crate_path.clone());
-    if let Ok(canonical_file) = full_path.canonicalize() {
-        if !canonical_file.starts_with(&canonical_crate) {
-            anyhow::bail!("Invalid path: path escapes crate directory");
+    // Double-check: ensure a file read from disk resolves within the crate directory
+    if let Some(crate_path) = db.get_crate_path(&crate_key)? {
+        let canonical_crate = crate_path.canonicalize().unwrap_or_else(|_| crate_path.clone());
+        if let Ok(canonical_file) = crate_path.join(file_path).canonicalize() {
+            if !canonical_file.starts_with(&canonical_crate) {
+                anyhow::bail!("Invalid path: path escapes crate directory");
+            }
         }
     }
 
-    if !full_path.exists() {
+    let Some(content) = db.read_file(&crate_key, &file_path.to_string_lossy())? else {
         // List available files if the requested one doesn't exist
         println!("File '{}' not found in {}", file_path.display(), crate_key);
         println!("\nAvailable files:");
-        for entry in walkdir::WalkDir::new(&crate_path)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-            .take(20)
-        {
-            if let Ok(rel) = entry.path().strip_prefix(&crate_path) {
-                println!("  {}", rel.display());
-            }
+        for file in db.list_files(&crate_key)?.iter().take(20) {
+            println!("  {}", file.path);
         }
         anyhow::bail!("File not found");
-    }
+    };
 
     const MAX_DEFAULT_LINES: usize = 500;
 
-    let content = std::fs::read_to_string(&full_path)?;
     let lines: Vec<&str> = content.lines().collect();
     let total_lines = lines.len();
 
@@ -879,14 +1915,14 @@ fn cmd_read(crate_name: &str, file_path: &str, start: Option<usize>, end: Option
 }
 
 fn find_crate_key(db: &Database, name: &str) -> Result<String> {
+    let key = resolve_crate_key(db, name)?;
+    indexer::reindex_if_stale(db, &key)?;
+    Ok(key)
+}
+
+fn resolve_crate_key(db: &Database, name: &str) -> Result<String> {
     // Check if user specified a version (e.g., "anyhow-1.0.100")
-    // Version must start with digit AND contain a dot (to avoid matching "crate-2fast")
-    let user_specified_version = name.contains('-') && name.split('-').next_back()
-        .map(|s| {
-            let starts_with_digit = s.chars().next().map(|c| c.is_ascii_digit()).unwrap_or(false);
-            starts_with_digit && s.contains('.')
-        })
-        .unwrap_or(false);
+    let user_specified_version = storage::split_crate_key(name).1.is_some();
 
     match db.find_crate_key(name)? {
         Some(key) => {
@@ -895,18 +1931,27 @@ fn find_crate_key(db: &Database, name: &str) -> Result<String> {
                 return Ok(key);
             }
 
+            // Offline, or pinned to a git commit: never check crates.io for updates
+            if config::get().offline || git::is_git_key(&key) {
+                return Ok(key);
+            }
+
             // Check if there's a newer version available
             let fetcher = Fetcher::new()?;
-            let crate_name = extract_crate_name(&key);
+            let crate_name = storage::split_crate_key(&key).0.to_string();
 
-            match fetcher.get_latest_version(&crate_name) {
+            match fetcher.latest_version_cached(db, &crate_name) {
                 Ok(latest_version) => {
                     let latest_key = format!("{}-{}", crate_name, latest_version);
                     if latest_key != key {
                         // Newer version available
                         if db.find_crate_key(&latest_key)?.is_none() {
                             println!("Newer version available: {} -> {}. Fetching...", key, latest_key);
-                            fetch_single_crate(db, &crate_name, Some(&latest_version))?;
+                            let report = fetch_single_crate(db, &crate_name, Some(&latest_version), true)?;
+                            print_fetch_failures(&report.failures);
+                            if db.find_crate_key(&latest_key)?.is_none() {
+                                return Ok(key); // Update failed, keep using the indexed version
+                            }
                         }
                         Ok(latest_key)
                     } else {
@@ -919,141 +1964,26 @@ fn find_crate_key(db: &Database, name: &str) -> Result<String> {
         None => {
             // Auto-fetch the crate if not found
             println!("Crate '{}' not indexed. Fetching...", name);
-            fetch_single_crate(db, name, None)?;
+            let report = fetch_single_crate(db, name, None, true)
+                .with_context(|| format!("Failed to fetch crate '{}'.{}", name, fuzzy::crate_hint(db, name)))?;
+            print_fetch_failures(&report.failures);
 
             // Try again after fetching
             db.find_crate_key(name)?
-                .ok_or_else(|| anyhow::anyhow!("Failed to fetch crate '{}'", name))
+                .ok_or_else(|| anyhow::anyhow!("Failed to fetch crate '{}'.{}", name, fuzzy::crate_hint(db, name)))
         }
     }
 }
 
-fn extract_crate_name(key: &str) -> String {
-    // Key format: "crate-name-1.2.3"
-    // We need to extract "crate-name" (handle crates with hyphens in names)
-    let parts: Vec<&str> = key.rsplitn(2, '-').collect();
-    if parts.len() == 2 {
-        // Check if the last part looks like a semver version (e.g., "1.2.3")
-        // Must start with digit and contain at least one '.'
-        let potential_version = parts[0];
-        let starts_with_digit = potential_version.chars().next().map(|c| c.is_ascii_digit()).unwrap_or(false);
-        let contains_dot = potential_version.contains('.');
-        if starts_with_digit && contains_dot {
-            return parts[1].to_string();
-        }
+fn print_fetch_failures(failures: &[FetchFailure]) {
+    if failures.is_empty() {
+        return;
     }
-    key.to_string()
-}
-
-fn fetch_single_crate(db: &Database, name: &str, version: Option<&str>) -> Result<()> {
-    let fetcher = Fetcher::new()?;
-    let mut fetched: HashSet<String> = db.list_crate_keys()?.into_iter().collect();
-    let mut queued: HashSet<String> = HashSet::new(); // Track crates already queued
-    let mut to_fetch: Vec<(String, Option<String>)> = vec![(name.to_string(), version.map(String::from))];
-    queued.insert(name.to_string());
-
-    while !to_fetch.is_empty() {
-        // Take current batch
-        let batch: Vec<_> = std::mem::take(&mut to_fetch);
-
-        // Resolve versions in parallel
-        if batch.len() > 1 {
-            println!("Resolving {} crate(s)...", batch.len());
-        }
-        let resolved: Vec<(String, String)> = batch
-            .par_iter()
-            .filter_map(|(crate_name, ver)| {
-                let version = match ver {
-                    Some(v) => v.clone(),
-                    None => {
-                        match fetcher.get_latest_version(crate_name) {
-                            Ok(v) => v,
-                            Err(e) => {
-                                eprintln!("Warning: Could not fetch {}: {}", crate_name, e);
-                                return None;
-                            }
-                        }
-                    }
-                };
-                let key = format!("{}-{}", crate_name, version);
-                if fetched.contains(&key) {
-                    None
-                } else {
-                    Some((crate_name.clone(), version))
-                }
-            })
-            .collect();
-
-        if resolved.is_empty() {
-            continue;
-        }
-
-        // Deduplicate resolved crates (same crate could be queued from multiple sources)
-        let resolved: Vec<_> = resolved
-            .into_iter()
-            .collect::<HashSet<_>>()
-            .into_iter()
-            .collect();
-
-        // Download and index in parallel
-        if resolved.len() > 1 {
-            println!("Downloading and indexing {} crate(s) in parallel...", resolved.len());
-        }
-        let results: Vec<_> = resolved
-            .par_iter()
-            .filter_map(|(crate_name, version)| {
-                let key = format!("{}-{}", crate_name, version);
-
-                let crate_path = match fetcher.fetch_crate(crate_name, version) {
-                    Ok(p) => p,
-                    Err(e) => {
-                        eprintln!("Warning: Could not download {}: {}", key, e);
-                        return None;
-                    }
-                };
-
-                println!("Indexing {}...", key);
-                match index_crate(&crate_path, &key) {
-                    Ok(result) => {
-                        println!("  {} fns, {} structs, {} enums, {} traits, {} macros, {} types, {} consts, {} impls",
-                            result.items.functions.len(),
-                            result.items.structs.len(),
-                            result.items.enums.len(),
-                            result.items.traits.len(),
-                            result.items.macros.len(),
-                            result.items.type_aliases.len(),
-                            result.items.constants.len(),
-                            result.items.impls.len());
-                        Some((key, crate_path, result))
-                    }
-                    Err(e) => {
-                        eprintln!("Warning: Failed to index {}: {}", key, e);
-                        None
-                    }
-                }
-            })
-            .collect();
-
-        // Store results and collect re-exports (sequential for DB writes)
-        for (key, crate_path, result) in results {
-            if !result.reexported_crates.is_empty() {
-                println!("  {} re-exports: {:?}", key, result.reexported_crates);
-
-                for reexport in &result.reexported_crates {
-                    let already_have = fetched.iter().any(|k| k.starts_with(&format!("{}-", reexport)));
-                    if !already_have && !queued.contains(reexport) {
-                        queued.insert(reexport.clone());
-                        to_fetch.push((reexport.clone(), None));
-                    }
-                }
-            }
-
-            db.add_crate(&key, &crate_path, &result.items, &result.reexported_crates)?;
-            fetched.insert(key);
-        }
+    eprintln!("\nFailed to fetch {} crate(s):", failures.len());
+    for f in failures {
+        eprintln!("  {}: {:#}", f.crate_name, f.error);
     }
-
-    Ok(())
+    eprintln!("Re-run the command to retry; crates already indexed are skipped.");
 }
 
 fn find_crate_keys_with_reexports(db: &Database, name: &str) -> Result<Vec<String>> {
@@ -1076,6 +2006,7 @@ fn find_crate_keys_with_reexports(db: &Database, name: &str) -> Result<Vec<Strin
             // Find the actual indexed key for this reexport
             if let Some(reexport_key) = db.find_crate_key(&reexport)? {
                 if !seen.contains(&reexport_key) {
+                    indexer::reindex_if_stale(db, &reexport_key)?;
                     seen.insert(reexport_key.clone());
                     keys.push(reexport_key.clone());
                     to_process.push((reexport_key, depth + 1));
@@ -1092,6 +2023,9 @@ fn find_crate_keys_with_reexports(db: &Database, name: &str) -> Result<Vec<Strin
 }
 
 async fn cmd_semantic_search(crate_name: &str, query: &str, limit: usize) -> Result<()> {
+    if limit == 0 {
+        anyhow::bail!("--limit must be at least 1");
+    }
     // Run blocking operations (database + potential fetcher) in spawn_blocking
     // Get all matching crate keys (handles multiple versions) and their re-exports
     let crate_name_owned = crate_name.to_string();
@@ -1126,18 +2060,9 @@ async fn cmd_semantic_search(crate_name: &str, query: &str, limit: usize) -> Res
         return Ok(());
     }
 
-    // Generate embeddings for all crates that need them
+    // Embed items that are new or changed since the last search
     for key in &all_crate_keys {
-        let key_clone = key.clone();
-        let has_embeddings = tokio::task::spawn_blocking(move || {
-            let db = Database::open()?;
-            db.has_embeddings(&key_clone)
-        }).await??;
-
-        if !has_embeddings {
-            println!("Generating embeddings for {}...", key);
-            generate_embeddings_async(key).await?;
-        }
+        generate_embeddings_async(key).await?;
     }
 
     // Initialize embedding manager for query
@@ -1213,75 +2138,28 @@ async fn cmd_semantic_search(crate_name: &str, query: &str, limit: usize) -> Res
 async fn cmd_embed(crate_name: &str) -> Result<()> {
     // Run blocking operations in spawn_blocking
     let crate_name = crate_name.to_string();
-    let (crate_key, has_embeddings) = tokio::task::spawn_blocking(move || {
+    let crate_key = tokio::task::spawn_blocking(move || {
         let db = Database::open()?;
-        let crate_key = find_crate_key(&db, &crate_name)?;
-        let has_embeddings = db.has_embeddings(&crate_key)?;
-        Ok::<_, anyhow::Error>((crate_key, has_embeddings))
+        find_crate_key(&db, &crate_name)
     }).await??;
 
-    if has_embeddings {
-        println!("Embeddings already exist for {}. Re-generating...", crate_key);
+    if generate_embeddings_async(&crate_key).await? == 0 {
+        println!("Embeddings for {} are up to date.", crate_key);
+    } else {
+        println!("Done!");
     }
 
-    generate_embeddings_async(&crate_key).await?;
-    println!("Done!");
-
     Ok(())
 }
 
-async fn generate_embeddings_async(crate_key: &str) -> Result<()> {
-    println!("Initializing embedding model...");
-    let embedder = EmbeddingManager::new()?;
-
-    // Phase 1: Collect all items from database (blocking)
+/// Embed the items of a crate that are new or changed since embeddings were last
+/// generated. Returns how many were embedded.
+async fn generate_embeddings_async(crate_key: &str) -> Result<usize> {
+    // Phase 1: Collect items without an up-to-date embedding (blocking)
     let crate_key_owned = crate_key.to_string();
     let (items_to_embed, crate_id) = tokio::task::spawn_blocking(move || {
         let db = Database::open()?;
-        let mut items: Vec<(String, String, String)> = Vec::new(); // (id, type, text)
-
-        // Functions
-        for func in db.get_functions(&crate_key_owned)? {
-            let text = format_function_for_embedding(&func);
-            items.push((func.id, "function".to_string(), text));
-        }
-
-        // Structs
-        for s in db.get_structs(&crate_key_owned)? {
-            let text = format_struct_for_embedding(&s);
-            items.push((s.id, "struct".to_string(), text));
-        }
-
-        // Enums
-        for e in db.get_enums(&crate_key_owned)? {
-            let text = format_enum_for_embedding(&e);
-            items.push((e.id, "enum".to_string(), text));
-        }
-
-        // Traits
-        for t in db.get_traits(&crate_key_owned)? {
-            let text = format_trait_for_embedding(&t);
-            items.push((t.id, "trait".to_string(), text));
-        }
-
-        // Macros
-        for m in db.get_macros(&crate_key_owned)? {
-            let text = format_macro_for_embedding(&m);
-            items.push((m.id, "macro".to_string(), text));
-        }
-
-        // Type aliases
-        for t in db.get_type_aliases(&crate_key_owned)? {
-            let text = format_type_alias_for_embedding(&t);
-            items.push((t.id, "type_alias".to_string(), text));
-        }
-
-        // Constants
-        for c in db.get_constants(&crate_key_owned)? {
-            let text = format_constant_for_embedding(&c);
-            items.push((c.id, "constant".to_string(), text));
-        }
-
+        let items = embeddings::pending_items(&db, &crate_key_owned)?;
         let crate_id = db.get_crate_id(&crate_key_owned)?
             .ok_or_else(|| anyhow::anyhow!("Crate not found"))?;
 
@@ -1289,11 +2167,12 @@ async fn generate_embeddings_async(crate_key: &str) -> Result<()> {
     }).await??;
 
     if items_to_embed.is_empty() {
-        println!("No items to embed.");
-        return Ok(());
+        return Ok(0);
     }
 
-    println!("Embedding {} items...", items_to_embed.len());
+    println!("Initializing embedding model...");
+    let embedder = EmbeddingManager::new()?;
+    println!("Embedding {} new or changed items of {}...", items_to_embed.len(), crate_key);
 
     // Phase 2: Generate embeddings (async)
     let texts: Vec<String> = items_to_embed.iter().map(|(_, _, t)| t.clone()).collect();
@@ -1319,80 +2198,7 @@ async fn generate_embeddings_async(crate_key: &str) -> Result<()> {
 
     println!("Stored {} embeddings.", count);
 
-    Ok(())
-}
-
-fn format_function_for_embedding(func: &storage::FunctionInfo) -> String {
-    let mut text = func.signature.clone();
-    if let Some(docs) = &func.docs {
-        text.push_str(". ");
-        text.push_str(docs);
-    }
-    text
-}
-
-fn format_struct_for_embedding(s: &storage::StructInfo) -> String {
-    let mut text = format!("struct {}", s.name);
-    if !s.fields.is_empty() {
-        let field_names: Vec<&str> = s.fields.iter().map(|f| f.name.as_str()).collect();
-        text.push_str(" with fields: ");
-        text.push_str(&field_names.join(", "));
-    }
-    if let Some(docs) = &s.docs {
-        text.push_str(". ");
-        text.push_str(docs);
-    }
-    text
-}
-
-fn format_enum_for_embedding(e: &storage::EnumInfo) -> String {
-    let mut text = format!("enum {}", e.name);
-    if !e.variants.is_empty() {
-        let variant_names: Vec<&str> = e.variants.iter().map(|v| v.name.as_str()).collect();
-        text.push_str(" with variants: ");
-        text.push_str(&variant_names.join(", "));
-    }
-    if let Some(docs) = &e.docs {
-        text.push_str(". ");
-        text.push_str(docs);
-    }
-    text
-}
-
-fn format_trait_for_embedding(t: &storage::TraitInfo) -> String {
-    let mut text = format!("trait {}", t.name);
-    if let Some(docs) = &t.docs {
-        text.push_str(". ");
-        text.push_str(docs);
-    }
-    text
-}
-
-fn format_macro_for_embedding(m: &storage::MacroInfo) -> String {
-    let mut text = format!("macro {}!", m.name);
-    if let Some(docs) = &m.docs {
-        text.push_str(". ");
-        text.push_str(docs);
-    }
-    text
-}
-
-fn format_type_alias_for_embedding(t: &storage::TypeAliasInfo) -> String {
-    let mut text = format!("type {} = {}", t.name, t.type_str);
-    if let Some(docs) = &t.docs {
-        text.push_str(". ");
-        text.push_str(docs);
-    }
-    text
-}
-
-fn format_constant_for_embedding(c: &storage::ConstantInfo) -> String {
-    let mut text = format!("{} {}: {}", c.kind, c.name, c.type_str);
-    if let Some(docs) = &c.docs {
-        text.push_str(". ");
-        text.push_str(docs);
-    }
-    text
+    Ok(count)
 }
 
 fn truncate_str(s: &str, max_len: usize) -> String {
@@ -1407,14 +2213,22 @@ fn truncate_str(s: &str, max_len: usize) -> String {
     }
 }
 
-fn print_summary(item_type: &str, total: usize, pattern: Option<&str>, crate_count: usize) {
+fn print_summary(
+    db: &Database,
+    crate_keys: &[String],
+    kind: &str,
+    item_type: &str,
+    total: usize,
+    pattern: Option<&str>,
+) -> Result<()> {
     if total == 0 {
         if let Some(p) = pattern {
-            println!("No {} matching '{}'", item_type, p);
+            println!("No {} matching '{}'.{}", item_type, p, fuzzy::item_hint(db, crate_keys, Some(kind), p)?);
         } else {
             println!("No {} found", item_type);
         }
     } else {
-        println!("Total: {} {} across {} crate(s)", total, item_type, crate_count);
+        println!("Total: {} {} across {} crate(s)", total, item_type, crate_keys.len());
     }
+    Ok(())
 }
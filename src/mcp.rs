@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use rmcp::{
     handler::server::router::tool::ToolRouter,
     handler::server::tool::Parameters,
@@ -12,11 +12,15 @@ use std::borrow::Cow;
 use std::collections::HashSet;
 use std::future::Future; // Required by #[tool] macro
 
-use crate::embeddings::{embedding_to_bytes, EmbeddingManager};
-use crate::fetcher::Fetcher;
-use crate::indexer::index_crate;
-use crate::search::{build_regex, search_functions, search_regex};
-use crate::storage::Database;
+use crate::embeddings::{self, embedding_to_bytes, EmbeddingManager};
+use crate::fetch;
+use crate::fuzzy;
+use crate::git::{self, GitReference};
+use crate::indexer;
+use crate::search::{self, build_regex, search_functions, search_regex, ItemFilter, SearchOptions};
+use crate::signature::TypeQuery;
+use crate::storage::{self, Database};
+use crate::structural::Pattern;
 
 #[derive(Debug, Clone)]
 pub struct CrateIndexerServer {
@@ -30,27 +34,115 @@ pub struct FetchCrateRequest {
     pub crate_name: String,
     #[schemars(description = "Specific version (optional, defaults to latest)")]
     pub version: Option<String>,
+    #[schemars(description = "Git repository URL to fetch from instead of crates.io (optional)")]
+    pub git: Option<String>,
+    #[schemars(description = "Git commit to check out (optional, git only)")]
+    pub rev: Option<String>,
+    #[schemars(description = "Git branch to check out (optional, git only)")]
+    pub branch: Option<String>,
+    #[schemars(description = "Git tag to check out (optional, git only)")]
+    pub tag: Option<String>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct SearchCrateRequest {
-    #[schemars(description = "Name of the crate to search")]
-    pub crate_name: String,
-    #[schemars(description = "Regex pattern to search for")]
+    #[schemars(description = "Name of the crate to search (omit when using crates or all)")]
+    pub crate_name: Option<String>,
+    #[schemars(description = "Regex pattern to search for (a literal string with fixed_string)")]
+    pub pattern: String,
+    #[schemars(description = "Search these crates instead of a single one (optional)")]
+    pub crates: Option<Vec<String>>,
+    #[schemars(description = "Search every indexed crate, newest version of each (optional)")]
+    pub all: Option<bool>,
+    #[schemars(description = "Let matches span lines; each match then reports its line:column range (optional)")]
+    pub multiline: Option<bool>,
+    #[schemars(description = "Treat the pattern as a literal string instead of a regex (optional)")]
+    pub fixed_string: Option<bool>,
+    #[schemars(description = "Case-insensitive matching (optional)")]
+    pub ignore_case: Option<bool>,
+    #[schemars(description = "Only match whole words (optional)")]
+    pub whole_word: Option<bool>,
+    #[schemars(description = "Lines of context before and after each match (optional)")]
+    pub context: Option<usize>,
+    #[schemars(description = "Lines of context before each match, overrides context (optional)")]
+    pub before_context: Option<usize>,
+    #[schemars(description = "Lines of context after each match, overrides context (optional)")]
+    pub after_context: Option<usize>,
+    #[schemars(description = "Only search files matching one of these globs, e.g. \"src/de/**\" or \"*_test.rs\" (optional)")]
+    pub glob: Option<Vec<String>>,
+    #[schemars(description = "Skip files matching one of these globs (optional)")]
+    pub exclude: Option<Vec<String>>,
+    #[schemars(description = "Stop searching a file after this many matches (optional)")]
+    pub max_count: Option<usize>,
+    #[schemars(description = "Only match in these kinds of text: \"code\", \"comments\", \"strings\" (string and char literals) or \"docs\" (doc comments), e.g. [\"code\"] to skip `unsafe` mentioned in comments (optional, default all)")]
+    pub within: Option<Vec<String>>,
+    #[schemars(description = "Only match inside items of this kind: function, struct, enum, trait, macro, type_alias, constant or impl (optional)")]
+    pub item_kind: Option<String>,
+    #[schemars(description = "Only match inside items whose name matches this regex, or whose self type does for impls (optional)")]
+    pub item_name: Option<String>,
+    #[schemars(description = "Only match inside impls of this trait, e.g. \"Drop\"; with item_kind, inside such items within those impls (optional)")]
+    pub impl_trait: Option<String>,
+    #[schemars(description = "Skip this many matches; pass the next offset from a previous result to page (optional)")]
+    pub offset: Option<usize>,
+    #[schemars(description = "Maximum number of matches to return (default 50)")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct StructuralSearchRequest {
+    #[schemars(description = "Name of the crate to search (omit when using crates or all)")]
+    pub crate_name: Option<String>,
+    #[schemars(description = "Item pattern, e.g. `fn $name(..) -> Result<$_, io::Error>` or `impl From<$t> for $_`. Forms: fn NAME(PARAMS) -> TYPE, impl TRAIT for TYPE, impl TYPE, struct NAME { FIELDS }, enum NAME { VARIANTS }, trait NAME, type NAME = TYPE, const/static NAME: TYPE. `$x` matches one identifier, type or expression (the same one each time it appears), `$_` any one, `..` any tokens; left-out parts match anything")]
     pub pattern: String,
+    #[schemars(description = "Search these crates instead of a single one (optional)")]
+    pub crates: Option<Vec<String>>,
+    #[schemars(description = "Search every indexed crate, newest version of each (optional)")]
+    pub all: Option<bool>,
+    #[schemars(description = "Maximum number of items to return (default 50)")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SearchByTypeRequest {
+    #[schemars(description = "Name of the crate to search (omit when using crates or all)")]
+    pub crate_name: Option<String>,
+    #[schemars(description = "Function type to look for, e.g. `(&str) -> Result<_, _>`, `-> Vec<u8>` (output only) or `(&Path, usize)` (inputs only). `_` matches any type; generics, references and self are matched loosely and parameter order is not required")]
+    pub signature: String,
+    #[schemars(description = "Search these crates instead of a single one (optional)")]
+    pub crates: Option<Vec<String>>,
+    #[schemars(description = "Search every indexed crate, newest version of each (optional)")]
+    pub all: Option<bool>,
+    #[schemars(description = "Maximum number of functions to return (default 20)")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct FindItemsRequest {
+    #[schemars(description = "Words to search for in item names, signatures, fields and docs. `word*` matches a prefix, \"quoted words\" a phrase")]
+    pub query: String,
+    #[schemars(description = "Only search these crates (optional, defaults to all indexed crates)")]
+    pub crates: Option<Vec<String>>,
+    #[schemars(description = "Only return one kind of item: function, struct, enum, trait, macro, type_alias, constant or impl (optional)")]
+    pub kind: Option<String>,
+    #[schemars(description = "Maximum number of results (default 20)")]
+    pub limit: Option<usize>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct ListItemsRequest {
-    #[schemars(description = "Name of the crate")]
-    pub crate_name: String,
+    #[schemars(description = "Name of the crate (omit when using crates or all)")]
+    pub crate_name: Option<String>,
     #[schemars(description = "Optional regex pattern to filter results")]
     pub pattern: Option<String>,
+    #[schemars(description = "List from these crates instead of a single one (optional)")]
+    pub crates: Option<Vec<String>>,
+    #[schemars(description = "List from every indexed crate, newest version of each (optional)")]
+    pub all: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct ShowItemRequest {
-    #[schemars(description = "Item ID (8-character hex)")]
+    #[schemars(description = "Item ID or a unique prefix of it, or a path like serde::de::Deserializer")]
     pub id: String,
 }
 
@@ -66,12 +158,26 @@ pub struct ReadFileRequest {
     pub end_line: Option<usize>,
 }
 
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ListFilesRequest {
+    #[schemars(description = "Name of the crate")]
+    pub crate_name: String,
+    #[schemars(description = "Only list files under this directory (e.g., 'src/de')")]
+    pub dir: Option<String>,
+}
+
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct ReadmeRequest {
     #[schemars(description = "Name of the crate")]
     pub crate_name: String,
 }
 
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct CrateInfoRequest {
+    #[schemars(description = "Name of the crate")]
+    pub crate_name: String,
+}
+
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct SemanticSearchRequest {
     #[schemars(description = "Name of the crate to search")]
@@ -98,13 +204,24 @@ impl CrateIndexerServer {
         }
     }
 
-    #[tool(description = "Fetch and index a crate from crates.io. Automatically fetches re-exported dependencies.")]
+    #[tool(description = "Fetch and index a crate from crates.io or a git repository. Automatically fetches re-exported dependencies.")]
     async fn fetch_crate(
         &self,
         Parameters(req): Parameters<FetchCrateRequest>,
     ) -> Result<CallToolResult, McpError> {
-        let result = tokio::task::spawn_blocking(move || {
-            do_fetch_crate(&req.crate_name, req.version.as_deref())
+        let result = tokio::task::spawn_blocking(move || match &req.git {
+            Some(url) => {
+                let reference = GitReference::from_args(
+                    req.rev.as_deref(),
+                    req.branch.as_deref(),
+                    req.tag.as_deref(),
+                );
+                do_fetch_git_crate(&req.crate_name, url, &reference)
+            }
+            None => {
+                let db = storage::pooled()?;
+                do_fetch_crate(&db, &req.crate_name, req.version.as_deref())
+            }
         })
         .await
         .map_err(|e| make_error(format!("Task error: {}", e)))?
@@ -113,13 +230,83 @@ impl CrateIndexerServer {
         Ok(CallToolResult::success(vec![Content::text(result)]))
     }
 
-    #[tool(description = "Search a crate's source code with a regex pattern")]
+    #[tool(description = "Search a crate's source code with a regex pattern, with optional multi-line or literal matching, context lines, file globs, case-insensitive or whole-word matching, limits to code, comments, strings, docs or the bodies of chosen items (reporting each enclosing item ID), and paging via offset/limit")]
     async fn search_crate(
         &self,
         Parameters(req): Parameters<SearchCrateRequest>,
     ) -> Result<CallToolResult, McpError> {
         let result = tokio::task::spawn_blocking(move || {
-            do_search_crate(&req.crate_name, &req.pattern)
+            let db = storage::pooled()?;
+            let crate_keys = scope_crate_keys(&db, req.crate_name.as_deref(), req.crates.as_deref(), req.all)?;
+            let options = SearchOptions {
+                multiline: req.multiline.unwrap_or(false),
+                fixed_strings: req.fixed_string.unwrap_or(false),
+                ignore_case: req.ignore_case.unwrap_or(false),
+                whole_word: req.whole_word.unwrap_or(false),
+                before_context: req.before_context.or(req.context).unwrap_or(0),
+                after_context: req.after_context.or(req.context).unwrap_or(0),
+                globs: req.glob.unwrap_or_default(),
+                excludes: req.exclude.unwrap_or_default(),
+                max_count: req.max_count,
+                within: req.within.unwrap_or_default().iter().map(|s| s.parse()).collect::<Result<_, String>>().map_err(anyhow::Error::msg)?,
+                items: (req.item_kind.is_some() || req.item_name.is_some() || req.impl_trait.is_some()).then_some(ItemFilter {
+                    kind: req.item_kind,
+                    name: req.item_name,
+                    impl_trait: req.impl_trait,
+                }),
+            };
+            do_search_crate(&db, &crate_keys, &req.pattern, &options, req.offset.unwrap_or(0), req.limit.unwrap_or(50))
+        })
+        .await
+        .map_err(|e| make_error(format!("Task error: {}", e)))?
+        .map_err(|e| make_error(format!("{}", e)))?;
+
+        Ok(CallToolResult::success(vec![Content::text(result)]))
+    }
+
+    #[tool(description = "Find items by their structure rather than text, e.g. functions returning `Result<$_, io::Error>` or impls of `From<$t>` for any type. Returns item IDs (usable with show_item) and line spans.")]
+    async fn structural_search(
+        &self,
+        Parameters(req): Parameters<StructuralSearchRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = tokio::task::spawn_blocking(move || {
+            let db = storage::pooled()?;
+            let pattern = Pattern::parse(&req.pattern)?;
+            let crate_keys = scope_crate_keys(&db, req.crate_name.as_deref(), req.crates.as_deref(), req.all)?;
+            do_structural_search(&db, &crate_keys, &pattern, req.limit.unwrap_or(50))
+        })
+        .await
+        .map_err(|e| make_error(format!("Task error: {}", e)))?
+        .map_err(|e| make_error(format!("{}", e)))?;
+
+        Ok(CallToolResult::success(vec![Content::text(result)]))
+    }
+
+    #[tool(description = "Find functions and methods by type signature (Hoogle-style), ranked by how closely their parameter and return types fit, e.g. `(&str) -> Result<_, _>`. Returns function IDs usable with show_item.")]
+    async fn search_by_type(
+        &self,
+        Parameters(req): Parameters<SearchByTypeRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = tokio::task::spawn_blocking(move || {
+            let db = storage::pooled()?;
+            let query = TypeQuery::parse(&req.signature)?;
+            let crate_keys = scope_crate_keys(&db, req.crate_name.as_deref(), req.crates.as_deref(), req.all)?;
+            do_search_by_type(&db, &crate_keys, &query, req.limit.unwrap_or(20))
+        })
+        .await
+        .map_err(|e| make_error(format!("Task error: {}", e)))?
+        .map_err(|e| make_error(format!("{}", e)))?;
+
+        Ok(CallToolResult::success(vec![Content::text(result)]))
+    }
+
+    #[tool(description = "Full-text search for items (functions, structs, enums, traits, ...) by name, signature, field names and docs across all indexed crates or a chosen set, ranked by relevance. Faster than listing when you don't know which crate or item type to look in.")]
+    async fn find_items(
+        &self,
+        Parameters(req): Parameters<FindItemsRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = tokio::task::spawn_blocking(move || {
+            do_find_items(&req.query, req.crates.as_deref(), req.kind.as_deref(), req.limit.unwrap_or(20))
         })
         .await
         .map_err(|e| make_error(format!("Task error: {}", e)))?
@@ -134,7 +321,9 @@ impl CrateIndexerServer {
         Parameters(req): Parameters<ListItemsRequest>,
     ) -> Result<CallToolResult, McpError> {
         let result = tokio::task::spawn_blocking(move || {
-            do_list_functions(&req.crate_name, req.pattern.as_deref())
+            let db = storage::pooled()?;
+            let crate_keys = scope_crate_keys(&db, req.crate_name.as_deref(), req.crates.as_deref(), req.all)?;
+            do_list_functions(&db, &crate_keys, req.pattern.as_deref())
         })
         .await
         .map_err(|e| make_error(format!("Task error: {}", e)))?
@@ -149,7 +338,9 @@ impl CrateIndexerServer {
         Parameters(req): Parameters<ListItemsRequest>,
     ) -> Result<CallToolResult, McpError> {
         let result = tokio::task::spawn_blocking(move || {
-            do_list_structs(&req.crate_name, req.pattern.as_deref())
+            let db = storage::pooled()?;
+            let crate_keys = scope_crate_keys(&db, req.crate_name.as_deref(), req.crates.as_deref(), req.all)?;
+            do_list_structs(&db, &crate_keys, req.pattern.as_deref())
         })
         .await
         .map_err(|e| make_error(format!("Task error: {}", e)))?
@@ -164,7 +355,9 @@ impl CrateIndexerServer {
         Parameters(req): Parameters<ListItemsRequest>,
     ) -> Result<CallToolResult, McpError> {
         let result = tokio::task::spawn_blocking(move || {
-            do_list_enums(&req.crate_name, req.pattern.as_deref())
+            let db = storage::pooled()?;
+            let crate_keys = scope_crate_keys(&db, req.crate_name.as_deref(), req.crates.as_deref(), req.all)?;
+            do_list_enums(&db, &crate_keys, req.pattern.as_deref())
         })
         .await
         .map_err(|e| make_error(format!("Task error: {}", e)))?
@@ -179,7 +372,9 @@ impl CrateIndexerServer {
         Parameters(req): Parameters<ListItemsRequest>,
     ) -> Result<CallToolResult, McpError> {
         let result = tokio::task::spawn_blocking(move || {
-            do_list_traits(&req.crate_name, req.pattern.as_deref())
+            let db = storage::pooled()?;
+            let crate_keys = scope_crate_keys(&db, req.crate_name.as_deref(), req.crates.as_deref(), req.all)?;
+            do_list_traits(&db, &crate_keys, req.pattern.as_deref())
         })
         .await
         .map_err(|e| make_error(format!("Task error: {}", e)))?
@@ -194,7 +389,9 @@ impl CrateIndexerServer {
         Parameters(req): Parameters<ListItemsRequest>,
     ) -> Result<CallToolResult, McpError> {
         let result = tokio::task::spawn_blocking(move || {
-            do_list_impls(&req.crate_name, req.pattern.as_deref())
+            let db = storage::pooled()?;
+            let crate_keys = scope_crate_keys(&db, req.crate_name.as_deref(), req.crates.as_deref(), req.all)?;
+            do_list_impls(&db, &crate_keys, req.pattern.as_deref())
         })
         .await
         .map_err(|e| make_error(format!("Task error: {}", e)))?
@@ -231,6 +428,19 @@ impl CrateIndexerServer {
         Ok(CallToolResult::success(vec![Content::text(result)]))
     }
 
+    #[tool(description = "List the files of an indexed crate with their size, line count, language and Cargo target (lib, bin, example, test, bench, build)")]
+    async fn list_files(
+        &self,
+        Parameters(req): Parameters<ListFilesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = tokio::task::spawn_blocking(move || do_list_files(&req.crate_name, req.dir.as_deref()))
+            .await
+            .map_err(|e| make_error(format!("Task error: {}", e)))?
+            .map_err(|e| make_error(format!("{}", e)))?;
+
+        Ok(CallToolResult::success(vec![Content::text(result)]))
+    }
+
     #[tool(description = "Get the README of a crate")]
     async fn read_readme(
         &self,
@@ -244,6 +454,19 @@ impl CrateIndexerServer {
         Ok(CallToolResult::success(vec![Content::text(result)]))
     }
 
+    #[tool(description = "Get package metadata of a crate from its Cargo.toml: description, license, repository, edition, MSRV (rust-version), authors, keywords, categories, features and dependency requirements")]
+    async fn crate_info(
+        &self,
+        Parameters(req): Parameters<CrateInfoRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let result = tokio::task::spawn_blocking(move || do_crate_info(&req.crate_name))
+            .await
+            .map_err(|e| make_error(format!("Task error: {}", e)))?
+            .map_err(|e| make_error(format!("{}", e)))?;
+
+        Ok(CallToolResult::success(vec![Content::text(result)]))
+    }
+
     #[tool(description = "Semantic search for code in a crate using natural language. Finds functions, structs, enums, traits, etc. based on meaning, not just keywords. Note: This is significantly slower than search_crate (regex) as it requires generating embeddings. Use search_crate for simple keyword/pattern matching.")]
     async fn semantic_search(
         &self,
@@ -289,124 +512,268 @@ pub async fn run_mcp_server() -> Result<()> {
 fn ensure_crate(db: &Database, name: &str) -> anyhow::Result<String> {
     // For MCP: use what's indexed, only auto-fetch if crate is not found at all.
     // This avoids network calls on every operation.
-    match db.find_crate_key(name)? {
-        Some(key) => Ok(key),
+    let key = match db.find_crate_key(name)? {
+        Some(key) => key,
         None => {
             // Auto-fetch only when crate is not found
-            do_fetch_crate(name, None)?;
+            do_fetch_crate(db, name, None)
+                .with_context(|| format!("Failed to fetch crate '{}'.{}", name, fuzzy::crate_hint(db, name)))?;
             db.find_crate_key(name)?
-                .ok_or_else(|| anyhow::anyhow!("Failed to fetch crate '{}'", name))
+                .ok_or_else(|| anyhow::anyhow!("Failed to fetch crate '{}'.{}", name, fuzzy::crate_hint(db, name)))?
         }
-    }
+    };
+    indexer::reindex_if_stale(db, &key)?;
+    Ok(key)
 }
 
-fn do_fetch_crate(name: &str, version: Option<&str>) -> anyhow::Result<String> {
-    use rayon::prelude::*;
-
-    let db = Database::open()?;
-    let fetcher = Fetcher::new()?;
-    let mut fetched: HashSet<String> = db.list_crate_keys()?.into_iter().collect();
-    let mut queued: HashSet<String> = HashSet::new();
-    let mut to_fetch: Vec<(String, Option<String>)> = vec![(name.to_string(), version.map(String::from))];
-    queued.insert(name.to_string());
+fn do_fetch_crate(db: &Database, name: &str, version: Option<&str>) -> anyhow::Result<String> {
+    let report = fetch::fetch_single_crate(db, name, version, false)?;
 
     let mut output = String::new();
+    for indexed in &report.indexed {
+        output.push_str(&format!(
+            "Indexed {}: {} functions, {} structs, {} enums, {} traits\n",
+            indexed.key, indexed.functions, indexed.structs, indexed.enums, indexed.traits
+        ));
+    }
 
-    while !to_fetch.is_empty() {
-        let batch: Vec<_> = std::mem::take(&mut to_fetch);
-
-        let resolved: Vec<(String, String)> = batch
-            .par_iter()
-            .filter_map(|(crate_name, ver)| {
-                let version = match ver {
-                    Some(v) => v.clone(),
-                    None => fetcher.get_latest_version(crate_name).ok()?,
-                };
-                let key = format!("{}-{}", crate_name, version);
-                if fetched.contains(&key) {
-                    None
-                } else {
-                    Some((crate_name.clone(), version))
-                }
-            })
-            .collect();
+    if !report.failures.is_empty() {
+        output.push_str(&format!("\nFailed to fetch {} crate(s):\n", report.failures.len()));
+        for failure in &report.failures {
+            output.push_str(&format!("  {}: {:#}\n", failure.crate_name, failure.error));
+        }
+        output.push_str("Call fetch_crate again to retry; crates already indexed are skipped.\n");
+    }
+
+    if output.is_empty() {
+        Ok(format!("Crate {} is already indexed", name))
+    } else {
+        Ok(output)
+    }
+}
+
+fn do_fetch_git_crate(name: &str, url: &str, reference: &GitReference) -> anyhow::Result<String> {
+    let db = storage::pooled()?;
+    let git_crate = git::fetch_git_crate(url, reference, name)?;
+    let result = indexer::index_crate_incremental(&db, &git_crate.path, &git_crate.key)?;
+    db.add_crate(&git_crate.key, &git_crate.path, &result.items, &result.reexported_crates)?;
+
+    let mut output = format!(
+        "Indexed {} (commit {}): {} functions, {} structs, {} enums, {} traits\n",
+        git_crate.key,
+        git_crate.commit,
+        result.items.functions.len(),
+        result.items.structs.len(),
+        result.items.enums.len(),
+        result.items.traits.len()
+    );
+    let reused = result.items.files.iter().filter(|f| f.reused).count();
+    if reused > 0 {
+        output.push_str(&format!("({} unchanged file(s) kept from the previous index)\n", reused));
+    }
 
-        if resolved.is_empty() {
-            continue;
+    for reexport in &result.reexported_crates {
+        if db.find_all_crate_keys(reexport)?.is_empty() {
+            output.push_str(&do_fetch_crate(&db, reexport, None)?);
         }
+    }
 
-        let resolved: Vec<_> = resolved.into_iter().collect::<HashSet<_>>().into_iter().collect();
+    Ok(output)
+}
 
-        let results: Vec<_> = resolved
-            .par_iter()
-            .filter_map(|(crate_name, version)| {
-                let key = format!("{}-{}", crate_name, version);
-                let crate_path = fetcher.fetch_crate(crate_name, version).ok()?;
-                let result = index_crate(&crate_path, &key).ok()?;
-                Some((key, crate_path, result))
-            })
-            .collect();
-
-        for (key, crate_path, result) in results {
-            output.push_str(&format!(
-                "Indexed {}: {} functions, {} structs, {} enums, {} traits\n",
-                key,
-                result.items.functions.len(),
-                result.items.structs.len(),
-                result.items.enums.len(),
-                result.items.traits.len()
-            ));
-
-            for reexport in &result.reexported_crates {
-                let already_have = fetched.iter().any(|k| k.starts_with(&format!("{}-", reexport)));
-                if !already_have && !queued.contains(reexport) {
-                    queued.insert(reexport.clone());
-                    to_fetch.push((reexport.clone(), None));
-                }
-            }
+/// Crates an MCP search or listing covers: `crate_name`, the given `crates`, or
+/// with `all` the newest indexed version of every crate
+fn scope_crate_keys(
+    db: &Database,
+    crate_name: Option<&str>,
+    crates: Option<&[String]>,
+    all: Option<bool>,
+) -> anyhow::Result<Vec<String>> {
+    if all == Some(true) {
+        let keys = db.latest_crate_keys()?;
+        indexer::reindex_stale_crates(db, Some(&keys))?;
+        return Ok(keys);
+    }
 
-            db.add_crate(&key, &crate_path, &result.items, &result.reexported_crates)?;
-            fetched.insert(key);
+    let mut keys: Vec<String> = Vec::new();
+    let names = crates.unwrap_or_default().iter().map(String::as_str).chain(crate_name);
+    for name in names {
+        let key = ensure_crate(db, name)?;
+        if !keys.contains(&key) {
+            keys.push(key);
         }
     }
+    if keys.is_empty() {
+        anyhow::bail!("Provide crate_name, crates or all");
+    }
+    Ok(keys)
+}
 
-    if output.is_empty() {
-        Ok(format!("Crate {} is already indexed", name))
-    } else {
-        Ok(output)
+/// Start a crate's section when results span several crates
+fn push_crate_header(output: &mut String, crate_keys: &[String], current: &mut Option<String>, key: &str) {
+    if crate_keys.len() > 1 && current.as_deref() != Some(key) {
+        output.push_str(&format!("── {} ──\n", key));
+        *current = Some(key.to_string());
     }
 }
 
-fn do_search_crate(crate_name: &str, pattern: &str) -> anyhow::Result<String> {
-    let db = Database::open()?;
-    let crate_key = ensure_crate(&db, crate_name)?;
-    let crate_path = db.get_crate_path(&crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found"))?;
+fn do_search_crate(
+    db: &Database,
+    crate_keys: &[String],
+    pattern: &str,
+    options: &SearchOptions,
+    offset: usize,
+    limit: usize,
+) -> anyhow::Result<String> {
+    if limit == 0 {
+        anyhow::bail!("limit must be at least 1");
+    }
+    let mut matches = Vec::new();
+    for crate_key in crate_keys {
+        matches.extend(search_regex(db, crate_key, pattern, options)?.into_iter().map(|m| (crate_key, m)));
+    }
 
-    let matches = search_regex(&crate_path, pattern)?;
+    let start = offset.min(matches.len());
+    let end = start.saturating_add(limit).min(matches.len());
+    let mut output = String::new();
+    let mut current = None;
+    for group in matches[start..end].chunk_by(|a, b| a.0 == b.0) {
+        push_crate_header(&mut output, crate_keys, &mut current, group[0].0);
+        for line in search::format_matches(group.iter().map(|(_, m)| m), options) {
+            output.push_str(&line);
+            output.push('\n');
+        }
+    }
+
+    output.push_str(&format!("\nTotal: {} matches", matches.len()));
+    if crate_keys.len() > 1 {
+        output.push_str(&format!(" across {} crate(s)", crate_keys.len()));
+    }
+    if start == end && start > 0 {
+        output.push_str(&format!(", none past offset {}", offset));
+    } else if start > 0 || end < matches.len() {
+        output.push_str(&format!(", showing {}-{}", start + 1, end));
+    }
+    if end < matches.len() {
+        output.push_str(&format!(" (next offset: {})", end));
+    }
+    Ok(output)
+}
+
+fn do_structural_search(db: &Database, crate_keys: &[String], pattern: &Pattern, limit: usize) -> anyhow::Result<String> {
+    if limit == 0 {
+        anyhow::bail!("limit must be at least 1");
+    }
+    let mut matches = Vec::new();
+    for crate_key in crate_keys {
+        matches.extend(search::search_structural(db, crate_key, pattern)?.into_iter().map(|m| (crate_key, m)));
+    }
+    if matches.is_empty() {
+        return Ok("No items match the pattern".to_string());
+    }
 
     let mut output = String::new();
-    for m in matches.iter().take(50) {
-        output.push_str(&format!("{}:{}: {}\n", m.file, m.line, m.content));
+    let mut current = None;
+    for (crate_key, m) in matches.iter().take(limit) {
+        push_crate_header(&mut output, crate_keys, &mut current, crate_key);
+        output.push_str(&search::format_structural_match(m));
+        output.push('\n');
     }
+    output.push_str(&format!("\nTotal: {} items", matches.len()));
+    if matches.len() > limit {
+        output.push_str(&format!(", showing the first {}", limit));
+    }
+    Ok(output)
+}
 
-    if matches.len() > 50 {
-        output.push_str(&format!("\n... and {} more matches\n", matches.len() - 50));
+fn do_search_by_type(db: &Database, crate_keys: &[String], query: &TypeQuery, limit: usize) -> anyhow::Result<String> {
+    if limit == 0 {
+        anyhow::bail!("limit must be at least 1");
+    }
+    let mut ranked = Vec::new();
+    for crate_key in crate_keys {
+        for (distance, func) in search::search_signatures(db.get_functions(crate_key)?, query) {
+            ranked.push((distance, crate_key, func));
+        }
+    }
+    if ranked.is_empty() {
+        return Ok("No functions match the type".to_string());
     }
+    ranked.sort_by(|(a, _, f), (b, _, g)| a.cmp(b).then_with(|| f.name.cmp(&g.name)));
 
-    output.push_str(&format!("\nTotal: {} matches", matches.len()));
+    let mut output = String::new();
+    for (distance, crate_key, func) in ranked.iter().take(limit) {
+        output.push_str(&format!("[{}] {}\n", func.id, func.signature));
+        let location = format!("{}:{}", func.file, func.line);
+        if crate_keys.len() > 1 {
+            output.push_str(&format!("  {} {} (distance {})\n", crate_key, location, distance));
+        } else {
+            output.push_str(&format!("  {} (distance {})\n", location, distance));
+        }
+    }
+    output.push_str(&format!("\nTotal: {} functions", ranked.len()));
+    if ranked.len() > limit {
+        output.push_str(&format!(", showing the closest {}", limit));
+    }
     Ok(output)
 }
 
-fn do_list_functions(crate_name: &str, pattern: Option<&str>) -> anyhow::Result<String> {
-    let db = Database::open()?;
-    let crate_key = ensure_crate(&db, crate_name)?;
+fn do_find_items(query: &str, crates: Option<&[String]>, kind: Option<&str>, limit: usize) -> anyhow::Result<String> {
+    if limit == 0 {
+        anyhow::bail!("limit must be at least 1");
+    }
+    let db = storage::pooled()?;
+    let fts_query = search::fts_query(query)
+        .ok_or_else(|| anyhow::anyhow!("Search query '{}' contains no words", query))?;
+    if let Some(kind) = kind {
+        if !storage::ITEM_KINDS.contains(&kind) {
+            anyhow::bail!("Unknown item kind '{}' (expected one of: {})", kind, storage::ITEM_KINDS.join(", "));
+        }
+    }
+
+    let scope = match crates {
+        Some(crates) if !crates.is_empty() => Some(
+            crates.iter().map(|c| ensure_crate(&db, c)).collect::<anyhow::Result<Vec<_>>>()?,
+        ),
+        _ => None,
+    };
+    indexer::reindex_stale_crates(&db, scope.as_deref())?;
+
+    let hits = db.search_items(&fts_query, scope.as_deref(), kind, limit)?;
+    if hits.is_empty() {
+        let crate_keys = match scope {
+            Some(keys) => keys,
+            None => db.list_crate_keys()?,
+        };
+        return Ok(format!("No items matching '{}'.{}", query, fuzzy::item_hint(&db, &crate_keys, kind, query.trim())?));
+    }
+
+    let mut output = String::new();
+    for hit in &hits {
+        output.push_str(&format!(
+            "[{}] {} {} ({}) {}:{}\n    {}\n",
+            hit.id, hit.item_type, hit.name, hit.crate_key, hit.file, hit.line,
+            hit.snippet.replace('\n', " ")
+        ));
+    }
+    output.push_str(&format!("\n{} result(s)", hits.len()));
+    if hits.len() == limit {
+        output.push_str(" (limit reached)");
+    }
+    Ok(output)
+}
 
-    let functions = db.get_functions(&crate_key)?;
-    let matches = search_functions(&functions, pattern)?;
+fn do_list_functions(db: &Database, crate_keys: &[String], pattern: Option<&str>) -> anyhow::Result<String> {
+    let mut matches = Vec::new();
+    for crate_key in crate_keys {
+        let functions = db.get_functions(crate_key)?;
+        matches.extend(search_functions(&functions, pattern)?.into_iter().map(|f| (crate_key, f)));
+    }
 
     let mut output = String::new();
-    for func in matches.iter().take(50) {
+    let mut current = None;
+    for (crate_key, func) in matches.iter().take(50) {
+        push_crate_header(&mut output, crate_keys, &mut current, crate_key);
         output.push_str(&format!("[{}] {}\n", func.id, func.signature));
         output.push_str(&format!("  {}:{}\n", func.file, func.line));
         if let Some(docs) = &func.docs {
@@ -423,22 +790,27 @@ fn do_list_functions(crate_name: &str, pattern: Option<&str>) -> anyhow::Result<
     }
 
     output.push_str(&format!("Total: {} functions", matches.len()));
+    if let Some(pattern) = pattern.filter(|_| matches.is_empty()) {
+        output.push_str(&format!(" matching '{}'.{}", pattern, fuzzy::item_hint(db, crate_keys, Some("function"), pattern)?));
+    }
     Ok(output)
 }
 
-fn do_list_structs(crate_name: &str, pattern: Option<&str>) -> anyhow::Result<String> {
-    let db = Database::open()?;
-    let crate_key = ensure_crate(&db, crate_name)?;
-
-    let structs = db.get_structs(&crate_key)?;
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
+fn do_list_structs(db: &Database, crate_keys: &[String], pattern: Option<&str>) -> anyhow::Result<String> {
+    let regex = pattern.map(build_regex).transpose()?;
 
-    let matches: Vec<_> = structs.iter()
-        .filter(|s| regex.as_ref().map(|r| r.is_match(&s.name)).unwrap_or(true))
-        .collect();
+    let mut matches = Vec::new();
+    for crate_key in crate_keys {
+        let structs = db.get_structs(crate_key)?;
+        matches.extend(structs.into_iter()
+            .filter(|s| regex.as_ref().map(|r| r.is_match(&s.name)).unwrap_or(true))
+            .map(|s| (crate_key, s)));
+    }
 
     let mut output = String::new();
-    for s in matches.iter().take(50) {
+    let mut current = None;
+    for (crate_key, s) in matches.iter().take(50) {
+        push_crate_header(&mut output, crate_keys, &mut current, crate_key);
         output.push_str(&format!("[{}] {} struct {}\n", s.id, s.visibility, s.name));
         output.push_str(&format!("  {}:{}\n", s.file, s.line));
         if !s.fields.is_empty() {
@@ -453,22 +825,27 @@ fn do_list_structs(crate_name: &str, pattern: Option<&str>) -> anyhow::Result<St
     }
 
     output.push_str(&format!("Total: {} structs", matches.len()));
+    if let Some(pattern) = pattern.filter(|_| matches.is_empty()) {
+        output.push_str(&format!(" matching '{}'.{}", pattern, fuzzy::item_hint(db, crate_keys, Some("struct"), pattern)?));
+    }
     Ok(output)
 }
 
-fn do_list_enums(crate_name: &str, pattern: Option<&str>) -> anyhow::Result<String> {
-    let db = Database::open()?;
-    let crate_key = ensure_crate(&db, crate_name)?;
-
-    let enums = db.get_enums(&crate_key)?;
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
+fn do_list_enums(db: &Database, crate_keys: &[String], pattern: Option<&str>) -> anyhow::Result<String> {
+    let regex = pattern.map(build_regex).transpose()?;
 
-    let matches: Vec<_> = enums.iter()
-        .filter(|e| regex.as_ref().map(|r| r.is_match(&e.name)).unwrap_or(true))
-        .collect();
+    let mut matches = Vec::new();
+    for crate_key in crate_keys {
+        let enums = db.get_enums(crate_key)?;
+        matches.extend(enums.into_iter()
+            .filter(|e| regex.as_ref().map(|r| r.is_match(&e.name)).unwrap_or(true))
+            .map(|e| (crate_key, e)));
+    }
 
     let mut output = String::new();
-    for e in matches.iter().take(50) {
+    let mut current = None;
+    for (crate_key, e) in matches.iter().take(50) {
+        push_crate_header(&mut output, crate_keys, &mut current, crate_key);
         output.push_str(&format!("[{}] {} enum {}\n", e.id, e.visibility, e.name));
         output.push_str(&format!("  {}:{}\n", e.file, e.line));
         let variant_names: Vec<_> = e.variants.iter().take(5).map(|v| v.name.as_str()).collect();
@@ -481,22 +858,27 @@ fn do_list_enums(crate_name: &str, pattern: Option<&str>) -> anyhow::Result<Stri
     }
 
     output.push_str(&format!("Total: {} enums", matches.len()));
+    if let Some(pattern) = pattern.filter(|_| matches.is_empty()) {
+        output.push_str(&format!(" matching '{}'.{}", pattern, fuzzy::item_hint(db, crate_keys, Some("enum"), pattern)?));
+    }
     Ok(output)
 }
 
-fn do_list_traits(crate_name: &str, pattern: Option<&str>) -> anyhow::Result<String> {
-    let db = Database::open()?;
-    let crate_key = ensure_crate(&db, crate_name)?;
-
-    let traits = db.get_traits(&crate_key)?;
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
+fn do_list_traits(db: &Database, crate_keys: &[String], pattern: Option<&str>) -> anyhow::Result<String> {
+    let regex = pattern.map(build_regex).transpose()?;
 
-    let matches: Vec<_> = traits.iter()
-        .filter(|t| regex.as_ref().map(|r| r.is_match(&t.name)).unwrap_or(true))
-        .collect();
+    let mut matches = Vec::new();
+    for crate_key in crate_keys {
+        let traits = db.get_traits(crate_key)?;
+        matches.extend(traits.into_iter()
+            .filter(|t| regex.as_ref().map(|r| r.is_match(&t.name)).unwrap_or(true))
+            .map(|t| (crate_key, t)));
+    }
 
     let mut output = String::new();
-    for t in matches.iter().take(50) {
+    let mut current = None;
+    for (crate_key, t) in matches.iter().take(50) {
+        push_crate_header(&mut output, crate_keys, &mut current, crate_key);
         output.push_str(&format!("[{}] {} trait {}\n", t.id, t.visibility, t.name));
         output.push_str(&format!("  {}:{}\n", t.file, t.line));
         if let Some(docs) = &t.docs {
@@ -513,26 +895,31 @@ fn do_list_traits(crate_name: &str, pattern: Option<&str>) -> anyhow::Result<Str
     }
 
     output.push_str(&format!("Total: {} traits", matches.len()));
+    if let Some(pattern) = pattern.filter(|_| matches.is_empty()) {
+        output.push_str(&format!(" matching '{}'.{}", pattern, fuzzy::item_hint(db, crate_keys, Some("trait"), pattern)?));
+    }
     Ok(output)
 }
 
-fn do_list_impls(crate_name: &str, pattern: Option<&str>) -> anyhow::Result<String> {
-    let db = Database::open()?;
-    let crate_key = ensure_crate(&db, crate_name)?;
-
-    let impls = db.get_impls(&crate_key)?;
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
-
-    let matches: Vec<_> = impls.iter()
-        .filter(|i| {
-            regex.as_ref().map(|r| {
-                r.is_match(&i.self_type) || i.trait_name.as_ref().map(|t| r.is_match(t)).unwrap_or(false)
-            }).unwrap_or(true)
-        })
-        .collect();
+fn do_list_impls(db: &Database, crate_keys: &[String], pattern: Option<&str>) -> anyhow::Result<String> {
+    let regex = pattern.map(build_regex).transpose()?;
+
+    let mut matches = Vec::new();
+    for crate_key in crate_keys {
+        let impls = db.get_impls(crate_key)?;
+        matches.extend(impls.into_iter()
+            .filter(|i| {
+                regex.as_ref().map(|r| {
+                    r.is_match(&i.self_type) || i.trait_name.as_ref().map(|t| r.is_match(t)).unwrap_or(false)
+                }).unwrap_or(true)
+            })
+            .map(|i| (crate_key, i)));
+    }
 
     let mut output = String::new();
-    for i in matches.iter().take(50) {
+    let mut current = None;
+    for (crate_key, i) in matches.iter().take(50) {
+        push_crate_header(&mut output, crate_keys, &mut current, crate_key);
         let impl_desc = match &i.trait_name {
             Some(trait_name) => format!("impl {} for {}", trait_name, i.self_type),
             None => format!("impl {}", i.self_type),
@@ -546,11 +933,18 @@ fn do_list_impls(crate_name: &str, pattern: Option<&str>) -> anyhow::Result<Stri
     }
 
     output.push_str(&format!("Total: {} impls", matches.len()));
+    if let Some(pattern) = pattern.filter(|_| matches.is_empty()) {
+        output.push_str(&format!(" matching '{}'.{}", pattern, fuzzy::item_hint(db, crate_keys, Some("impl"), pattern)?));
+    }
     Ok(output)
 }
 
-fn do_show_item(id: &str) -> anyhow::Result<String> {
-    let db = Database::open()?;
+fn do_show_item(selector: &str) -> anyhow::Result<String> {
+    let db = storage::pooled()?;
+    let id = &match selector.split_once("::") {
+        Some((crate_name, path)) => db.resolve_item_path(&ensure_crate(&db, crate_name)?, path)?,
+        None => db.resolve_item_id(selector)?,
+    };
 
     if let Some((crate_key, func)) = db.get_function_by_id(id)? {
         return show_function_detail(&db, &crate_key, &func);
@@ -664,15 +1058,8 @@ fn show_impl_detail(db: &Database, crate_key: &str, i: &crate::storage::ImplInfo
 }
 
 fn get_source(db: &Database, crate_key: &str, file: &str, start: usize, end: Option<usize>) -> anyhow::Result<String> {
-    let crate_path = db.get_crate_path(crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found"))?;
-
-    let source_path = crate_path.join(file);
-    if !source_path.exists() {
-        anyhow::bail!("Source file '{}' not found in {}", file, crate_key);
-    }
-
-    let content = std::fs::read_to_string(&source_path)?;
+    let content = db.read_file(crate_key, file)?
+        .ok_or_else(|| anyhow::anyhow!("Source file '{}' not found in {}", file, crate_key))?;
     let lines: Vec<&str> = content.lines().collect();
     let start_idx = start.saturating_sub(1);
     let end_idx = end.unwrap_or(start + 30).min(lines.len());
@@ -688,10 +1075,8 @@ fn get_source(db: &Database, crate_key: &str, file: &str, start: usize, end: Opt
 const MAX_DEFAULT_LINES: usize = 500;
 
 fn do_read_file(crate_name: &str, file_path: &str, start: Option<usize>, end: Option<usize>) -> anyhow::Result<String> {
-    let db = Database::open()?;
+    let db = storage::pooled()?;
     let crate_key = ensure_crate(&db, crate_name)?;
-    let crate_path = db.get_crate_path(&crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found"))?;
 
     // Security check
     let file_path_obj = std::path::Path::new(file_path);
@@ -707,12 +1092,9 @@ fn do_read_file(crate_name: &str, file_path: &str, start: Option<usize>, end: Op
         }
     }
 
-    let full_path = crate_path.join(file_path);
-    if !full_path.exists() {
-        anyhow::bail!("File '{}' not found in {}", file_path, crate_key);
-    }
-
-    let content = std::fs::read_to_string(&full_path)?;
+    let content = db.read_file(&crate_key, file_path)?.ok_or_else(|| {
+        anyhow::anyhow!("File '{}' not found in {}; use list_files to see its files", file_path, crate_key)
+    })?;
     let lines: Vec<&str> = content.lines().collect();
     let total = lines.len();
 
@@ -749,11 +1131,33 @@ fn do_read_file(crate_name: &str, file_path: &str, start: Option<usize>, end: Op
     Ok(output)
 }
 
+fn do_list_files(crate_name: &str, dir: Option<&str>) -> anyhow::Result<String> {
+    let db = storage::pooled()?;
+    let crate_key = ensure_crate(&db, crate_name)?;
+    let prefix = dir.map(|d| format!("{}/", d.trim_end_matches('/')));
+    let files: Vec<_> = db
+        .list_files(&crate_key)?
+        .into_iter()
+        .filter(|f| prefix.as_ref().is_none_or(|p| f.path.starts_with(p.as_str())))
+        .collect();
+
+    let mut output = format!("{} ({} files)\n\n", crate_key, files.len());
+    for f in &files {
+        output.push_str(&format!("{} ({} bytes, {} lines", f.path, f.size, f.lines));
+        if let Some(language) = &f.language {
+            output.push_str(&format!(", {}", language));
+        }
+        if let Some(target) = &f.target {
+            output.push_str(&format!(", {}", target));
+        }
+        output.push_str(")\n");
+    }
+    Ok(output)
+}
+
 fn do_read_readme(crate_name: &str) -> anyhow::Result<String> {
-    let db = Database::open()?;
+    let db = storage::pooled()?;
     let crate_key = ensure_crate(&db, crate_name)?;
-    let crate_path = db.get_crate_path(&crate_key)?
-        .ok_or_else(|| anyhow::anyhow!("Crate path not found"))?;
 
     // Look for README files in order of preference
     let readme_names = [
@@ -768,9 +1172,7 @@ fn do_read_readme(crate_name: &str) -> anyhow::Result<String> {
     ];
 
     for name in &readme_names {
-        let readme_path = crate_path.join(name);
-        if readme_path.exists() {
-            let content = std::fs::read_to_string(&readme_path)?;
+        if let Some(content) = db.read_file(&crate_key, name)? {
             return Ok(format!("── {} ({}) ──\n\n{}", crate_key, name, content));
         }
     }
@@ -778,6 +1180,14 @@ fn do_read_readme(crate_name: &str) -> anyhow::Result<String> {
     anyhow::bail!("No README found in {}", crate_key)
 }
 
+fn do_crate_info(crate_name: &str) -> anyhow::Result<String> {
+    let db = storage::pooled()?;
+    let crate_key = ensure_crate(&db, crate_name)?;
+    let meta = fetch::indexed_metadata(&db, &crate_key)?;
+    let deps = fetch::indexed_dependencies(&db, &crate_key)?;
+    Ok(meta.summary(&crate_key, &deps))
+}
+
 fn truncate(s: &str, max: usize) -> String {
     if s.len() <= max {
         return s.to_string();
@@ -791,9 +1201,12 @@ fn truncate(s: &str, max: usize) -> String {
 }
 
 async fn do_semantic_search(crate_name: &str, query: &str, limit: usize) -> anyhow::Result<String> {
+    if limit == 0 {
+        anyhow::bail!("limit must be at least 1");
+    }
     // Phase 1: Get all crate keys including re-exports (synchronous)
     let crate_keys = {
-        let db = Database::open()?;
+        let db = storage::pooled()?;
         let main_key = ensure_crate(&db, crate_name)?;
         get_crate_keys_with_reexports(&db, &main_key)?
     };
@@ -802,20 +1215,14 @@ async fn do_semantic_search(crate_name: &str, query: &str, limit: usize) -> anyh
         return Ok(format!("No crates found for: {}", crate_name));
     }
 
-    // Phase 2: Generate embeddings for all crates that need them
+    // Phase 2: Embed items that are new or changed since the last search
     for key in &crate_keys {
-        let has_embeddings = {
-            let db = Database::open()?;
-            db.has_embeddings(key)?
-        };
-        if !has_embeddings {
-            generate_embeddings_for_crate(key).await?;
-        }
+        generate_embeddings_for_crate(key).await?;
     }
 
     // Phase 3: Get all embeddings from all crates
     let stored_embeddings = {
-        let db = Database::open()?;
+        let db = storage::pooled()?;
         let mut all_embeddings = Vec::new();
         for key in &crate_keys {
             all_embeddings.extend(db.get_all_embeddings(key)?);
@@ -893,6 +1300,7 @@ fn get_crate_keys_with_reexports(db: &Database, main_key: &str) -> anyhow::Resul
         for reexport in db.get_reexports(&key)? {
             if let Some(reexport_key) = db.find_crate_key(&reexport)? {
                 if !seen.contains(&reexport_key) {
+                    indexer::reindex_if_stale(db, &reexport_key)?;
                     seen.insert(reexport_key.clone());
                     keys.push(reexport_key.clone());
                     to_process.push((reexport_key, depth + 1));
@@ -908,62 +1316,12 @@ fn get_crate_keys_with_reexports(db: &Database, main_key: &str) -> anyhow::Resul
     Ok(keys)
 }
 
+/// Embed the items of a crate that are new or changed since embeddings were last generated
 async fn generate_embeddings_for_crate(crate_key: &str) -> anyhow::Result<()> {
-    // Phase 1: Collect items from database (synchronous)
+    // Phase 1: Collect items without an up-to-date embedding (synchronous)
     let (items_to_embed, crate_id) = {
-        let db = Database::open()?;
-        let mut items: Vec<(String, String, String)> = Vec::new(); // (id, type, text)
-
-        // Functions
-        for func in db.get_functions(crate_key)? {
-            let mut text = func.signature.clone();
-            if let Some(docs) = &func.docs {
-                text.push_str(". ");
-                text.push_str(docs);
-            }
-            items.push((func.id, "function".to_string(), text));
-        }
-
-        // Structs
-        for s in db.get_structs(crate_key)? {
-            let mut text = format!("struct {}", s.name);
-            if !s.fields.is_empty() {
-                let field_names: Vec<&str> = s.fields.iter().map(|f| f.name.as_str()).collect();
-                text.push_str(" with fields: ");
-                text.push_str(&field_names.join(", "));
-            }
-            if let Some(docs) = &s.docs {
-                text.push_str(". ");
-                text.push_str(docs);
-            }
-            items.push((s.id, "struct".to_string(), text));
-        }
-
-        // Enums
-        for e in db.get_enums(crate_key)? {
-            let mut text = format!("enum {}", e.name);
-            if !e.variants.is_empty() {
-                let variant_names: Vec<&str> = e.variants.iter().map(|v| v.name.as_str()).collect();
-                text.push_str(" with variants: ");
-                text.push_str(&variant_names.join(", "));
-            }
-            if let Some(docs) = &e.docs {
-                text.push_str(". ");
-                text.push_str(docs);
-            }
-            items.push((e.id, "enum".to_string(), text));
-        }
-
-        // Traits
-        for t in db.get_traits(crate_key)? {
-            let mut text = format!("trait {}", t.name);
-            if let Some(docs) = &t.docs {
-                text.push_str(". ");
-                text.push_str(docs);
-            }
-            items.push((t.id, "trait".to_string(), text));
-        }
-
+        let db = storage::pooled()?;
+        let items = embeddings::pending_items(&db, crate_key)?;
         let crate_id = db.get_crate_id(crate_key)?.ok_or_else(|| anyhow::anyhow!("Crate not found"))?;
         (items, crate_id)
     };
@@ -987,7 +1345,7 @@ async fn generate_embeddings_for_crate(crate_key: &str) -> anyhow::Result<()> {
         })
         .collect();
 
-    let db = Database::open()?;
+    let db = storage::pooled()?;
     db.save_embeddings(crate_id, &embeddings_to_store)?;
 
     Ok(())
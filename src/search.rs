@@ -1,12 +1,17 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use rayon::prelude::*;
 use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
 use std::fs;
-use std::io::{BufRead, BufReader};
-use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::path::Path;
 
-use crate::storage::FunctionInfo;
+use crate::indexer;
+use crate::lexer::{Class, Classes};
+use crate::signature::TypeQuery;
+use crate::storage::{self, unpack_contents, Database, FunctionInfo, ItemSpan};
+use crate::structural::{Pattern, StructuralMatch};
+use crate::trigram::Query;
 
 /// Build a regex with size limits to prevent ReDoS attacks
 pub fn build_regex(pattern: &str) -> Result<Regex> {
@@ -17,67 +22,379 @@ pub fn build_regex(pattern: &str) -> Result<Regex> {
         .with_context(|| format!("Invalid or too complex regex: {}", pattern))
 }
 
+/// Turn user input into an FTS5 query. Words are ANDed, `word*` matches a
+/// prefix and `"several words"` a phrase (`"several wo"*` a phrase prefix).
+/// Everything else is quoted, so FTS5 operators and punctuation such as
+/// `HashMap::new` never cause a syntax error. Returns `None` for empty input.
+pub fn fts_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let text: String = if c == '"' {
+            chars.next();
+            chars.by_ref().take_while(|&c| c != '"').collect()
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            word
+        };
+
+        let mut text = text.as_str();
+        let mut prefix = chars.peek() == Some(&'*');
+        if prefix {
+            chars.next();
+        } else if let Some(stripped) = text.strip_suffix('*') {
+            text = stripped;
+            prefix = true;
+        }
+
+        let text = text.trim();
+        if text.is_empty() || !text.chars().any(|c| c.is_alphanumeric()) {
+            continue;
+        }
+        terms.push(format!("\"{}\"{}", text.replace('"', "\"\""), if prefix { "*" } else { "" }));
+    }
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
 #[derive(Debug)]
 pub struct SearchMatch {
     pub file: String,
+    /// Range of the match: 1-based lines and byte columns, the end inclusive
     pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    /// The lines the match spans, joined with newlines
     pub content: String,
+    /// Lines before and after the match, when context was asked for
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+    /// ID of the innermost item around the match, when searching inside items
+    pub item: Option<String>,
 }
 
-pub fn search_regex(crate_path: &Path, pattern: &str) -> Result<Vec<SearchMatch>> {
-    let regex = build_regex(pattern)?;
+/// Options of a regex search besides the pattern
+#[derive(Debug, Default)]
+pub struct SearchOptions {
+    /// Match the pattern against whole files, so it can span lines
+    pub multiline: bool,
+    /// Treat the pattern as a literal string
+    pub fixed_strings: bool,
+    pub ignore_case: bool,
+    /// Only match whole words (`\b` around the pattern)
+    pub whole_word: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    /// Only search files matching one of these globs; globs without a `/` match file names
+    pub globs: Vec<String>,
+    /// Skip files matching one of these globs
+    pub excludes: Vec<String>,
+    /// Stop searching a file after this many matches
+    pub max_count: Option<usize>,
+    /// Only keep matches starting in these kinds of text (all when empty)
+    pub within: Vec<Class>,
+    /// Only keep matches inside items passing this filter, noting the item
+    pub items: Option<ItemFilter>,
+}
 
-    // Collect all .rs files first
-    let files: Vec<(PathBuf, String)> = WalkDir::new(crate_path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().is_some_and(|ext| ext == "rs"))
-        .map(|entry| {
-            let file_path = entry.path().to_path_buf();
-            let relative_path = file_path
-                .strip_prefix(crate_path)
-                .unwrap_or(&file_path)
-                .to_string_lossy()
-                .to_string();
-            (file_path, relative_path)
-        })
-        .collect();
+/// Which items a search is limited to
+#[derive(Debug, Default)]
+pub struct ItemFilter {
+    /// One of `storage::ITEM_KINDS`; impls when only a trait is given
+    pub kind: Option<String>,
+    /// Regex for item names, or self types of impls
+    pub name: Option<String>,
+    /// Impls of this trait (e.g. "Drop" or "From<String>"), or with a kind the items inside them
+    pub impl_trait: Option<String>,
+}
+
+impl SearchOptions {
+    fn has_context(&self) -> bool {
+        self.before_context > 0 || self.after_context > 0
+    }
+}
+
+/// Regex search over a crate's Rust files, read from the index where their contents
+/// are stored and from the crate's sources on disk otherwise. The trigram index
+/// narrows down the files to scan when the pattern contains literals.
+pub fn search_regex(db: &Database, crate_key: &str, pattern: &str, options: &SearchOptions) -> Result<Vec<SearchMatch>> {
+    let mut pattern = if options.fixed_strings { regex::escape(pattern) } else { pattern.to_string() };
+    if options.whole_word {
+        pattern = format!(r"\b(?:{})\b", pattern);
+    }
+    if options.ignore_case {
+        pattern = format!("(?i){}", pattern);
+    }
+    if options.multiline {
+        // `^` and `$` still match at line boundaries
+        pattern = format!("(?m){}", pattern);
+    }
+    let regex = build_regex(&pattern)?;
+    let globs = build_globs(&options.globs)?;
+    let excludes = build_globs(&options.excludes)?;
+    let crate_path = db.get_crate_path(crate_key)?.unwrap_or_default();
+
+    let items = options.items.as_ref().map(|filter| scoped_items(db, crate_key, filter)).transpose()?;
+
+    let mut files = candidate_files(db, crate_key, &Query::from_regex(&pattern))?;
+    files.retain(|(path, _)| {
+        globs.as_ref().is_none_or(|g| glob_matches(g, path))
+            && !excludes.as_ref().is_some_and(|g| glob_matches(g, path))
+            && items.as_ref().is_none_or(|items| items.contains_key(path))
+    });
 
     // Search files in parallel
     let matches: Vec<SearchMatch> = files
         .par_iter()
-        .flat_map(|(file_path, relative_path)| {
-            search_file(file_path, relative_path, &regex)
+        .flat_map(|(relative_path, packed)| {
+            let items = items.as_ref().map(|items| items[relative_path].as_slice());
+            read_source(&crate_path, relative_path, packed.as_deref())
+                .map(|content| search_file(&content, relative_path, &regex, options, items))
+                .unwrap_or_default()
         })
         .collect();
 
     Ok(matches)
 }
 
-fn search_file(file_path: &Path, relative_path: &str, regex: &Regex) -> Vec<SearchMatch> {
-    let mut matches = Vec::new();
+/// The items of a crate passing the filter, by file
+fn scoped_items(db: &Database, crate_key: &str, filter: &ItemFilter) -> Result<HashMap<String, Vec<ItemSpan>>> {
+    if let Some(kind) = &filter.kind {
+        if !storage::ITEM_KINDS.contains(&kind.as_str()) {
+            bail!("Unknown item kind '{}' (expected one of: {})", kind, storage::ITEM_KINDS.join(", "));
+        }
+    }
+    let kind = filter.kind.as_deref().or(filter.impl_trait.as_ref().map(|_| "impl"));
+    let name = filter.name.as_deref().map(build_regex).transpose()?;
+
+    let spans = db.get_item_spans(crate_key)?;
+    let impls: Vec<&ItemSpan> = match &filter.impl_trait {
+        Some(wanted) => spans.iter()
+            .filter(|s| s.trait_name.as_deref().is_some_and(|t| trait_matches(t, wanted)))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let mut items: HashMap<String, Vec<ItemSpan>> = HashMap::new();
+    for span in &spans {
+        let in_impl = filter.impl_trait.is_none()
+            || impls.iter().any(|i| i.file == span.file && i.line <= span.line && span.end_line <= i.end_line);
+        if kind.is_none_or(|k| span.kind == k) && name.as_ref().is_none_or(|r| r.is_match(&span.name)) && in_impl {
+            items.entry(span.file.clone()).or_default().push(span.clone());
+        }
+    }
+    Ok(items)
+}
 
-    if let Ok(file) = fs::File::open(file_path) {
-        let reader = BufReader::new(file);
+/// Whether an impl's trait is the wanted one. Paths may be given partly and
+/// generic arguments left out, so "Drop" matches `std::ops::Drop`.
+fn trait_matches(trait_name: &str, wanted: &str) -> bool {
+    let trait_name: String = trait_name.split_whitespace().collect();
+    let wanted: String = wanted.split_whitespace().collect();
+    let trait_name = if wanted.contains('<') { &trait_name } else { trait_name.split('<').next().unwrap_or_default() };
+    trait_name == wanted || trait_name.ends_with(&format!("::{}", wanted)) || wanted.ends_with(&format!("::{}", trait_name))
+}
 
-        for (line_num, line_result) in reader.lines().enumerate() {
-            if let Ok(line) = line_result {
-                if regex.is_match(&line) {
-                    matches.push(SearchMatch {
-                        file: relative_path.to_string(),
-                        line: line_num + 1,
-                        content: line.trim().to_string(),
-                    });
+fn search_file(
+    content: &str,
+    relative_path: &str,
+    regex: &Regex,
+    options: &SearchOptions,
+    items: Option<&[ItemSpan]>,
+) -> Vec<SearchMatch> {
+    let lines: Vec<&str> = content.lines().collect();
+    if lines.is_empty() {
+        return Vec::new();
+    }
+    // Byte offsets of line starts, to turn match offsets into lines and columns
+    let starts: Vec<usize> = std::iter::once(0).chain(content.match_indices('\n').map(|(i, _)| i + 1)).collect();
+    let position = |offset: usize| {
+        let line = (starts.partition_point(|&start| start <= offset) - 1).min(lines.len() - 1);
+        (line, offset - starts[line])
+    };
+
+    let classes = (!options.within.is_empty()).then(|| Classes::of(content));
+    let wanted = |start: &usize| classes.as_ref().is_none_or(|c| options.within.contains(&c.at(*start)));
+    let spans: Vec<(usize, usize)> = if options.multiline {
+        regex.find_iter(content).map(|m| (m.start(), m.end())).filter(|(start, _)| wanted(start)).collect()
+    } else {
+        lines.iter().enumerate()
+            .filter_map(|(i, line)| {
+                regex.find_iter(line).map(|m| (starts[i] + m.start(), starts[i] + m.end())).find(|(start, _)| wanted(start))
+            })
+            .collect()
+    };
+
+    // Context and multi-line matches keep indentation; bare matching lines are trimmed
+    let keep_indent = options.has_context() || options.multiline;
+    spans
+        .into_iter()
+        .filter_map(|(start, end)| {
+            let Some(items) = items else { return Some((start, end, None)) };
+            let line = position(start).0 + 1;
+            items.iter()
+                .filter(|item| item.line <= line && line <= item.end_line)
+                .min_by_key(|item| item.end_line - item.line)
+                .map(|item| (start, end, Some(item.id.clone())))
+        })
+        .take(options.max_count.unwrap_or(usize::MAX))
+        .map(|(start, end, item)| {
+            let (line, column) = position(start);
+            let (end_line, end_column) = position(end.max(start + 1) - 1);
+            let content = lines[line..=end_line].iter()
+                .map(|l| if keep_indent { l.trim_end() } else { l.trim() })
+                .collect::<Vec<_>>()
+                .join("\n");
+            SearchMatch {
+                file: relative_path.to_string(),
+                line: line + 1,
+                column: column + 1,
+                end_line: end_line + 1,
+                end_column: end_column + 1,
+                content,
+                before: lines[line.saturating_sub(options.before_context)..line].iter().map(|l| l.trim_end().to_string()).collect(),
+                after: lines[end_line + 1..(end_line + 1 + options.after_context).min(lines.len())].iter().map(|l| l.trim_end().to_string()).collect(),
+                item,
+            }
+        })
+        .collect()
+}
+
+/// Items of a crate matching a structural pattern. Files are read like in
+/// `search_regex`; only those containing the pattern's identifiers are parsed.
+pub fn search_structural(db: &Database, crate_key: &str, pattern: &Pattern) -> Result<Vec<StructuralMatch>> {
+    let crate_path = db.get_crate_path(crate_key)?.unwrap_or_default();
+    let files = candidate_files(db, crate_key, &Query::all_of(&pattern.literals()))?;
+
+    let matches: Vec<StructuralMatch> = files
+        .par_iter()
+        .flat_map(|(relative_path, packed)| {
+            read_source(&crate_path, relative_path, packed.as_deref())
+                .and_then(|content| indexer::find_structural(&content, relative_path, crate_key, pattern).ok())
+                .unwrap_or_default()
+        })
+        .collect();
+
+    Ok(matches)
+}
+
+/// The crate's Rust files (with their packed contents, if stored) that may satisfy
+/// `query`, according to the trigram index when the crate has one
+fn candidate_files(db: &Database, crate_key: &str, query: &Query) -> Result<Vec<(String, Option<Vec<u8>>)>> {
+    let candidates = if *query != Query::All && db.has_trigram_index(crate_key)? {
+        query.eval(&mut |t| db.get_postings(crate_key, t))?
+    } else {
+        None
+    };
+    if candidates.as_ref().is_some_and(Vec::is_empty) {
+        return Ok(Vec::new());
+    }
+    db.get_packed_files(crate_key, "rust", candidates.as_deref())
+}
+
+fn read_source(crate_path: &Path, relative_path: &str, packed: Option<&[u8]>) -> Option<String> {
+    match packed {
+        Some(packed) => unpack_contents(packed).ok(),
+        None => fs::read_to_string(crate_path.join(relative_path)).ok(),
+    }
+}
+
+/// Glob set for file filters, `None` when there are no globs
+fn build_globs(globs: &[String]) -> Result<Option<GlobSet>> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        let glob = GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("Invalid glob: {}", glob))?;
+        builder.add(glob);
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// Match a relative path, or its file name for globs without a `/`
+fn glob_matches(globs: &GlobSet, path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    globs.is_match(path) || globs.is_match(name)
+}
+
+/// Grep-style output lines: `file:line: text` for matches and `file-line- text` for
+/// context, with `--` between groups of lines that aren't adjacent. In multiline
+/// mode the first line of each match shows its range, `file:line:column-line:column: text`.
+/// Matches inside items start with the item's ID, `file:line: [id] text`.
+pub fn format_matches<'a>(matches: impl IntoIterator<Item = &'a SearchMatch>, options: &SearchOptions) -> Vec<String> {
+    let mut output = Vec::new();
+    let mut last: Option<(&str, usize)> = None;
+    let mut matches = matches.into_iter().peekable();
+    while let Some(m) = matches.next() {
+        // Context must not swallow the next match
+        let next_line = matches.peek().filter(|n| n.file == m.file).map_or(usize::MAX, |n| n.line);
+        let first = m.line - m.before.len();
+        let before = m.before.iter().enumerate().map(|(i, text)| (first + i, '-', text.as_str()));
+        let matched = m.content.split('\n').enumerate().map(|(i, text)| (m.line + i, ':', text));
+        let after = m.after.iter().enumerate()
+            .map(|(i, text)| (m.end_line + 1 + i, '-', text.as_str()))
+            .take_while(|&(line, _, _)| line < next_line);
+        for (line, separator, text) in before.chain(matched).chain(after) {
+            // A match starting on a line already shown still gets its range
+            let range = options.multiline && separator == ':' && line == m.line;
+            match last {
+                Some((file, printed)) if file == m.file && line <= printed && !range => continue,
+                Some((file, printed)) if options.has_context() && (file != m.file || line > printed + 1) => {
+                    output.push("--".to_string());
                 }
+                _ => {}
             }
+            let location = if range {
+                format!("{}:{}-{}:{}", m.line, m.column, m.end_line, m.end_column)
+            } else {
+                line.to_string()
+            };
+            let item = m.item.as_ref().filter(|_| separator == ':' && line == m.line).map(|id| format!("[{}] ", id));
+            output.push(format!("{}{}{}{} {}{}", m.file, separator, location, separator, item.unwrap_or_default(), text));
+            last = Some((&m.file, line.max(last.filter(|(file, _)| *file == m.file).map_or(0, |(_, printed)| printed))));
         }
     }
+    output
+}
 
-    matches
+/// `[id] kind file:line-end: first line`, then the metavariable bindings
+pub fn format_structural_match(m: &StructuralMatch) -> String {
+    let span = match m.end_line {
+        Some(end) if end != m.line => format!("{}-{}", m.line, end),
+        _ => m.line.to_string(),
+    };
+    let mut line = format!("[{}] {} {}:{}: {}", m.id, m.kind, m.file, span, m.text);
+    if !m.bindings.is_empty() {
+        let bindings: Vec<String> = m.bindings.iter().map(|(name, value)| format!("${} = {}", name, value)).collect();
+        line.push_str(&format!("\n    {}", bindings.join(", ")));
+    }
+    line
 }
 
 pub fn search_functions(functions: &[FunctionInfo], pattern: Option<&str>) -> Result<Vec<FunctionInfo>> {
-    let regex = pattern.map(|p| build_regex(p)).transpose()?;
+    let regex = pattern.map(build_regex).transpose()?;
 
     let matches: Vec<FunctionInfo> = functions
         .par_iter()
@@ -93,6 +410,19 @@ pub fn search_functions(functions: &[FunctionInfo], pattern: Option<&str>) -> Re
     Ok(matches)
 }
 
+/// Functions whose types fit the query, closest first (ties by name)
+pub fn search_signatures(functions: Vec<FunctionInfo>, query: &TypeQuery) -> Vec<(u32, FunctionInfo)> {
+    let mut matches: Vec<(u32, FunctionInfo)> = functions
+        .into_par_iter()
+        .filter_map(|func| {
+            let distance = func.type_signature.as_ref()?.distance(query)?;
+            Some((distance, func))
+        })
+        .collect();
+    matches.sort_by(|(a, f), (b, g)| a.cmp(b).then_with(|| f.name.cmp(&g.name)));
+    matches
+}
+
 #[derive(Debug, Clone)]
 pub struct SemanticSearchResult {
     pub item_id: String,
@@ -101,3 +431,126 @@ pub struct SemanticSearchResult {
     pub text_content: String,
     pub crate_key: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::demo_index;
+
+    #[test]
+    fn test_trigram_candidates() {
+        let (_tmp, db) = demo_index(|_| {});
+        // Rust files are numbered by path: src/bin/tool.rs, src/lib.rs, tests/parse.rs
+        assert!(db.has_trigram_index("demo-0.1.0").unwrap());
+        assert_eq!(db.get_postings("demo-0.1.0", u32::from_be_bytes([0, b'd', b'e', b'm'])).unwrap(), [2]);
+
+        let files = |pattern: &str| -> Vec<String> {
+            let files = candidate_files(&db, "demo-0.1.0", &Query::from_regex(pattern)).unwrap();
+            files.into_iter().map(|(path, _)| path).collect()
+        };
+        assert_eq!(files(r"parse\("), ["src/lib.rs", "tests/parse.rs"]);
+        assert_eq!(files("fn main"), ["src/bin/tool.rs"]);
+        // Without literals every file is a candidate
+        assert_eq!(files(r"\w+"), ["src/bin/tool.rs", "src/lib.rs", "tests/parse.rs"]);
+        assert!(files("missing").is_empty());
+        assert!(search_regex(&db, "demo-0.1.0", "missing", &Default::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_case_context_and_globs() {
+        let (_tmp, db) = demo_index(|_| {});
+        let options = SearchOptions {
+            ignore_case: true,
+            before_context: 1,
+            globs: vec!["tests/**".to_string()],
+            ..Default::default()
+        };
+        let matches = search_regex(&db, "demo-0.1.0", "PARSE", &options).unwrap();
+        assert_eq!(format_matches(&matches, &options), [
+            "tests/parse.rs-1- #[test]",
+            "tests/parse.rs:2: fn parses() { demo::parse() }",
+        ]);
+
+        let options = SearchOptions { whole_word: true, excludes: vec!["lib.rs".to_string()], ..Default::default() };
+        let matches = search_regex(&db, "demo-0.1.0", "parses?", &options).unwrap();
+        assert_eq!(matches.iter().map(|m| m.file.as_str()).collect::<Vec<_>>(), ["tests/parse.rs"]);
+        let options = SearchOptions { excludes: vec!["tests/*".to_string(), "src/bin/**".to_string()], ..Default::default() };
+        let matches = search_regex(&db, "demo-0.1.0", "fn", &options).unwrap();
+        assert_eq!(matches.iter().map(|m| m.file.as_str()).collect::<Vec<_>>(), ["src/lib.rs"]);
+    }
+
+    #[test]
+    fn test_multiline_and_fixed_strings() {
+        let (_tmp, db) = demo_index(|_| {});
+        let options = SearchOptions { multiline: true, ..Default::default() };
+        let matches = search_regex(&db, "demo-0.1.0", r"test\]\s*\nfn", &options).unwrap();
+        assert_eq!(format_matches(&matches, &options), [
+            "tests/parse.rs:1:3-2:2: #[test]",
+            "tests/parse.rs:2: fn parses() { demo::parse() }",
+        ]);
+        // `^` still anchors at line starts
+        let matches = search_regex(&db, "demo-0.1.0", r"^fn \w+", &options).unwrap();
+        assert_eq!(matches.iter().map(|m| (m.file.as_str(), m.line)).collect::<Vec<_>>(), [("src/bin/tool.rs", 1), ("tests/parse.rs", 2)]);
+
+        let options = SearchOptions { fixed_strings: true, ..Default::default() };
+        let matches = search_regex(&db, "demo-0.1.0", "parse()", &options).unwrap();
+        assert_eq!(matches.iter().map(|m| (m.file.as_str(), m.column)).collect::<Vec<_>>(), [("src/lib.rs", 8), ("tests/parse.rs", 21)]);
+    }
+
+    #[test]
+    fn test_search_within_classes() {
+        let (_tmp, db) = demo_index(|_| {});
+        let lines = |pattern: &str, within: Vec<Class>| -> Vec<(String, usize)> {
+            let options = SearchOptions { ignore_case: true, within, ..Default::default() };
+            search_regex(&db, "demo-0.1.0", pattern, &options).unwrap().into_iter().map(|m| (m.file, m.line)).collect()
+        };
+        let at = |file: &str, line: usize| (file.to_string(), line);
+        assert_eq!(lines("parse", vec![Class::Doc]), [at("src/lib.rs", 1)]);
+        assert_eq!(lines("parse", vec![Class::Code]), [at("src/lib.rs", 2), at("tests/parse.rs", 2)]);
+        assert_eq!(lines("parse", vec![Class::Comment, Class::Doc]), [at("src/lib.rs", 1)]);
+        assert!(lines("parse", vec![Class::String]).is_empty());
+    }
+
+    #[test]
+    fn test_item_filter() {
+        let (_tmp, db) = demo_index(|_| {});
+        let items = ItemFilter { kind: Some("function".to_string()), name: Some("^parses$".to_string()), impl_trait: None };
+        let options = SearchOptions { items: Some(items), ..Default::default() };
+        let matches = search_regex(&db, "demo-0.1.0", r"parse", &options).unwrap();
+        let parses = db.get_functions("demo-0.1.0").unwrap().into_iter().find(|f| f.name == "parses").unwrap();
+        assert_eq!(format_matches(&matches, &options), [
+            format!("tests/parse.rs:2: [{}] fn parses() {{ demo::parse() }}", parses.id),
+        ]);
+
+        let items = ItemFilter { kind: Some("struct".to_string()), ..Default::default() };
+        let options = SearchOptions { items: Some(items), ..Default::default() };
+        assert!(search_regex(&db, "demo-0.1.0", r"parse", &options).unwrap().is_empty());
+        let items = ItemFilter { kind: Some("module".to_string()), ..Default::default() };
+        let options = SearchOptions { items: Some(items), ..Default::default() };
+        assert!(search_regex(&db, "demo-0.1.0", r"parse", &options).is_err());
+
+        assert!(trait_matches("std :: ops :: Drop", "Drop"));
+        assert!(trait_matches("From < String >", "From"));
+        assert!(trait_matches("From < String >", "From<String>"));
+        assert!(!trait_matches("From < String >", "From<&str>"));
+        assert!(trait_matches("Display", "std::fmt::Display"));
+        assert!(!trait_matches("MyDrop", "Drop"));
+    }
+
+    #[test]
+    fn test_structural_search() {
+        let (_tmp, db) = demo_index(|_| {});
+        let pattern = Pattern::parse("fn $name()").unwrap();
+        let items = search_structural(&db, "demo-0.1.0", &pattern).unwrap();
+        let names: Vec<&str> = items.iter().map(|m| m.bindings["name"].as_str()).collect();
+        assert_eq!(names, ["main", "parse", "parses"]);
+        let parse = db.get_functions("demo-0.1.0").unwrap().into_iter().find(|f| f.name == "parse").unwrap();
+        assert_eq!((items[1].id.as_str(), items[1].text.as_str()), (parse.id.as_str(), "pub fn parse() {}"));
+        assert_eq!(format_structural_match(&items[1]), format!("[{}] function src/lib.rs:2: pub fn parse() {{}}\n    $name = parse", parse.id));
+
+        // The pattern's literals narrow the files down before parsing
+        let pattern = Pattern::parse("fn main()").unwrap();
+        let items = search_structural(&db, "demo-0.1.0", &pattern).unwrap();
+        assert_eq!(items.iter().map(|m| m.file.as_str()).collect::<Vec<_>>(), ["src/bin/tool.rs"]);
+    }
+}
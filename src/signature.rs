@@ -0,0 +1,349 @@
+//! Function types for type-directed search, in the spirit of Hoogle: signatures are
+//! reduced at index time to the shapes of their parameters and return type, and
+//! a query like `(&str) -> Result<_, _>` is ranked against them by how closely
+//! each part fits.
+//!
+//! Types are named by their last path segment (`io::Error` is `Error`), lifetimes
+//! are dropped, receivers become `Self`, `&Self` or `&mut Self`, and generic
+//! parameters become their bounds (`R: AsyncRead` is `impl AsyncRead`) or, without
+//! bounds, wildcards. Single capital letters are treated as type variables too.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use syn::{FnArg, GenericArgument, GenericParam, PathArguments, ReturnType, Signature, Type, TypeParamBound, WherePredicate};
+
+/// Parameter lists longer than this are only matched in order
+const MAX_UNORDERED_PARAMS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Ty {
+    /// `_`, or a generic parameter without bounds
+    Any,
+    Path { name: String, args: Vec<Ty> },
+    /// `Item = T` in the arguments of a path
+    Binding { name: String, ty: Box<Ty> },
+    Ref { mutable: bool, ty: Box<Ty> },
+    /// `impl A + B`, or a generic parameter bounded by `A + B`
+    Impl(Vec<Ty>),
+    Dyn(Vec<Ty>),
+    /// `()` is the empty tuple
+    Tuple(Vec<Ty>),
+    Slice(Box<Ty>),
+    Array(Box<Ty>),
+    /// Anything else (function pointers, `!`, macros, ...), compared as text
+    Other(String),
+}
+
+/// Parameter and return types of a function
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypeSignature {
+    pub inputs: Vec<Ty>,
+    pub output: Ty,
+    /// Whether the first input is `self`
+    pub receiver: bool,
+}
+
+/// A type search: parameter types to look for (`None` for any) and the return type
+#[derive(Debug, PartialEq)]
+pub struct TypeQuery {
+    pub inputs: Option<Vec<Ty>>,
+    pub output: Option<Ty>,
+}
+
+impl TypeSignature {
+    pub fn from_sig(sig: &Signature) -> TypeSignature {
+        let mut generics: HashMap<String, Vec<Ty>> = HashMap::new();
+        for param in &sig.generics.params {
+            if let GenericParam::Type(param) = param {
+                generics.entry(param.ident.to_string()).or_default().extend(bounds(&param.bounds, &HashMap::new()));
+            }
+        }
+        for predicate in sig.generics.where_clause.iter().flat_map(|w| &w.predicates) {
+            if let WherePredicate::Type(predicate) = predicate {
+                if let Some(name) = single_ident(&predicate.bounded_ty) {
+                    if let Some(existing) = generics.get_mut(&name) {
+                        existing.extend(bounds(&predicate.bounds, &HashMap::new()));
+                    }
+                }
+            }
+        }
+
+        // `&self` has the type `&Self`
+        let inputs = sig.inputs.iter().map(|arg| match arg {
+            FnArg::Receiver(receiver) => normalize(&receiver.ty, &generics),
+            FnArg::Typed(arg) => normalize(&arg.ty, &generics),
+        }).collect();
+        let output = match &sig.output {
+            ReturnType::Default => Ty::Tuple(Vec::new()),
+            ReturnType::Type(_, ty) => normalize(ty, &generics),
+        };
+
+        TypeSignature { inputs, output, receiver: sig.receiver().is_some() }
+    }
+
+    /// How far the function is from the query (0 is an exact fit), or `None` if
+    /// it doesn't match at all
+    pub fn distance(&self, query: &TypeQuery) -> Option<u32> {
+        let mut distance = 0;
+        if let Some(output) = &query.output {
+            distance += type_distance(output, &self.output)?;
+        }
+        if let Some(inputs) = &query.inputs {
+            distance += self.inputs_distance(inputs)?;
+        }
+        Some(distance)
+    }
+
+    /// Every queried parameter must fit a different parameter of the function.
+    /// Parameters left over cost 2 each (1 for the receiver), and matching them
+    /// out of order costs 1.
+    fn inputs_distance(&self, query: &[Ty]) -> Option<u32> {
+        let (n, m) = (query.len(), self.inputs.len());
+        if n > m {
+            return None;
+        }
+        let unused = |j: usize| if j == 0 && self.receiver { 1 } else { 2 };
+        let costs: Vec<Vec<Option<u32>>> = query.iter()
+            .map(|q| self.inputs.iter().map(|t| type_distance(q, t)).collect())
+            .collect();
+
+        // In order: best[i][j] fits the first i queried types into the first j parameters
+        let mut best = vec![vec![None; m + 1]; n + 1];
+        best[0][0] = Some(0);
+        for j in 1..=m {
+            best[0][j] = best[0][j - 1].map(|d: u32| d + unused(j - 1));
+        }
+        for i in 1..=n {
+            for j in i..=m {
+                let skip = best[i][j - 1].map(|d| d + unused(j - 1));
+                let fit = best[i - 1][j - 1].zip(costs[i - 1][j - 1]).map(|(d, c)| d + c);
+                best[i][j] = [skip, fit].into_iter().flatten().min();
+            }
+        }
+        let ordered = best[n][m];
+
+        // Any order: cheapest assignment over the sets of parameters used so far
+        let unordered = (m <= MAX_UNORDERED_PARAMS).then(|| {
+            let mut by_used: Vec<Option<u32>> = vec![None; 1 << m];
+            by_used[0] = Some(0);
+            for used in 0..1usize << m {
+                let (Some(d), i) = (by_used[used], used.count_ones() as usize) else { continue };
+                if i == n {
+                    continue;
+                }
+                for j in (0..m).filter(|j| used & (1 << j) == 0) {
+                    if let Some(c) = costs[i][j] {
+                        let next = &mut by_used[used | (1 << j)];
+                        *next = Some(next.map_or(d + c, |e| e.min(d + c)));
+                    }
+                }
+            }
+            (0..1usize << m)
+                .filter(|used| used.count_ones() as usize == n)
+                .filter_map(|used| {
+                    let left_over: u32 = (0..m).filter(|j| used & (1 << j) == 0).map(unused).sum();
+                    by_used[used].map(|d| d + left_over + 1)
+                })
+                .min()
+        }).flatten();
+
+        [ordered, unordered].into_iter().flatten().min()
+    }
+}
+
+impl TypeQuery {
+    /// Parse `(A, B) -> C`; either side may be left out (`(A, B)`, `-> C`), and `_`
+    /// matches any type
+    pub fn parse(query: &str) -> Result<TypeQuery> {
+        let (inputs, output) = match split_arrow(query) {
+            Some(i) => (query[..i].trim(), Some(query[i + 2..].trim())),
+            None => (query.trim(), None),
+        };
+        let parse_type = |text: &str| {
+            syn::parse_str::<Type>(text)
+                .map_err(|e| anyhow::anyhow!("Invalid type '{}' in signature query: {}", text, e))
+        };
+
+        let inputs = if inputs.is_empty() {
+            None
+        } else {
+            let no_generics = HashMap::new();
+            Some(match parse_type(inputs)? {
+                Type::Paren(ty) => vec![normalize(&ty.elem, &no_generics)],
+                Type::Tuple(tuple) => tuple.elems.iter().map(|ty| normalize(ty, &no_generics)).collect(),
+                ty => vec![normalize(&ty, &no_generics)],
+            })
+        };
+        let output = match output {
+            Some("") => anyhow::bail!("Missing return type after `->` in signature query"),
+            Some(text) => Some(normalize(&parse_type(text)?, &HashMap::new())),
+            None => None,
+        };
+        if inputs.is_none() && output.is_none() {
+            anyhow::bail!("Empty signature query; expected e.g. \"(&str) -> Result<_, _>\"");
+        }
+        Ok(TypeQuery { inputs, output })
+    }
+}
+
+/// Byte position of the `->` separating parameters from the return type
+fn split_arrow(query: &str) -> Option<usize> {
+    let bytes = query.as_bytes();
+    let mut depth = 0i32;
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'(' | b'[' | b'<' => depth += 1,
+            b'>' if i > 0 && bytes[i - 1] == b'-' => {}
+            b')' | b']' | b'>' => depth -= 1,
+            b'-' if depth == 0 && bytes.get(i + 1) == Some(&b'>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn normalize(ty: &Type, generics: &HashMap<String, Vec<Ty>>) -> Ty {
+    match ty {
+        Type::Infer(_) => Ty::Any,
+        Type::Paren(ty) => normalize(&ty.elem, generics),
+        Type::Group(ty) => normalize(&ty.elem, generics),
+        Type::Reference(r) => Ty::Ref { mutable: r.mutability.is_some(), ty: Box::new(normalize(&r.elem, generics)) },
+        Type::Tuple(tuple) => Ty::Tuple(tuple.elems.iter().map(|ty| normalize(ty, generics)).collect()),
+        Type::Slice(slice) => Ty::Slice(Box::new(normalize(&slice.elem, generics))),
+        Type::Array(array) => Ty::Array(Box::new(normalize(&array.elem, generics))),
+        Type::ImplTrait(ty) => Ty::Impl(bounds(&ty.bounds, generics)),
+        Type::TraitObject(ty) => Ty::Dyn(bounds(&ty.bounds, generics)),
+        Type::Path(path) if path.qself.is_none() => {
+            if let Some(name) = single_ident(ty) {
+                if let Some(bounds) = generics.get(&name) {
+                    return if bounds.is_empty() { Ty::Any } else { Ty::Impl(bounds.clone()) };
+                }
+                if name.len() == 1 && name.chars().all(|c| c.is_ascii_uppercase()) {
+                    return Ty::Any;
+                }
+            }
+            normalize_path(&path.path, generics)
+        }
+        _ => Ty::Other(quote::quote!(#ty).to_string().replace(' ', "")),
+    }
+}
+
+fn normalize_path(path: &syn::Path, generics: &HashMap<String, Vec<Ty>>) -> Ty {
+    let Some(last) = path.segments.last() else { return Ty::Other(String::new()) };
+    let args = match &last.arguments {
+        PathArguments::None => Vec::new(),
+        PathArguments::AngleBracketed(args) => args.args.iter().filter_map(|arg| match arg {
+            GenericArgument::Type(ty) => Some(normalize(ty, generics)),
+            GenericArgument::AssocType(assoc) => Some(Ty::Binding {
+                name: assoc.ident.to_string(),
+                ty: Box::new(normalize(&assoc.ty, generics)),
+            }),
+            _ => None,
+        }).collect(),
+        // `Fn(A) -> B` and friends
+        PathArguments::Parenthesized(_) => return Ty::Other(quote::quote!(#last).to_string().replace(' ', "")),
+    };
+    Ty::Path { name: last.ident.to_string(), args }
+}
+
+fn bounds<'a>(bounds: impl IntoIterator<Item = &'a TypeParamBound>, generics: &HashMap<String, Vec<Ty>>) -> Vec<Ty> {
+    bounds.into_iter().filter_map(|bound| match bound {
+        TypeParamBound::Trait(bound) if matches!(bound.modifier, syn::TraitBoundModifier::None) => {
+            Some(normalize_path(&bound.path, generics))
+        }
+        _ => None,
+    }).collect()
+}
+
+fn single_ident(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.get_ident().map(|ident| ident.to_string()),
+        _ => None,
+    }
+}
+
+/// How far `target` is from the queried type, or `None` if it doesn't fit
+fn type_distance(query: &Ty, target: &Ty) -> Option<u32> {
+    match (query, target) {
+        (Ty::Any, _) => Some(0),
+        // A generic parameter accepts the queried type, but less specifically
+        (_, Ty::Any) => Some(2),
+        (Ty::Impl(q), Ty::Impl(t)) | (Ty::Dyn(q), Ty::Dyn(t)) => bounds_distance(q, t),
+        (Ty::Impl(q), Ty::Dyn(t)) | (Ty::Dyn(q), Ty::Impl(t)) => Some(bounds_distance(q, t)? + 1),
+        (_, Ty::Impl(_)) => Some(3),
+        (Ty::Ref { mutable: qm, ty: q }, Ty::Ref { mutable: tm, ty: t }) => {
+            Some(type_distance(q, t)? + if qm == tm { 0 } else { 2 })
+        }
+        (Ty::Ref { ty: q, .. }, t) => Some(type_distance(q, t)? + 2),
+        (q, Ty::Ref { ty: t, .. }) => Some(type_distance(q, t)? + 2),
+        (Ty::Path { name: qn, args: qa }, Ty::Path { name: tn, args: ta }) if qn == tn => {
+            if qa.is_empty() {
+                Some(if ta.is_empty() { 0 } else { 1 })
+            } else if qa.len() == ta.len() {
+                qa.iter().zip(ta).map(|(q, t)| type_distance(q, t)).sum()
+            } else {
+                None
+            }
+        }
+        (Ty::Binding { name: qn, ty: q }, Ty::Binding { name: tn, ty: t }) if qn == tn => type_distance(q, t),
+        (Ty::Tuple(q), Ty::Tuple(t)) if q.len() == t.len() => q.iter().zip(t).map(|(q, t)| type_distance(q, t)).sum(),
+        (Ty::Slice(q), Ty::Slice(t)) | (Ty::Array(q), Ty::Array(t)) => type_distance(q, t),
+        (Ty::Slice(q), Ty::Array(t)) | (Ty::Array(q), Ty::Slice(t)) => Some(type_distance(q, t)? + 1),
+        (Ty::Other(q), Ty::Other(t)) if q == t => Some(0),
+        _ => None,
+    }
+}
+
+/// Every queried bound must be among the target's; extra bounds cost 1 each
+fn bounds_distance(query: &[Ty], target: &[Ty]) -> Option<u32> {
+    let fitted: u32 = query.iter()
+        .map(|q| target.iter().filter_map(|t| type_distance(q, t)).min())
+        .sum::<Option<u32>>()?;
+    Some(fitted + target.len().saturating_sub(query.len()) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(source: &str) -> TypeSignature {
+        TypeSignature::from_sig(&syn::parse_str::<syn::ItemFn>(&format!("{} {{}}", source)).unwrap().sig)
+    }
+
+    fn distance(query: &str, source: &str) -> Option<u32> {
+        signature(source).distance(&TypeQuery::parse(query).unwrap())
+    }
+
+    #[test]
+    fn test_normalized_signatures() {
+        let path = |name: &str| Ty::Path { name: name.to_string(), args: Vec::new() };
+        let sig = signature("fn read<'a, R: AsyncRead>(&'a mut self, reader: R, buf: &'a [u8]) -> io::Result<usize>");
+        assert!(sig.receiver);
+        assert_eq!(sig.inputs, [
+            Ty::Ref { mutable: true, ty: Box::new(path("Self")) },
+            Ty::Impl(vec![path("AsyncRead")]),
+            Ty::Ref { mutable: false, ty: Box::new(Ty::Slice(Box::new(path("u8")))) },
+        ]);
+        assert_eq!(sig.output, Ty::Path { name: "Result".to_string(), args: vec![path("usize")] });
+        assert_eq!(signature("fn f<T>(t: T) where T: Clone").inputs, [Ty::Impl(vec![path("Clone")])]);
+        assert_eq!(signature("fn f<T>(t: T)").inputs, [Ty::Any]);
+    }
+
+    #[test]
+    fn test_ranking() {
+        let query = "(&str) -> Result<_, _>";
+        assert_eq!(distance(query, "fn parse(s: &str) -> Result<Value, Error>"), Some(0));
+        assert_eq!(distance(query, "fn parse(s: &str, strict: bool) -> Result<Value, Error>"), Some(2));
+        assert_eq!(distance(query, "fn parse(&self, s: &str) -> Result<Value, Error>"), Some(1));
+        assert_eq!(distance(query, "fn parse<S: AsRef<str>>(s: S) -> Result<Value, Error>"), Some(3));
+        assert_eq!(distance(query, "fn parse(s: &str) -> Option<Value>"), None);
+        assert_eq!(distance(query, "fn parse() -> Result<Value, Error>"), None);
+
+        // Parameters may come in any order, at a small cost
+        assert_eq!(distance("(u8, &str)", "fn f(a: &str, b: u8)"), Some(1));
+        assert_eq!(distance("(impl AsyncRead) -> Vec<u8>", "async fn f<R: AsyncRead + Unpin>(r: R) -> Vec<u8>"), Some(1));
+        assert_eq!(distance("-> Vec<u8>", "fn f(a: &str, b: u8) -> Vec<u8>"), Some(0));
+        assert!(TypeQuery::parse("(&str) ->").is_err());
+    }
+}
@@ -1,10 +1,28 @@
 use anyhow::{Context, Result};
-use rusqlite::{params, Connection, OptionalExtension};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Transaction, TransactionBehavior};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::config;
+use crate::indexer::INDEX_FORMAT_VERSION;
+use crate::signature::TypeSignature;
+use crate::trigram::{self, Trigram};
 
-const INDEX_DIR: &str = ".crate-indexer";
 const DB_FILE: &str = "index.db";
 
+/// How long to wait for another connection (or process) to release a write lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Idle connections kept open by the pool
+const MAX_IDLE_CONNECTIONS: usize = 8;
+/// zstd level for stored file contents (favors indexing speed; source text compresses well anyway)
+const CONTENTS_COMPRESSION_LEVEL: i32 = 3;
+
+static POOL: OnceLock<Mutex<Vec<Database>>> = OnceLock::new();
+
 // Type alias for common item row pattern: (id, name, file, line, end_line, visibility, docs)
 type ItemRow = (String, String, String, usize, Option<usize>, String, Option<String>);
 
@@ -17,6 +35,8 @@ pub struct FunctionInfo {
     pub end_line: Option<usize>,
     pub signature: String,
     pub docs: Option<String>,
+    /// Parameter and return types for type search; missing if not stored
+    pub type_signature: Option<TypeSignature>,
 }
 
 #[derive(Debug, Clone)]
@@ -114,6 +134,120 @@ pub struct ImplInfo {
     pub trait_name: Option<String>,
 }
 
+/// Where an item of any kind lies, for searching inside items
+#[derive(Debug, Clone)]
+pub struct ItemSpan {
+    pub id: String,
+    pub kind: String,
+    /// The item's name, or its self type for an impl
+    pub name: String,
+    pub file: String,
+    pub line: usize,
+    pub end_line: usize,
+    pub trait_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DependencyInfo {
+    pub name: String,           // Package name on crates.io (after `package = "..."` renames)
+    pub req: String,            // Version requirement, e.g. "^1.0"
+    pub kind: String,           // "normal", "build" or "dev"
+    pub optional: bool,
+    pub target: Option<String>, // Platform of a `[target.'cfg(unix)'.dependencies]` entry
+    pub alias: Option<String>,  // Name the crate is used under when `package` renames it
+}
+
+impl DependencyInfo {
+    /// What sets the declaration apart from a plain dependency, e.g. ` [dev, cfg(unix), as serde1]`;
+    /// empty for a normal, non-optional one
+    pub fn flags(&self) -> String {
+        let kind = (self.kind != "normal").then(|| self.kind.clone());
+        let alias = self.alias.as_ref().map(|alias| format!("as {}", alias));
+        let optional = self.optional.then(|| "optional".to_string());
+        let flags: Vec<String> = [kind, self.target.clone(), alias, optional].into_iter().flatten().collect();
+        if flags.is_empty() { String::new() } else { format!(" [{}]", flags.join(", ")) }
+    }
+}
+
+/// Package metadata from a crate's Cargo.toml
+#[derive(Debug, Clone, Default)]
+pub struct PackageMetadata {
+    pub description: Option<String>,
+    pub license: Option<String>,
+    pub repository: Option<String>,
+    pub homepage: Option<String>,
+    pub documentation: Option<String>,
+    pub edition: Option<String>,
+    pub rust_version: Option<String>,
+    pub authors: Vec<String>,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+    pub features: BTreeMap<String, Vec<String>>,
+}
+
+impl PackageMetadata {
+    /// Overview of a crate shown by `info` and the MCP `crate_info` tool
+    pub fn summary(&self, crate_key: &str, deps: &[DependencyInfo]) -> String {
+        let mut output = format!("{}\n", crate_key);
+        if let Some(description) = &self.description {
+            output.push_str(&format!("  {}\n", description));
+        }
+        output.push('\n');
+
+        let fields = [
+            ("License", self.license.clone()),
+            ("Edition", self.edition.clone()),
+            ("Rust version", self.rust_version.clone()),
+            ("Repository", self.repository.clone()),
+            ("Homepage", self.homepage.clone()),
+            ("Documentation", self.documentation.clone()),
+            ("Authors", Some(self.authors.join(", ")).filter(|s| !s.is_empty())),
+            ("Keywords", Some(self.keywords.join(", ")).filter(|s| !s.is_empty())),
+            ("Categories", Some(self.categories.join(", ")).filter(|s| !s.is_empty())),
+        ];
+        for (label, value) in fields {
+            output.push_str(&format!("{:<15}{}\n", format!("{}:", label), value.as_deref().unwrap_or("-")));
+        }
+
+        if !self.features.is_empty() {
+            output.push_str("\nFeatures:\n");
+            for (feature, enables) in &self.features {
+                output.push_str(&format!("  {} = [{}]\n", feature, enables.join(", ")));
+            }
+        }
+
+        if !deps.is_empty() {
+            output.push_str("\nDependencies:\n");
+            for dep in deps {
+                output.push_str(&format!("  {} {}{}\n", dep.name, dep.req, dep.flags()));
+            }
+        }
+        output
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub id: String,
+    pub item_type: String,
+    pub name: String,
+    pub crate_key: String,
+    pub file: String,
+    pub line: usize,
+    pub snippet: String, // Best-matching text with matches wrapped in `**`
+    score: f64,          // BM25, lower is better
+}
+
+#[derive(Debug, Clone)]
+pub struct CrateSummary {
+    pub key: String,
+    pub path: PathBuf,
+    pub fetched_at: Option<i64>, // Unix time of the first index; None for crates indexed before it was recorded
+    pub item_count: usize,
+    pub has_embeddings: bool,
+    pub shared: bool, // Comes from a read-only shared index
+}
+
 #[derive(Debug, Clone)]
 pub struct EmbeddingInfo {
     pub id: String,
@@ -123,6 +257,29 @@ pub struct EmbeddingInfo {
     pub crate_key: String,
 }
 
+/// Qualified path of an item within its crate (e.g. `de::Deserializer::new`)
+#[derive(Debug, Clone)]
+pub struct ItemPath {
+    pub id: String,
+    pub kind: String,
+    pub path: String,
+}
+
+/// A file of a crate as of its last indexing
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: String,
+    pub hash: String,               // SHA-256 of the contents; empty for files of unknown type, which aren't read
+    pub size: u64,
+    pub lines: usize,
+    pub language: Option<String>,
+    pub target: Option<String>,     // lib, bin, example, test, bench or build (Rust files only)
+    pub reexports: Vec<String>,     // Modules re-exported with `pub use`, before filtering by dependencies
+    pub contents: Option<Vec<u8>>,  // Compressed with `pack_contents`; never loaded when listing files
+    pub trigrams: Vec<Trigram>,     // Rust files only; never loaded from the database
+    pub reused: bool,               // Unchanged since the last index; its rows are already stored
+}
+
 // Container for all indexed items from a crate
 #[derive(Debug, Default)]
 pub struct CrateItems {
@@ -134,197 +291,561 @@ pub struct CrateItems {
     pub type_aliases: Vec<TypeAliasInfo>,
     pub constants: Vec<ConstantInfo>,
     pub impls: Vec<ImplInfo>,
+    pub dependencies: Vec<DependencyInfo>,
+    pub metadata: PackageMetadata,
+    pub paths: Vec<ItemPath>,
+    pub files: Vec<SourceFile>,
 }
 
+/// Schema migrations, applied in order inside one transaction. `PRAGMA user_version`
+/// records how many have run. Append new migrations; never edit a released one.
+/// Migrations 1-4 use `IF NOT EXISTS` because databases created before versioning
+/// (user_version 0) may already contain those tables.
+const MIGRATIONS: &[&str] = &[
+    // 1: items, re-exports and embeddings
+    "
+    CREATE TABLE IF NOT EXISTS crates (
+        id INTEGER PRIMARY KEY,
+        key TEXT UNIQUE NOT NULL,
+        path TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS functions (
+        id TEXT PRIMARY KEY,
+        crate_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        file TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        end_line INTEGER,
+        signature TEXT NOT NULL,
+        docs TEXT,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS structs (
+        id TEXT PRIMARY KEY,
+        crate_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        file TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        end_line INTEGER,
+        visibility TEXT NOT NULL,
+        docs TEXT,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS struct_fields (
+        id INTEGER PRIMARY KEY,
+        struct_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        type_str TEXT NOT NULL,
+        visibility TEXT NOT NULL,
+        docs TEXT,
+        FOREIGN KEY (struct_id) REFERENCES structs(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS enums (
+        id TEXT PRIMARY KEY,
+        crate_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        file TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        end_line INTEGER,
+        visibility TEXT NOT NULL,
+        docs TEXT,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS enum_variants (
+        id INTEGER PRIMARY KEY,
+        enum_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        fields TEXT,
+        docs TEXT,
+        FOREIGN KEY (enum_id) REFERENCES enums(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS traits (
+        id TEXT PRIMARY KEY,
+        crate_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        file TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        end_line INTEGER,
+        visibility TEXT NOT NULL,
+        docs TEXT,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS macros (
+        id TEXT PRIMARY KEY,
+        crate_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        file TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        end_line INTEGER,
+        kind TEXT NOT NULL,
+        docs TEXT,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS type_aliases (
+        id TEXT PRIMARY KEY,
+        crate_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        file TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        type_str TEXT NOT NULL,
+        visibility TEXT NOT NULL,
+        docs TEXT,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS constants (
+        id TEXT PRIMARY KEY,
+        crate_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        file TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        type_str TEXT NOT NULL,
+        visibility TEXT NOT NULL,
+        docs TEXT,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS impls (
+        id TEXT PRIMARY KEY,
+        crate_id INTEGER NOT NULL,
+        file TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        end_line INTEGER,
+        self_type TEXT NOT NULL,
+        trait_name TEXT,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS reexports (
+        crate_id INTEGER NOT NULL,
+        reexported_crate TEXT NOT NULL,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE,
+        PRIMARY KEY (crate_id, reexported_crate)
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_functions_crate ON functions(crate_id);
+    CREATE INDEX IF NOT EXISTS idx_functions_name ON functions(name);
+    CREATE INDEX IF NOT EXISTS idx_structs_crate ON structs(crate_id);
+    CREATE INDEX IF NOT EXISTS idx_structs_name ON structs(name);
+    CREATE INDEX IF NOT EXISTS idx_enums_crate ON enums(crate_id);
+    CREATE INDEX IF NOT EXISTS idx_enums_name ON enums(name);
+    CREATE INDEX IF NOT EXISTS idx_traits_crate ON traits(crate_id);
+    CREATE INDEX IF NOT EXISTS idx_traits_name ON traits(name);
+    CREATE INDEX IF NOT EXISTS idx_macros_crate ON macros(crate_id);
+    CREATE INDEX IF NOT EXISTS idx_macros_name ON macros(name);
+    CREATE INDEX IF NOT EXISTS idx_type_aliases_crate ON type_aliases(crate_id);
+    CREATE INDEX IF NOT EXISTS idx_constants_crate ON constants(crate_id);
+    CREATE INDEX IF NOT EXISTS idx_impls_crate ON impls(crate_id);
+    CREATE INDEX IF NOT EXISTS idx_impls_self_type ON impls(self_type);
+    CREATE INDEX IF NOT EXISTS idx_reexports_crate ON reexports(crate_id);
+
+    CREATE TABLE IF NOT EXISTS embeddings (
+        id TEXT PRIMARY KEY,
+        item_type TEXT NOT NULL,
+        crate_id INTEGER NOT NULL,
+        embedding BLOB NOT NULL,
+        text_content TEXT NOT NULL,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    );
+    CREATE INDEX IF NOT EXISTS idx_embeddings_crate ON embeddings(crate_id);
+    CREATE INDEX IF NOT EXISTS idx_embeddings_type ON embeddings(item_type);
+    ",
+    // 2: latest-version cache
+    "
+    CREATE TABLE IF NOT EXISTS latest_versions (
+        crate_name TEXT PRIMARY KEY,
+        version TEXT NOT NULL,
+        checked_at INTEGER NOT NULL
+    );
+    ",
+    // 3: declared dependencies
+    "
+    CREATE TABLE IF NOT EXISTS dependencies (
+        crate_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        req TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        optional INTEGER NOT NULL,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE,
+        PRIMARY KEY (crate_id, name, kind)
+    );
+    CREATE INDEX IF NOT EXISTS idx_dependencies_name ON dependencies(name);
+    ",
+    // 4: Cargo.toml package metadata
+    "
+    CREATE TABLE IF NOT EXISTS crate_metadata (
+        crate_id INTEGER PRIMARY KEY,
+        description TEXT,
+        license TEXT,
+        repository TEXT,
+        homepage TEXT,
+        documentation TEXT,
+        edition TEXT,
+        rust_version TEXT,
+        authors TEXT NOT NULL,    -- JSON array
+        keywords TEXT NOT NULL,   -- JSON array
+        categories TEXT NOT NULL, -- JSON array
+        features TEXT NOT NULL,   -- JSON object: feature -> enabled features/dependencies
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    );
+    ",
+    // 5: indexer output format per crate; older crates are re-indexed from their sources
+    "
+    ALTER TABLE crates ADD COLUMN index_version INTEGER NOT NULL DEFAULT 0;
+    ",
+    // 6: full-text search over item names, signatures, fields and docs (filled by re-indexing)
+    "
+    CREATE VIRTUAL TABLE item_search USING fts5(
+        name,
+        signature,
+        fields,
+        docs,
+        item_id UNINDEXED,
+        item_type UNINDEXED,
+        crate_id UNINDEXED,
+        file UNINDEXED,
+        line UNINDEXED
+    );
+    ",
+    // 7: when a crate was first indexed (NULL for crates indexed before this migration)
+    "
+    ALTER TABLE crates ADD COLUMN fetched_at INTEGER;
+    ",
+    // 8: qualified item paths, for selectors like `serde::de::Deserializer`. The
+    // primary key also catches ID collisions between different kinds of items.
+    "
+    CREATE TABLE item_paths (
+        item_id TEXT PRIMARY KEY,
+        crate_id INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        path TEXT NOT NULL,
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    );
+    CREATE INDEX idx_item_paths_crate ON item_paths(crate_id, path);
+    ",
+    // 9: source files with content hashes, so re-indexing only parses changed files
+    "
+    CREATE TABLE files (
+        crate_id INTEGER NOT NULL,
+        path TEXT NOT NULL,
+        hash TEXT NOT NULL,      -- SHA-256 of the contents
+        reexports TEXT NOT NULL, -- JSON array of modules re-exported with `pub use`
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE,
+        PRIMARY KEY (crate_id, path)
+    );
+    ",
+    // 10: every file of a crate (not just Rust sources), with optional compressed contents
+    "
+    ALTER TABLE files ADD COLUMN size INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE files ADD COLUMN lines INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE files ADD COLUMN language TEXT;
+    ALTER TABLE files ADD COLUMN target TEXT;  -- Cargo target kind of Rust files
+    ALTER TABLE files ADD COLUMN contents BLOB; -- zstd-compressed text, NULL if not stored
+    ",
+    // 11: trigram index over each crate's Rust files, to narrow down regex searches
+    "
+    CREATE TABLE trigrams (
+        crate_id INTEGER NOT NULL,
+        trigram INTEGER NOT NULL,
+        postings BLOB NOT NULL, -- positions among the crate's Rust files sorted by path
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE,
+        PRIMARY KEY (crate_id, trigram)
+    ) WITHOUT ROWID;
+    ",
+    // 12: normalized parameter and return types of functions, for type search
+    "
+    ALTER TABLE functions ADD COLUMN type_signature TEXT; -- JSON of signature::TypeSignature
+    ",
+    // 13: a crate may declare a dependency once per kind, platform and rename, so
+    // those are part of the key ('' when not set, as NULLs would never collide)
+    "
+    CREATE TABLE dependencies_keyed (
+        crate_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        req TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        optional INTEGER NOT NULL,
+        target TEXT NOT NULL DEFAULT '', -- e.g. cfg(unix), '' for every platform
+        alias TEXT NOT NULL DEFAULT '',  -- Cargo.toml key of a renamed dependency
+        FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE,
+        PRIMARY KEY (crate_id, name, kind, target, alias)
+    );
+    INSERT INTO dependencies_keyed (crate_id, name, req, kind, optional)
+        SELECT crate_id, name, req, kind, optional FROM dependencies;
+    DROP TABLE dependencies;
+    ALTER TABLE dependencies_keyed RENAME TO dependencies;
+    CREATE INDEX idx_dependencies_name ON dependencies(name);
+    ",
+];
+
+/// Schema version of databases written by this build
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+/// Tables holding per-crate rows keyed by `crate_id` (struct fields and enum
+/// variants hang off structs and enums and are deleted with them)
+const CRATE_TABLES: &[&str] = &[
+    "embeddings", "functions", "structs", "enums", "traits", "macros", "type_aliases",
+    "constants", "impls", "reexports", "dependencies", "crate_metadata", "item_search", "item_paths", "files",
+    "trigrams",
+];
+
+/// Per-crate tables whose rows come from a single source file (`file` column)
+const FILE_TABLES: &[&str] = &[
+    "functions", "structs", "enums", "traits", "macros", "type_aliases", "constants", "impls", "item_search",
+];
+
+/// Item kinds stored in `item_search.item_type`
+pub const ITEM_KINDS: &[&str] = &[
+    "function", "struct", "enum", "trait", "macro", "type_alias", "constant", "impl",
+];
+
 pub struct Database {
     conn: Connection,
+    /// Read-only indexes that lookups fall through to, in priority order
+    shared: Vec<Database>,
+    /// Directory of a shared index, for finding crate sources recorded under another path
+    shared_dir: Option<PathBuf>,
+}
+
+/// A connection borrowed from the process-wide pool; returned to it on drop
+pub struct PooledDatabase(Option<Database>);
+
+/// Borrow a connection to the index database, reusing an idle one if possible.
+/// Meant for long-running processes (the MCP server) that open the database per request.
+pub fn pooled() -> Result<PooledDatabase> {
+    let pool = POOL.get_or_init(|| Mutex::new(Vec::new()));
+    let idle = pool.lock().unwrap_or_else(|e| e.into_inner()).pop();
+    let db = match idle {
+        Some(db) => db,
+        None => Database::open()?,
+    };
+    Ok(PooledDatabase(Some(db)))
+}
+
+impl Deref for PooledDatabase {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        self.0.as_ref().expect("connection taken")
+    }
+}
+
+impl Drop for PooledDatabase {
+    fn drop(&mut self) {
+        let (Some(db), Some(pool)) = (self.0.take(), POOL.get()) else {
+            return;
+        };
+        // Don't hand out a connection left inside a transaction by a panic
+        if !db.conn.is_autocommit() {
+            return;
+        }
+        let mut idle = pool.lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(db);
+        }
+    }
 }
 
 impl Database {
     pub fn open() -> Result<Self> {
-        let db_path = db_path();
-        if let Some(parent) = db_path.parent() {
-            std::fs::create_dir_all(parent)?;
+        let mut db = Self::open_at(&db_path())?;
+        for dir in &config::get().shared_indexes {
+            match Self::open_shared(dir) {
+                Ok(shared) => db.shared.push(shared),
+                Err(e) => eprintln!("Warning: skipping shared index {:?}: {:#}", dir, e),
+            }
         }
-
-        let conn = Connection::open(&db_path)
-            .with_context(|| format!("Failed to open database at {:?}", db_path))?;
-
-        let db = Self { conn };
-        db.init_schema()?;
         Ok(db)
     }
 
-    fn init_schema(&self) -> Result<()> {
-        self.conn.execute_batch(
-            "
-            CREATE TABLE IF NOT EXISTS crates (
-                id INTEGER PRIMARY KEY,
-                key TEXT UNIQUE NOT NULL,
-                path TEXT NOT NULL
-            );
-
-            CREATE TABLE IF NOT EXISTS functions (
-                id TEXT PRIMARY KEY,
-                crate_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                file TEXT NOT NULL,
-                line INTEGER NOT NULL,
-                end_line INTEGER,
-                signature TEXT NOT NULL,
-                docs TEXT,
-                FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+    /// Open another index read-only. It must have the current schema, since it can't be migrated.
+    fn open_shared(dir: &Path) -> Result<Self> {
+        let path = dir.join(DB_FILE);
+        if !path.exists() {
+            anyhow::bail!("{:?} does not exist", path);
+        }
+        let open = |uri: &str| -> Result<Self> {
+            let conn = Connection::open_with_flags(uri, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI)?;
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+            let db = Self { conn, shared: Vec::new(), shared_dir: Some(dir.to_path_buf()) };
+            db.schema_version()?;
+            Ok(db)
+        };
+        // A WAL database on a read-only mount can only be opened as an immutable snapshot
+        let uri = file_uri(&path);
+        let db = open(&uri).or_else(|_| open(&format!("{}?immutable=1", uri)))
+            .with_context(|| format!("Failed to open {:?}", path))?;
+
+        let version = db.schema_version()?;
+        if version != MIGRATIONS.len() {
+            anyhow::bail!(
+                "it has schema version {}, this build needs {} (open it once with this version to upgrade it)",
+                version,
+                MIGRATIONS.len()
             );
+        }
+        Ok(db)
+    }
 
-            CREATE TABLE IF NOT EXISTS structs (
-                id TEXT PRIMARY KEY,
-                crate_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                file TEXT NOT NULL,
-                line INTEGER NOT NULL,
-                end_line INTEGER,
-                visibility TEXT NOT NULL,
-                docs TEXT,
-                FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
-            );
+    /// This index followed by the shared ones
+    fn indexes(&self) -> impl Iterator<Item = &Database> {
+        std::iter::once(self).chain(&self.shared)
+    }
 
-            CREATE TABLE IF NOT EXISTS struct_fields (
-                id INTEGER PRIMARY KEY,
-                struct_id TEXT NOT NULL,
-                name TEXT NOT NULL,
-                type_str TEXT NOT NULL,
-                visibility TEXT NOT NULL,
-                docs TEXT,
-                FOREIGN KEY (struct_id) REFERENCES structs(id) ON DELETE CASCADE
-            );
+    /// The index holding a crate: this one if it has it (or no index does), else the first shared one
+    fn holder(&self, crate_key: &str) -> Result<&Database> {
+        if self.shared.is_empty() {
+            return Ok(self);
+        }
+        for db in self.indexes() {
+            if db.has_crate(crate_key)? {
+                return Ok(db);
+            }
+        }
+        Ok(self)
+    }
 
-            CREATE TABLE IF NOT EXISTS enums (
-                id TEXT PRIMARY KEY,
-                crate_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                file TEXT NOT NULL,
-                line INTEGER NOT NULL,
-                end_line INTEGER,
-                visibility TEXT NOT NULL,
-                docs TEXT,
-                FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
-            );
+    /// The index holding an item of the kind stored in `table`, like `holder`
+    fn item_holder(&self, table: &str, item_id: &str) -> Result<&Database> {
+        if self.shared.is_empty() {
+            return Ok(self);
+        }
+        let sql = format!("SELECT 1 FROM {} WHERE id = ?", table);
+        for db in self.indexes() {
+            let found = db.conn.query_row(&sql, [item_id], |_| Ok(())).optional()?;
+            if found.is_some() {
+                return Ok(db);
+            }
+        }
+        Ok(self)
+    }
 
-            CREATE TABLE IF NOT EXISTS enum_variants (
-                id INTEGER PRIMARY KEY,
-                enum_id TEXT NOT NULL,
-                name TEXT NOT NULL,
-                kind TEXT NOT NULL,
-                fields TEXT,
-                docs TEXT,
-                FOREIGN KEY (enum_id) REFERENCES enums(id) ON DELETE CASCADE
-            );
+    fn has_crate(&self, crate_key: &str) -> Result<bool> {
+        Ok(self.conn.query_row("SELECT 1 FROM crates WHERE key = ?", [crate_key], |_| Ok(()))
+            .optional()?
+            .is_some())
+    }
 
-            CREATE TABLE IF NOT EXISTS traits (
-                id TEXT PRIMARY KEY,
-                crate_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                file TEXT NOT NULL,
-                line INTEGER NOT NULL,
-                end_line INTEGER,
-                visibility TEXT NOT NULL,
-                docs TEXT,
-                FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
-            );
+    /// Open the index database at `db_path`, creating and upgrading it as needed
+    pub fn open_at(db_path: &Path) -> Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
 
-            CREATE TABLE IF NOT EXISTS macros (
-                id TEXT PRIMARY KEY,
-                crate_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                file TEXT NOT NULL,
-                line INTEGER NOT NULL,
-                end_line INTEGER,
-                kind TEXT NOT NULL,
-                docs TEXT,
-                FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
-            );
+        let conn = Connection::open(db_path)
+            .with_context(|| format!("Failed to open database at {:?}", db_path))?;
 
-            CREATE TABLE IF NOT EXISTS type_aliases (
-                id TEXT PRIMARY KEY,
-                crate_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                file TEXT NOT NULL,
-                line INTEGER NOT NULL,
-                type_str TEXT NOT NULL,
-                visibility TEXT NOT NULL,
-                docs TEXT,
-                FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
-            );
+        // Other processes' writes are waited out
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
 
-            CREATE TABLE IF NOT EXISTS constants (
-                id TEXT PRIMARY KEY,
-                crate_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                file TEXT NOT NULL,
-                line INTEGER NOT NULL,
-                kind TEXT NOT NULL,
-                type_str TEXT NOT NULL,
-                visibility TEXT NOT NULL,
-                docs TEXT,
-                FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
-            );
+        let db = Self { conn, shared: Vec::new(), shared_dir: None };
+        db.migrate()?;
+        Ok(db)
+    }
 
-            CREATE TABLE IF NOT EXISTS impls (
-                id TEXT PRIMARY KEY,
-                crate_id INTEGER NOT NULL,
-                file TEXT NOT NULL,
-                line INTEGER NOT NULL,
-                end_line INTEGER,
-                self_type TEXT NOT NULL,
-                trait_name TEXT,
-                FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
-            );
+    /// Bring the schema up to date, refusing databases written by a newer build
+    fn migrate(&self) -> Result<()> {
+        if self.schema_version()? == MIGRATIONS.len() {
+            return Ok(());
+        }
 
-            CREATE TABLE IF NOT EXISTS reexports (
-                crate_id INTEGER NOT NULL,
-                reexported_crate TEXT NOT NULL,
-                FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE,
-                PRIMARY KEY (crate_id, reexported_crate)
+        // The journal mode is stored in the database file, so it only needs setting
+        // when the database is created or upgraded
+        self.enable_wal();
+
+        // Take the write lock before re-reading the version, so two processes
+        // opening an old database don't both run the migrations
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        let version = self.schema_version()?;
+        if version > MIGRATIONS.len() {
+            anyhow::bail!(
+                "Index database has schema version {}, but this build only supports up to {}. \
+                 Upgrade crate-indexer or remove the database to rebuild it.",
+                version,
+                MIGRATIONS.len()
             );
+        }
 
-            CREATE INDEX IF NOT EXISTS idx_functions_crate ON functions(crate_id);
-            CREATE INDEX IF NOT EXISTS idx_functions_name ON functions(name);
-            CREATE INDEX IF NOT EXISTS idx_structs_crate ON structs(crate_id);
-            CREATE INDEX IF NOT EXISTS idx_structs_name ON structs(name);
-            CREATE INDEX IF NOT EXISTS idx_enums_crate ON enums(crate_id);
-            CREATE INDEX IF NOT EXISTS idx_enums_name ON enums(name);
-            CREATE INDEX IF NOT EXISTS idx_traits_crate ON traits(crate_id);
-            CREATE INDEX IF NOT EXISTS idx_traits_name ON traits(name);
-            CREATE INDEX IF NOT EXISTS idx_macros_crate ON macros(crate_id);
-            CREATE INDEX IF NOT EXISTS idx_macros_name ON macros(name);
-            CREATE INDEX IF NOT EXISTS idx_type_aliases_crate ON type_aliases(crate_id);
-            CREATE INDEX IF NOT EXISTS idx_constants_crate ON constants(crate_id);
-            CREATE INDEX IF NOT EXISTS idx_impls_crate ON impls(crate_id);
-            CREATE INDEX IF NOT EXISTS idx_impls_self_type ON impls(self_type);
-            CREATE INDEX IF NOT EXISTS idx_reexports_crate ON reexports(crate_id);
-
-            CREATE TABLE IF NOT EXISTS embeddings (
-                id TEXT PRIMARY KEY,
-                item_type TEXT NOT NULL,
-                crate_id INTEGER NOT NULL,
-                embedding BLOB NOT NULL,
-                text_content TEXT NOT NULL,
-                FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
-            );
-            CREATE INDEX IF NOT EXISTS idx_embeddings_crate ON embeddings(crate_id);
-            CREATE INDEX IF NOT EXISTS idx_embeddings_type ON embeddings(item_type);
-            ",
-        )?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            tx.execute_batch(migration)
+                .with_context(|| format!("Schema migration {} failed", i + 1))?;
+            tx.execute_batch(&format!("PRAGMA user_version = {}", i + 1))?;
+        }
+        tx.commit()?;
         Ok(())
     }
 
+    /// Switch to WAL, which lets readers run alongside a writer. Where it is unavailable
+    /// (e.g. filesystems without shared memory support) the rollback journal is kept.
+    fn enable_wal(&self) {
+        match self.conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0)) {
+            Ok(mode) if mode.eq_ignore_ascii_case("wal") => {}
+            Ok(mode) => eprintln!("Warning: WAL is unavailable for the index database, using journal mode {}", mode),
+            Err(e) => eprintln!("Warning: could not enable WAL for the index database: {}", e),
+        }
+    }
+
+    fn schema_version(&self) -> Result<usize> {
+        Ok(self.conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+
+    /// Write the given crates and everything indexed for them to a new database file
+    pub fn export_crates(&self, keys: &[String], dest: &Path) -> Result<()> {
+        let bundle = Self::open_at(dest)?;
+        // A single self-contained file, without -wal/-shm companions
+        bundle.conn.query_row("PRAGMA journal_mode = DELETE", [], |_| Ok(()))?;
+        drop(bundle);
+
+        self.conn.execute("ATTACH DATABASE ? AS bundle", [dest.to_string_lossy()])?;
+        let result = (|| {
+            let tx = self.conn.unchecked_transaction()?;
+            for key in keys {
+                copy_crate(&tx, "main", "bundle", key, None)?;
+            }
+            tx.commit()?;
+            Ok(())
+        })();
+        self.conn.execute("DETACH DATABASE bundle", [])?;
+        result
+    }
+
+    /// Copy a crate from a database written by `export_crates`, replacing any local
+    /// copy. `path` is where its sources live on this machine; without one no source
+    /// directory is recorded and its stored file contents are read instead. The bundle
+    /// is upgraded to the current schema first; bundles from a newer build are refused.
+    pub fn import_crate(&self, bundle_path: &Path, key: &str, path: Option<&Path>) -> Result<()> {
+        Self::open_at(bundle_path).context("Can't read the bundle's index")?;
+
+        self.conn.execute("ATTACH DATABASE ? AS bundle", [bundle_path.to_string_lossy()])?;
+        let result = (|| {
+            let tx = self.conn.unchecked_transaction()?;
+            copy_crate(&tx, "bundle", "main", key, Some(path.unwrap_or(Path::new(""))))?;
+            tx.commit()?;
+            Ok(())
+        })();
+        self.conn.execute("DETACH DATABASE bundle", [])?;
+        result
+    }
+
     pub fn add_crate(&self, key: &str, path: &Path, items: &CrateItems, reexports: &[String]) -> Result<()> {
         let tx = self.conn.unchecked_transaction()?;
 
-        // Insert or replace crate
+        // Insert or update crate (keeping its id, so the cleanup below finds the old rows)
         tx.execute(
-            "INSERT OR REPLACE INTO crates (key, path) VALUES (?, ?)",
-            params![key, path.to_string_lossy()],
+            "INSERT INTO crates (key, path, index_version, fetched_at) VALUES (?, ?, ?, ?)
+             ON CONFLICT(key) DO UPDATE SET path = excluded.path, index_version = excluded.index_version",
+            params![key, path.to_string_lossy(), INDEX_FORMAT_VERSION, unix_now() as i64],
         )?;
 
         let crate_id: i64 = tx.query_row(
@@ -333,31 +854,46 @@ impl Database {
             |row| row.get(0),
         )?;
 
-        // Delete old data for this crate (including embeddings)
-        tx.execute("DELETE FROM embeddings WHERE crate_id = ?", [crate_id])?;
-        tx.execute("DELETE FROM functions WHERE crate_id = ?", [crate_id])?;
-        tx.execute("DELETE FROM struct_fields WHERE struct_id IN (SELECT id FROM structs WHERE crate_id = ?)", [crate_id])?;
-        tx.execute("DELETE FROM structs WHERE crate_id = ?", [crate_id])?;
-        tx.execute("DELETE FROM enum_variants WHERE enum_id IN (SELECT id FROM enums WHERE crate_id = ?)", [crate_id])?;
-        tx.execute("DELETE FROM enums WHERE crate_id = ?", [crate_id])?;
-        tx.execute("DELETE FROM traits WHERE crate_id = ?", [crate_id])?;
-        tx.execute("DELETE FROM macros WHERE crate_id = ?", [crate_id])?;
-        tx.execute("DELETE FROM type_aliases WHERE crate_id = ?", [crate_id])?;
-        tx.execute("DELETE FROM constants WHERE crate_id = ?", [crate_id])?;
-        tx.execute("DELETE FROM impls WHERE crate_id = ?", [crate_id])?;
-        tx.execute("DELETE FROM reexports WHERE crate_id = ?", [crate_id])?;
+        // Delete old data for this crate, except items of files that were not re-parsed
+        let reused: Vec<&str> = items.files.iter().filter(|f| f.reused).map(|f| f.path.as_str()).collect();
+        delete_replaced_rows(&tx, crate_id, &reused)?;
+
+        // Insert item paths first, so an ID collision is reported with both items
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO item_paths (item_id, crate_id, kind, path) VALUES (?, ?, ?, ?)"
+            )?;
+            for p in &items.paths {
+                if let Err(e) = stmt.execute(params![p.id, crate_id, p.kind, p.path]) {
+                    let existing: Option<(String, String, String)> = tx.query_row(
+                        "SELECT c.key, p.kind, p.path FROM item_paths p JOIN crates c ON c.id = p.crate_id
+                         WHERE p.item_id = ?",
+                        [&p.id],
+                        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                    ).optional()?;
+                    return Err(match existing {
+                        Some((other_key, kind, path)) => anyhow::anyhow!(
+                            "Item ID collision: {} {} in {} and {} {} in {} both hash to {}",
+                            p.kind, p.path, key, kind, path, other_key, p.id
+                        ),
+                        None => e.into(),
+                    });
+                }
+            }
+        }
 
         // Insert functions
         {
             let mut stmt = tx.prepare(
-                "INSERT INTO functions (id, crate_id, name, file, line, end_line, signature, docs)
-                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+                "INSERT INTO functions (id, crate_id, name, file, line, end_line, signature, docs, type_signature)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
             )?;
             for func in &items.functions {
+                let type_signature = func.type_signature.as_ref().map(serde_json::to_string).transpose()?;
                 stmt.execute(params![
                     func.id, crate_id, func.name, func.file,
                     func.line as i64, func.end_line.map(|l| l as i64),
-                    func.signature, func.docs,
+                    func.signature, func.docs, type_signature,
                 ])?;
             }
         }
@@ -493,28 +1029,259 @@ impl Database {
             }
         }
 
+        // Insert dependencies (`build-dependencies` and `build_dependencies` may repeat one)
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO dependencies (crate_id, name, req, kind, optional, target, alias)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)"
+            )?;
+            for dep in &items.dependencies {
+                stmt.execute(params![
+                    crate_id, dep.name, dep.req, dep.kind, dep.optional,
+                    dep.target.as_deref().unwrap_or(""), dep.alias.as_deref().unwrap_or(""),
+                ])?;
+            }
+        }
+
+        // Insert full-text search rows
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO item_search (name, signature, fields, docs, item_id, item_type, crate_id, file, line)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+            )?;
+            for row in search_rows(items) {
+                stmt.execute(params![
+                    row.name, row.signature, row.fields, row.docs.unwrap_or(""),
+                    row.id, row.item_type, crate_id, row.file, row.line as i64,
+                ])?;
+            }
+        }
+
+        // Insert new and changed files
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO files (crate_id, path, hash, reexports, size, lines, language, target, contents)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+            )?;
+            for file in items.files.iter().filter(|f| !f.reused) {
+                stmt.execute(params![
+                    crate_id, file.path, file.hash, serde_json::to_string(&file.reexports)?,
+                    file.size as i64, file.lines as i64, file.language, file.target, file.contents,
+                ])?;
+            }
+        }
+
+        // Rebuild the trigram index, numbering the Rust files by path
+        {
+            let mut rust_files: Vec<&SourceFile> =
+                items.files.iter().filter(|f| f.language.as_deref() == Some("rust")).collect();
+            rust_files.sort_by(|a, b| a.path.cmp(&b.path));
+            let mut postings: BTreeMap<Trigram, Vec<u32>> = BTreeMap::new();
+            for (position, file) in rust_files.iter().enumerate() {
+                for &t in &file.trigrams {
+                    postings.entry(t).or_default().push(position as u32);
+                }
+            }
+
+            let mut stmt = tx.prepare(
+                "INSERT INTO trigrams (crate_id, trigram, postings) VALUES (?, ?, ?)"
+            )?;
+            for (t, positions) in postings {
+                stmt.execute(params![crate_id, t, trigram::encode_postings(&positions)])?;
+            }
+        }
+
+        // Keep embeddings of items that still exist; embedding generation replaces
+        // those whose text changed
+        tx.execute(
+            "DELETE FROM embeddings WHERE crate_id = ?1
+             AND id NOT IN (SELECT item_id FROM item_paths WHERE crate_id = ?1)",
+            [crate_id],
+        )?;
+
+        // Insert package metadata
+        let meta = &items.metadata;
+        tx.execute(
+            "INSERT INTO crate_metadata (crate_id, description, license, repository, homepage, documentation,
+                                         edition, rust_version, authors, keywords, categories, features)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                crate_id,
+                meta.description,
+                meta.license,
+                meta.repository,
+                meta.homepage,
+                meta.documentation,
+                meta.edition,
+                meta.rust_version,
+                serde_json::to_string(&meta.authors)?,
+                serde_json::to_string(&meta.keywords)?,
+                serde_json::to_string(&meta.categories)?,
+                serde_json::to_string(&meta.features)?,
+            ],
+        )?;
+
         tx.commit()?;
         Ok(())
     }
 
+    /// Remove a crate and everything indexed for it. Returns false if it was not indexed.
+    pub fn remove_crate(&self, key: &str) -> Result<bool> {
+        let Some(crate_id) = self.get_crate_id(key)? else {
+            return Ok(false);
+        };
+        let tx = self.conn.unchecked_transaction()?;
+        delete_crate_rows(&tx, crate_id)?;
+        tx.execute("DELETE FROM crates WHERE id = ?", [crate_id])?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Delete rows whose crate no longer exists (foreign keys don't cascade into
+    /// the full-text index). Returns the number deleted.
+    pub fn delete_orphaned_rows(&self) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut deleted = tx.execute(
+            "DELETE FROM struct_fields WHERE struct_id NOT IN
+                (SELECT id FROM structs WHERE crate_id IN (SELECT id FROM crates))",
+            [],
+        )?;
+        deleted += tx.execute(
+            "DELETE FROM enum_variants WHERE enum_id NOT IN
+                (SELECT id FROM enums WHERE crate_id IN (SELECT id FROM crates))",
+            [],
+        )?;
+        for table in CRATE_TABLES {
+            deleted += tx.execute(
+                &format!("DELETE FROM {} WHERE crate_id NOT IN (SELECT id FROM crates)", table),
+                [],
+            )?;
+        }
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Merge the full-text index segments and rebuild the database file to reclaim space
+    pub fn compact(&self) -> Result<()> {
+        self.conn.execute("INSERT INTO item_search (item_search) VALUES ('optimize')", [])?;
+        self.conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    /// Resolve an item ID or a unique prefix of one (at least 4 characters)
+    pub fn resolve_item_id(&self, prefix: &str) -> Result<String> {
+        const MIN_PREFIX_LEN: usize = 4;
+        let prefix = prefix.to_ascii_lowercase();
+        if prefix.len() < MIN_PREFIX_LEN || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            anyhow::bail!(
+                "'{}' is not an item ID (expected at least {} hex digits, or a path like crate::module::Item)",
+                prefix,
+                MIN_PREFIX_LEN
+            );
+        }
+
+        // IDs are hex, so every ID starting with the prefix sorts below `prefix~`
+        let sql = "SELECT p.item_id, p.kind, p.path, c.key FROM item_paths p JOIN crates c ON c.id = p.crate_id
+                   WHERE p.item_id >= ?1 AND p.item_id < ?1 || '~'
+                   ORDER BY p.item_id LIMIT 20";
+        let matches = self.merged(
+            |db| {
+                let mut stmt = db.conn.prepare(sql)?;
+                let matches = stmt.query_map([&prefix], item_match_from_row)?
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                Ok(matches)
+            },
+            |m| &m.crate_key,
+        )?;
+        pick_item(matches, &format!("ID prefix '{}'", prefix))
+    }
+
+    /// Resolve a path within a crate (e.g. `de::Deserializer`) to an item ID. Exact
+    /// paths win over path suffixes, and definitions over impl blocks.
+    pub fn resolve_item_path(&self, crate_key: &str, path: &str) -> Result<String> {
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
+            "SELECT p.item_id, p.kind, p.path, c.key FROM item_paths p JOIN crates c ON c.id = p.crate_id
+             WHERE c.key = ?1 AND (p.path = ?2 OR p.path LIKE '%::' || ?3 ESCAPE '\\')
+             ORDER BY p.path LIMIT 50"
+        )?;
+        let escaped = path.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        let suffix = format!("::{}", path);
+        let mut matches = stmt.query_map(params![crate_key, path, escaped], item_match_from_row)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        // LIKE ignores ASCII case
+        matches.retain(|m| m.path == path || m.path.ends_with(&suffix));
+
+        if matches.iter().any(|m| m.path == path) {
+            matches.retain(|m| m.path == path);
+        }
+        if matches.iter().any(|m| m.kind != "impl") {
+            matches.retain(|m| m.kind != "impl");
+        }
+        pick_item(matches, &format!("'{}' in {}", path, crate_key))
+    }
+
+    /// Every indexed crate (including shared ones) with its item count and embedding status, sorted by key
+    pub fn list_crates(&self) -> Result<Vec<CrateSummary>> {
+        let mut crates = self.merged(Database::local_crates, |c| &c.key)?;
+        crates.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(crates)
+    }
+
+    fn local_crates(&self) -> Result<Vec<CrateSummary>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.key, c.path, c.fetched_at,
+                    (SELECT COUNT(*) FROM item_search s WHERE s.crate_id = c.id),
+                    EXISTS (SELECT 1 FROM embeddings e WHERE e.crate_id = c.id)
+             FROM crates c
+             ORDER BY c.key"
+        )?;
+        let crates = stmt.query_map([], |row| {
+            Ok(CrateSummary {
+                key: row.get(0)?,
+                path: self.source_path(PathBuf::from(row.get::<_, String>(1)?)),
+                fetched_at: row.get(2)?,
+                item_count: row.get::<_, i64>(3)? as usize,
+                has_embeddings: row.get(4)?,
+                shared: self.shared_dir.is_some(),
+            })
+        })?;
+        crates.collect::<std::result::Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
     pub fn get_crate_path(&self, key: &str) -> Result<Option<PathBuf>> {
-        let mut stmt = self.conn.prepare("SELECT path FROM crates WHERE key = ?")?;
+        let db = self.holder(key)?;
+        let mut stmt = db.conn.prepare("SELECT path FROM crates WHERE key = ?")?;
         let path = stmt.query_row([key], |row| {
             let path: String = row.get(0)?;
+            // Crates imported without sources have no path recorded
+            if path.is_empty() {
+                return Ok(None);
+            }
             let path_buf = PathBuf::from(&path);
             // Handle legacy relative paths stored in database
             if path_buf.is_relative() {
                 if let Some(home) = dirs::home_dir() {
-                    return Ok(home.join(path_buf));
+                    return Ok(Some(home.join(path_buf)));
                 }
             }
-            Ok(path_buf)
-        }).optional()?;
-        Ok(path)
+            Ok(Some(path_buf))
+        }).optional()?.flatten();
+        Ok(path.map(|path| db.source_path(path)))
+    }
+
+    /// Sources of a shared index's crates are looked up in its own `crates`
+    /// directory when the recorded path (from the machine that built it) is missing
+    fn source_path(&self, path: PathBuf) -> PathBuf {
+        match (&self.shared_dir, path.file_name()) {
+            (Some(dir), Some(name)) if !path.exists() => dir.join("crates").join(name),
+            _ => path,
+        }
     }
 
     pub fn get_reexports(&self, key: &str) -> Result<Vec<String>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.holder(key)?;
+        let mut stmt = db.conn.prepare(
             "SELECT r.reexported_crate FROM reexports r
              JOIN crates c ON c.id = r.crate_id
              WHERE c.key = ?"
@@ -524,56 +1291,376 @@ impl Database {
         Ok(reexports)
     }
 
+    /// Package metadata of a crate, or `None` if it was indexed before metadata was recorded
+    pub fn get_metadata(&self, key: &str) -> Result<Option<PackageMetadata>> {
+        let db = self.holder(key)?;
+        let row = db.conn.query_row(
+            "SELECT m.description, m.license, m.repository, m.homepage, m.documentation, m.edition,
+                    m.rust_version, m.authors, m.keywords, m.categories, m.features
+             FROM crate_metadata m
+             JOIN crates c ON c.id = m.crate_id
+             WHERE c.key = ?",
+            [key],
+            |row| {
+                Ok((
+                    PackageMetadata {
+                        description: row.get(0)?,
+                        license: row.get(1)?,
+                        repository: row.get(2)?,
+                        homepage: row.get(3)?,
+                        documentation: row.get(4)?,
+                        edition: row.get(5)?,
+                        rust_version: row.get(6)?,
+                        ..Default::default()
+                    },
+                    row.get::<_, String>(7)?,
+                    row.get::<_, String>(8)?,
+                    row.get::<_, String>(9)?,
+                    row.get::<_, String>(10)?,
+                ))
+            },
+        ).optional()?;
+
+        let Some((mut meta, authors, keywords, categories, features)) = row else {
+            return Ok(None);
+        };
+        meta.authors = serde_json::from_str(&authors)?;
+        meta.keywords = serde_json::from_str(&keywords)?;
+        meta.categories = serde_json::from_str(&categories)?;
+        meta.features = serde_json::from_str(&features)?;
+        Ok(Some(meta))
+    }
+
+    pub fn get_dependencies(&self, key: &str) -> Result<Vec<DependencyInfo>> {
+        let db = self.holder(key)?;
+        let mut stmt = db.conn.prepare(
+            "SELECT d.name, d.req, d.kind, d.optional, d.target, d.alias FROM dependencies d
+             JOIN crates c ON c.id = d.crate_id
+             WHERE c.key = ?
+             ORDER BY d.kind, d.name, d.target, d.alias"
+        )?;
+        let deps = stmt.query_map([key], |row| dependency_from_row(row, 0))?;
+        deps.collect::<std::result::Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Indexed crates that declare a dependency on `name`, with the declaring crate's key
+    pub fn get_dependents(&self, name: &str) -> Result<Vec<(String, DependencyInfo)>> {
+        let mut dependents = self.merged(|db| db.local_dependents(name), |(key, _)| key)?;
+        dependents.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.kind.cmp(&b.1.kind)));
+        Ok(dependents)
+    }
+
+    fn local_dependents(&self, name: &str) -> Result<Vec<(String, DependencyInfo)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.key, d.name, d.req, d.kind, d.optional, d.target, d.alias FROM dependencies d
+             JOIN crates c ON c.id = d.crate_id
+             WHERE d.name = ?
+             ORDER BY c.key, d.kind, d.target, d.alias"
+        )?;
+        let dependents = stmt.query_map([name], |row| Ok((row.get(0)?, dependency_from_row(row, 1)?)))?;
+        dependents.collect::<std::result::Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Full-text search over indexed items, best matches first. `fts_query` uses
+    /// FTS5 query syntax (see `search::fts_query`); `crate_keys` limits the crates searched.
+    pub fn search_items(
+        &self,
+        fts_query: &str,
+        crate_keys: Option<&[String]>,
+        item_type: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let mut hits = self.merged(|db| db.local_search_items(fts_query, crate_keys, item_type, limit), |h| &h.crate_key)?;
+        hits.sort_by(|a, b| a.score.total_cmp(&b.score));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    fn local_search_items(
+        &self,
+        fts_query: &str,
+        crate_keys: Option<&[String]>,
+        item_type: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        use rusqlite::types::Value;
+
+        // Name matches weigh most, then signatures, field/variant names and docs
+        let mut sql = String::from(
+            "SELECT item_search.item_id, item_search.item_type, item_search.name, c.key,
+                    item_search.file, item_search.line,
+                    snippet(item_search, -1, '**', '**', '...', 16),
+                    bm25(item_search, 10.0, 5.0, 2.0, 1.0) AS score
+             FROM item_search
+             JOIN crates c ON c.id = item_search.crate_id
+             WHERE item_search MATCH ?"
+        );
+        let mut values = vec![Value::Text(fts_query.to_string())];
+
+        if let Some(item_type) = item_type {
+            sql.push_str(" AND item_search.item_type = ?");
+            values.push(Value::Text(item_type.to_string()));
+        }
+        if let Some(keys) = crate_keys {
+            sql.push_str(&format!(" AND c.key IN ({})", vec!["?"; keys.len()].join(", ")));
+            values.extend(keys.iter().map(|k| Value::Text(k.clone())));
+        }
+        sql.push_str(" ORDER BY score LIMIT ?");
+        values.push(Value::Integer(limit as i64));
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let hits = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            Ok(SearchHit {
+                id: row.get(0)?,
+                item_type: row.get(1)?,
+                name: row.get(2)?,
+                crate_key: row.get(3)?,
+                file: row.get(4)?,
+                line: row.get::<_, i64>(5)? as usize,
+                snippet: row.get(6)?,
+                score: row.get(7)?,
+            })
+        })?;
+        hits.collect::<std::result::Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid search query: {}", fts_query))
+    }
+
+    /// Crates indexed by an older indexer format that need to be re-indexed
+    pub fn stale_crate_keys(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT key FROM crates WHERE index_version < ? ORDER BY key")?;
+        let keys = stmt.query_map([INDEX_FORMAT_VERSION], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(keys)
+    }
+
+    pub fn is_stale(&self, key: &str) -> Result<bool> {
+        let db = self.holder(key)?;
+        let version: Option<i64> = db.conn.query_row(
+            "SELECT index_version FROM crates WHERE key = ?",
+            [key],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(version.is_some_and(|v| v < INDEX_FORMAT_VERSION))
+    }
+
+    /// Files of a crate as of its last indexing, sorted by path (contents not loaded)
+    pub fn list_files(&self, crate_key: &str) -> Result<Vec<SourceFile>> {
+        self.holder(crate_key)?.local_files(crate_key)
+    }
+
+    /// Files of a crate in the writable index, sorted by path (contents not loaded)
+    pub fn local_files(&self, crate_key: &str) -> Result<Vec<SourceFile>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT f.path, f.hash, f.size, f.lines, f.language, f.target, f.reexports FROM files f
+             JOIN crates c ON c.id = f.crate_id
+             WHERE c.key = ?
+             ORDER BY f.path"
+        )?;
+        let rows = stmt.query_map([crate_key], |row| {
+            let reexports: String = row.get(6)?;
+            Ok(SourceFile {
+                path: row.get(0)?,
+                hash: row.get(1)?,
+                size: row.get::<_, i64>(2)? as u64,
+                lines: row.get::<_, i64>(3)? as usize,
+                language: row.get(4)?,
+                target: row.get(5)?,
+                reexports: serde_json::from_str(&reexports).unwrap_or_default(),
+                contents: None,
+                trigrams: Vec::new(),
+                reused: false,
+            })
+        })?;
+        rows.collect::<std::result::Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Contents of a crate's file: from the index if they were stored there, otherwise
+    /// from the crate's sources on disk. `None` if neither has the file.
+    pub fn read_file(&self, crate_key: &str, path: &str) -> Result<Option<String>> {
+        let db = self.holder(crate_key)?;
+        let stored: Option<Vec<u8>> = db.conn.query_row(
+            "SELECT f.contents FROM files f
+             JOIN crates c ON c.id = f.crate_id
+             WHERE c.key = ? AND f.path = ?",
+            [crate_key, path],
+            |row| row.get(0),
+        ).optional()?.flatten();
+        if let Some(contents) = stored {
+            return unpack_contents(&contents).map(Some);
+        }
+
+        let Some(crate_path) = self.get_crate_path(crate_key)? else {
+            return Ok(None);
+        };
+        let full_path = crate_path.join(path);
+        if !full_path.is_file() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&full_path)
+            .with_context(|| format!("Failed to read {:?}", full_path))?;
+        Ok(Some(content))
+    }
+
+    /// Whether the index holds the contents of any of a crate's files, so it stays
+    /// readable without its sources
+    pub fn has_stored_contents(&self, crate_key: &str) -> Result<bool> {
+        let db = self.holder(crate_key)?;
+        db.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM files f
+                            JOIN crates c ON c.id = f.crate_id
+                            WHERE c.key = ? AND f.contents IS NOT NULL)",
+            [crate_key],
+            |row| row.get(0),
+        ).map_err(Into::into)
+    }
+
+    /// Path and stored contents (if any, still compressed) of a crate's files in one
+    /// language, for callers that unpack and scan them in parallel
+    /// With `positions`, only the files at those (sorted) positions in the list
+    pub fn get_packed_files(
+        &self,
+        crate_key: &str,
+        language: &str,
+        positions: Option<&[u32]>,
+    ) -> Result<Vec<(String, Option<Vec<u8>>)>> {
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
+            "SELECT f.path, f.contents FROM files f
+             JOIN crates c ON c.id = f.crate_id
+             WHERE c.key = ? AND f.language = ?
+             ORDER BY f.path"
+        )?;
+        let mut rows = stmt.query([crate_key, language])?;
+        let mut files = Vec::new();
+        let mut wanted = positions.map(|p| p.iter().peekable());
+        let mut position = 0;
+        while let Some(row) = rows.next()? {
+            if let Some(wanted) = &mut wanted {
+                if wanted.next_if_eq(&&position).is_none() {
+                    if wanted.peek().is_none() {
+                        break;
+                    }
+                    position += 1;
+                    continue;
+                }
+            }
+            files.push((row.get(0)?, row.get(1)?));
+            position += 1;
+        }
+        Ok(files)
+    }
+
+    /// Whether the crate has a trigram index (crates without Rust files have none)
+    pub fn has_trigram_index(&self, crate_key: &str) -> Result<bool> {
+        let db = self.holder(crate_key)?;
+        let found = db.conn.query_row(
+            "SELECT 1 FROM trigrams t JOIN crates c ON c.id = t.crate_id WHERE c.key = ? LIMIT 1",
+            [crate_key],
+            |_| Ok(()),
+        ).optional()?;
+        Ok(found.is_some())
+    }
+
+    /// Positions of the crate's Rust files (sorted by path) containing a trigram
+    pub fn get_postings(&self, crate_key: &str, trigram: Trigram) -> Result<Vec<u32>> {
+        let db = self.holder(crate_key)?;
+        let postings: Option<Vec<u8>> = db.conn.query_row(
+            "SELECT t.postings FROM trigrams t JOIN crates c ON c.id = t.crate_id
+             WHERE c.key = ? AND t.trigram = ?",
+            params![crate_key, trigram],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(postings.map(|p| trigram::decode_postings(&p)).unwrap_or_default())
+    }
+
+    /// Keys of all crates, including those only in shared indexes
     pub fn list_crate_keys(&self) -> Result<Vec<String>> {
+        self.merged(Database::local_crate_keys, |key| key)
+    }
+
+    /// Keys of the crates in the writable index
+    pub fn local_crate_keys(&self) -> Result<Vec<String>> {
         let mut stmt = self.conn.prepare("SELECT key FROM crates")?;
         let keys = stmt.query_map([], |row| row.get(0))?
             .collect::<std::result::Result<Vec<String>, _>>()?;
         Ok(keys)
     }
 
-    pub fn find_crate_key(&self, name: &str) -> Result<Option<String>> {
-        let mut stmt = self.conn.prepare("SELECT key FROM crates WHERE key = ?")?;
-        if let Some(key) = stmt.query_row([name], |row| row.get::<_, String>(0)).optional()? {
-            return Ok(Some(key));
+    /// Newest indexed version of every crate, sorted by key. Keys without a
+    /// parseable version are kept as they are.
+    pub fn latest_crate_keys(&self) -> Result<Vec<String>> {
+        let mut newest: BTreeMap<String, (Option<semver::Version>, String)> = BTreeMap::new();
+        for key in self.list_crate_keys()? {
+            let (name, version) = split_crate_key(&key);
+            match newest.get(name) {
+                Some((current, _)) if *current >= version => {}
+                _ => {
+                    newest.insert(name.to_string(), (version, key));
+                }
+            }
         }
+        let mut keys: Vec<String> = newest.into_values().map(|(_, key)| key).collect();
+        keys.sort();
+        Ok(keys)
+    }
 
-        let mut stmt = self.conn.prepare(
-            "SELECT key FROM crates WHERE key GLOB ? ORDER BY key"
-        )?;
-        let pattern = format!("{}-[0-9]*", name);
-        let matches: Vec<String> = stmt.query_map([&pattern], |row| row.get(0))?
-            .collect::<std::result::Result<_, _>>()?;
-
+    pub fn find_crate_key(&self, name: &str) -> Result<Option<String>> {
+        let mut matches = self.find_all_crate_keys(name)?;
         match matches.len() {
             0 => Ok(None),
-            1 => Ok(Some(matches.into_iter().next().unwrap())),
-            _ => anyhow::bail!("Multiple versions found for '{}': {:?}", name, matches),
+            1 => Ok(matches.pop()),
+            _ => anyhow::bail!("Multiple versions found for '{}'.{}", name, crate::fuzzy::did_you_mean(&matches)),
         }
     }
 
     /// Find all crate keys matching a name (returns all versions if multiple exist)
     pub fn find_all_crate_keys(&self, name: &str) -> Result<Vec<String>> {
         // First check for exact match
-        let mut stmt = self.conn.prepare("SELECT key FROM crates WHERE key = ?")?;
-        if let Some(key) = stmt.query_row([name], |row| row.get::<_, String>(0)).optional()? {
-            return Ok(vec![key]);
+        for db in self.indexes() {
+            if db.has_crate(name)? {
+                return Ok(vec![name.to_string()]);
+            }
         }
 
         // Then search for versioned matches
-        let mut stmt = self.conn.prepare(
-            "SELECT key FROM crates WHERE key GLOB ? ORDER BY key"
-        )?;
         let pattern = format!("{}-[0-9]*", name);
-        let matches: Vec<String> = stmt.query_map([&pattern], |row| row.get(0))?
-            .collect::<std::result::Result<_, _>>()?;
-
+        let mut matches = self.merged(
+            |db| {
+                let mut stmt = db.conn.prepare("SELECT key FROM crates WHERE key GLOB ?")?;
+                let keys = stmt.query_map([&pattern], |row| row.get(0))?
+                    .collect::<std::result::Result<Vec<String>, _>>()?;
+                Ok(keys)
+            },
+            |key| key,
+        )?;
+        matches.sort();
         Ok(matches)
     }
 
+    /// Run a query against this index and each shared one, dropping results for
+    /// crates that an earlier index also has (the writable index shadows shared ones)
+    fn merged<T>(
+        &self,
+        query: impl Fn(&Database) -> Result<Vec<T>>,
+        crate_key: impl Fn(&T) -> &String,
+    ) -> Result<Vec<T>> {
+        let mut results = query(self)?;
+        if self.shared.is_empty() {
+            return Ok(results);
+        }
+        let mut seen: HashSet<String> = self.local_crate_keys()?.into_iter().collect();
+        for db in &self.shared {
+            results.extend(query(db)?.into_iter().filter(|row| !seen.contains(crate_key(row))));
+            seen.extend(db.local_crate_keys()?);
+        }
+        Ok(results)
+    }
+
     // Query functions
     pub fn get_functions(&self, crate_key: &str) -> Result<Vec<FunctionInfo>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT f.id, f.name, f.file, f.line, f.end_line, f.signature, f.docs
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
+            "SELECT f.id, f.name, f.file, f.line, f.end_line, f.signature, f.docs, f.type_signature
              FROM functions f JOIN crates c ON c.id = f.crate_id WHERE c.key = ?"
         )?;
         let rows = stmt.query_map([crate_key], |row| {
@@ -582,14 +1669,16 @@ impl Database {
                 line: row.get::<_, i64>(3)? as usize,
                 end_line: row.get::<_, Option<i64>>(4)?.map(|l| l as usize),
                 signature: row.get(5)?, docs: row.get(6)?,
+                type_signature: Self::parse_type_signature(row.get(7)?),
             })
         })?;
         rows.collect::<std::result::Result<Vec<_>, _>>().map_err(Into::into)
     }
 
     pub fn get_function_by_id(&self, id: &str) -> Result<Option<(String, FunctionInfo)>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT c.key, f.id, f.name, f.file, f.line, f.end_line, f.signature, f.docs
+        let db = self.item_holder("functions", id)?;
+        let mut stmt = db.conn.prepare(
+            "SELECT c.key, f.id, f.name, f.file, f.line, f.end_line, f.signature, f.docs, f.type_signature
              FROM functions f JOIN crates c ON c.id = f.crate_id WHERE f.id = ?"
         )?;
         stmt.query_row([id], |row| {
@@ -598,13 +1687,20 @@ impl Database {
                 line: row.get::<_, i64>(4)? as usize,
                 end_line: row.get::<_, Option<i64>>(5)?.map(|l| l as usize),
                 signature: row.get(6)?, docs: row.get(7)?,
+                type_signature: Self::parse_type_signature(row.get(8)?),
             }))
         }).optional().map_err(Into::into)
     }
 
+    /// Stored type signatures that fail to parse are treated as missing
+    fn parse_type_signature(json: Option<String>) -> Option<TypeSignature> {
+        json.and_then(|json| serde_json::from_str(&json).ok())
+    }
+
     // Query structs
     pub fn get_structs(&self, crate_key: &str) -> Result<Vec<StructInfo>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
             "SELECT s.id, s.name, s.file, s.line, s.end_line, s.visibility, s.docs
              FROM structs s JOIN crates c ON c.id = s.crate_id WHERE c.key = ?"
         )?;
@@ -619,7 +1715,7 @@ impl Database {
 
         let mut result = Vec::new();
         for (id, name, file, line, end_line, visibility, docs) in structs {
-            let fields = self.get_struct_fields(&id)?;
+            let fields = db.get_struct_fields(&id)?;
             result.push(StructInfo { id, name, file, line, end_line, visibility, fields, docs });
         }
         Ok(result)
@@ -639,7 +1735,8 @@ impl Database {
     }
 
     pub fn get_struct_by_id(&self, id: &str) -> Result<Option<(String, StructInfo)>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.item_holder("structs", id)?;
+        let mut stmt = db.conn.prepare(
             "SELECT c.key, s.id, s.name, s.file, s.line, s.end_line, s.visibility, s.docs
              FROM structs s JOIN crates c ON c.id = s.crate_id WHERE s.id = ?"
         )?;
@@ -655,7 +1752,7 @@ impl Database {
 
         match result {
             Some((crate_key, id, name, file, line, end_line, visibility, docs)) => {
-                let fields = self.get_struct_fields(&id)?;
+                let fields = db.get_struct_fields(&id)?;
                 Ok(Some((crate_key, StructInfo { id, name, file, line, end_line, visibility, fields, docs })))
             }
             None => Ok(None),
@@ -664,7 +1761,8 @@ impl Database {
 
     // Query enums
     pub fn get_enums(&self, crate_key: &str) -> Result<Vec<EnumInfo>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
             "SELECT e.id, e.name, e.file, e.line, e.end_line, e.visibility, e.docs
              FROM enums e JOIN crates c ON c.id = e.crate_id WHERE c.key = ?"
         )?;
@@ -679,7 +1777,7 @@ impl Database {
 
         let mut result = Vec::new();
         for (id, name, file, line, end_line, visibility, docs) in enums {
-            let variants = self.get_enum_variants(&id)?;
+            let variants = db.get_enum_variants(&id)?;
             result.push(EnumInfo { id, name, file, line, end_line, visibility, variants, docs });
         }
         Ok(result)
@@ -699,7 +1797,8 @@ impl Database {
     }
 
     pub fn get_enum_by_id(&self, id: &str) -> Result<Option<(String, EnumInfo)>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.item_holder("enums", id)?;
+        let mut stmt = db.conn.prepare(
             "SELECT c.key, e.id, e.name, e.file, e.line, e.end_line, e.visibility, e.docs
              FROM enums e JOIN crates c ON c.id = e.crate_id WHERE e.id = ?"
         )?;
@@ -715,7 +1814,7 @@ impl Database {
 
         match result {
             Some((crate_key, id, name, file, line, end_line, visibility, docs)) => {
-                let variants = self.get_enum_variants(&id)?;
+                let variants = db.get_enum_variants(&id)?;
                 Ok(Some((crate_key, EnumInfo { id, name, file, line, end_line, visibility, variants, docs })))
             }
             None => Ok(None),
@@ -724,7 +1823,8 @@ impl Database {
 
     // Query traits
     pub fn get_traits(&self, crate_key: &str) -> Result<Vec<TraitInfo>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
             "SELECT t.id, t.name, t.file, t.line, t.end_line, t.visibility, t.docs
              FROM traits t JOIN crates c ON c.id = t.crate_id WHERE c.key = ?"
         )?;
@@ -740,7 +1840,8 @@ impl Database {
     }
 
     pub fn get_trait_by_id(&self, id: &str) -> Result<Option<(String, TraitInfo)>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.item_holder("traits", id)?;
+        let mut stmt = db.conn.prepare(
             "SELECT c.key, t.id, t.name, t.file, t.line, t.end_line, t.visibility, t.docs
              FROM traits t JOIN crates c ON c.id = t.crate_id WHERE t.id = ?"
         )?;
@@ -756,7 +1857,8 @@ impl Database {
 
     // Query macros
     pub fn get_macros(&self, crate_key: &str) -> Result<Vec<MacroInfo>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
             "SELECT m.id, m.name, m.file, m.line, m.end_line, m.kind, m.docs
              FROM macros m JOIN crates c ON c.id = m.crate_id WHERE c.key = ?"
         )?;
@@ -772,7 +1874,8 @@ impl Database {
     }
 
     pub fn get_macro_by_id(&self, id: &str) -> Result<Option<(String, MacroInfo)>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.item_holder("macros", id)?;
+        let mut stmt = db.conn.prepare(
             "SELECT c.key, m.id, m.name, m.file, m.line, m.end_line, m.kind, m.docs
              FROM macros m JOIN crates c ON c.id = m.crate_id WHERE m.id = ?"
         )?;
@@ -788,7 +1891,8 @@ impl Database {
 
     // Query type aliases
     pub fn get_type_aliases(&self, crate_key: &str) -> Result<Vec<TypeAliasInfo>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
             "SELECT t.id, t.name, t.file, t.line, t.type_str, t.visibility, t.docs
              FROM type_aliases t JOIN crates c ON c.id = t.crate_id WHERE c.key = ?"
         )?;
@@ -803,7 +1907,8 @@ impl Database {
     }
 
     pub fn get_type_alias_by_id(&self, id: &str) -> Result<Option<(String, TypeAliasInfo)>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.item_holder("type_aliases", id)?;
+        let mut stmt = db.conn.prepare(
             "SELECT c.key, t.id, t.name, t.file, t.line, t.type_str, t.visibility, t.docs
              FROM type_aliases t JOIN crates c ON c.id = t.crate_id WHERE t.id = ?"
         )?;
@@ -818,7 +1923,8 @@ impl Database {
 
     // Query constants
     pub fn get_constants(&self, crate_key: &str) -> Result<Vec<ConstantInfo>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
             "SELECT c2.id, c2.name, c2.file, c2.line, c2.kind, c2.type_str, c2.visibility, c2.docs
              FROM constants c2 JOIN crates c ON c.id = c2.crate_id WHERE c.key = ?"
         )?;
@@ -834,7 +1940,8 @@ impl Database {
     }
 
     pub fn get_constant_by_id(&self, id: &str) -> Result<Option<(String, ConstantInfo)>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.item_holder("constants", id)?;
+        let mut stmt = db.conn.prepare(
             "SELECT c.key, c2.id, c2.name, c2.file, c2.line, c2.kind, c2.type_str, c2.visibility, c2.docs
              FROM constants c2 JOIN crates c ON c.id = c2.crate_id WHERE c2.id = ?"
         )?;
@@ -850,7 +1957,8 @@ impl Database {
 
     // Query impls
     pub fn get_impls(&self, crate_key: &str) -> Result<Vec<ImplInfo>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
             "SELECT i.id, i.file, i.line, i.end_line, i.self_type, i.trait_name
              FROM impls i JOIN crates c ON c.id = i.crate_id WHERE c.key = ?"
         )?;
@@ -865,8 +1973,36 @@ impl Database {
         rows.collect::<std::result::Result<Vec<_>, _>>().map_err(Into::into)
     }
 
+    /// Line spans of all items of a crate; type aliases and constants, which have
+    /// no end line stored, span their first line
+    pub fn get_item_spans(&self, crate_key: &str) -> Result<Vec<ItemSpan>> {
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
+            "WITH c AS (SELECT id FROM crates WHERE key = ?1)
+             SELECT 'function', id, name, file, line, end_line, NULL FROM functions WHERE crate_id = (SELECT id FROM c)
+             UNION ALL SELECT 'struct', id, name, file, line, end_line, NULL FROM structs WHERE crate_id = (SELECT id FROM c)
+             UNION ALL SELECT 'enum', id, name, file, line, end_line, NULL FROM enums WHERE crate_id = (SELECT id FROM c)
+             UNION ALL SELECT 'trait', id, name, file, line, end_line, NULL FROM traits WHERE crate_id = (SELECT id FROM c)
+             UNION ALL SELECT 'macro', id, name, file, line, end_line, NULL FROM macros WHERE crate_id = (SELECT id FROM c)
+             UNION ALL SELECT 'type_alias', id, name, file, line, NULL, NULL FROM type_aliases WHERE crate_id = (SELECT id FROM c)
+             UNION ALL SELECT 'constant', id, name, file, line, NULL, NULL FROM constants WHERE crate_id = (SELECT id FROM c)
+             UNION ALL SELECT 'impl', id, self_type, file, line, end_line, trait_name FROM impls WHERE crate_id = (SELECT id FROM c)"
+        )?;
+        let rows = stmt.query_map([crate_key], |row| {
+            let line = row.get::<_, i64>(4)? as usize;
+            Ok(ItemSpan {
+                kind: row.get(0)?, id: row.get(1)?, name: row.get(2)?, file: row.get(3)?,
+                line,
+                end_line: row.get::<_, Option<i64>>(5)?.map_or(line, |l| l as usize),
+                trait_name: row.get(6)?,
+            })
+        })?;
+        rows.collect::<std::result::Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
     pub fn get_impl_by_id(&self, id: &str) -> Result<Option<(String, ImplInfo)>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.item_holder("impls", id)?;
+        let mut stmt = db.conn.prepare(
             "SELECT c.key, i.id, i.file, i.line, i.end_line, i.self_type, i.trait_name
              FROM impls i JOIN crates c ON c.id = i.crate_id WHERE i.id = ?"
         )?;
@@ -880,18 +2016,34 @@ impl Database {
         }).optional().map_err(Into::into)
     }
 
-    // Embedding methods
-    pub fn has_embeddings(&self, crate_key: &str) -> Result<bool> {
-        let count: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM embeddings e
-             JOIN crates c ON c.id = e.crate_id
-             WHERE c.key = ?",
-            [crate_key],
+    // Latest-version cache
+    /// Cached "latest" version of a crate, if it was checked less than `max_age_secs` ago
+    pub fn get_cached_latest_version(&self, crate_name: &str, max_age_secs: u64) -> Result<Option<String>> {
+        let min_checked_at = unix_now().saturating_sub(max_age_secs) as i64;
+        self.conn.query_row(
+            "SELECT version FROM latest_versions WHERE crate_name = ? AND checked_at >= ?",
+            params![crate_name, min_checked_at],
             |row| row.get(0),
+        ).optional().map_err(Into::into)
+    }
+
+    /// Names of all crates whose latest version was looked up, however long ago
+    pub fn cached_crate_names(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT crate_name FROM latest_versions")?;
+        let names = stmt.query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(names)
+    }
+
+    pub fn set_cached_latest_version(&self, crate_name: &str, version: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO latest_versions (crate_name, version, checked_at) VALUES (?, ?, ?)",
+            params![crate_name, version, unix_now() as i64],
         )?;
-        Ok(count > 0)
+        Ok(())
     }
 
+    // Embedding methods
     pub fn get_crate_id(&self, crate_key: &str) -> Result<Option<i64>> {
         self.conn.query_row(
             "SELECT id FROM crates WHERE key = ?",
@@ -917,8 +2069,22 @@ impl Database {
         Ok(())
     }
 
+    /// Text each stored embedding of a crate was generated from, by item ID
+    pub fn get_embedded_texts(&self, crate_key: &str) -> Result<HashMap<String, String>> {
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
+            "SELECT e.id, e.text_content FROM embeddings e
+             JOIN crates c ON c.id = e.crate_id
+             WHERE c.key = ?"
+        )?;
+        let texts = stmt.query_map([crate_key], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<HashMap<_, _>, _>>()?;
+        Ok(texts)
+    }
+
     pub fn get_all_embeddings(&self, crate_key: &str) -> Result<Vec<EmbeddingInfo>> {
-        let mut stmt = self.conn.prepare(
+        let db = self.holder(crate_key)?;
+        let mut stmt = db.conn.prepare(
             "SELECT e.id, e.item_type, e.embedding, e.text_content, c.key
              FROM embeddings e
              JOIN crates c ON c.id = e.crate_id
@@ -939,10 +2105,281 @@ impl Database {
     }
 }
 
+struct ItemMatch {
+    id: String,
+    kind: String,
+    path: String,
+    crate_key: String,
+}
+
+fn item_match_from_row(row: &rusqlite::Row) -> rusqlite::Result<ItemMatch> {
+    Ok(ItemMatch { id: row.get(0)?, kind: row.get(1)?, path: row.get(2)?, crate_key: row.get(3)? })
+}
+
+/// The single matching item's ID, or an error listing the candidates
+fn pick_item(mut matches: Vec<ItemMatch>, what: &str) -> Result<String> {
+    match matches.len() {
+        0 => anyhow::bail!("No item found for {}", what),
+        1 => Ok(matches.remove(0).id),
+        _ => {
+            let candidates: Vec<String> = matches
+                .iter()
+                .map(|m| format!("  [{}] {} {} ({})", m.id, m.kind, m.path, m.crate_key))
+                .collect();
+            anyhow::bail!("{} matches several items:\n{}", what, candidates.join("\n"))
+        }
+    }
+}
+
+/// Copy a crate row and all rows belonging to it between attached databases,
+/// replacing the crate in `to` if it is already there
+fn copy_crate(tx: &rusqlite::Transaction, from: &str, to: &str, key: &str, path: Option<&Path>) -> Result<()> {
+    let crate_id: i64 = tx.query_row(&format!("SELECT id FROM {}.crates WHERE key = ?", from), [key], |row| row.get(0))
+        .optional()?
+        .with_context(|| format!("Crate {} is not in the index", key))?;
+
+    if let Some(old_id) = tx.query_row(&format!("SELECT id FROM {}.crates WHERE key = ?", to), [key], |row| row.get::<_, i64>(0))
+        .optional()?
+    {
+        if to != "main" {
+            anyhow::bail!("Crate {} is already in the destination index", key);
+        }
+        delete_crate_rows(tx, old_id)?;
+        tx.execute("DELETE FROM main.crates WHERE id = ?", [old_id])?;
+    }
+
+    let columns = copied_columns(tx, to, "crates")?.join(", ");
+    tx.execute(
+        &format!("INSERT INTO {to}.crates ({columns}) SELECT {columns} FROM {from}.crates WHERE id = ?"),
+        [crate_id],
+    )?;
+    let new_id = tx.last_insert_rowid();
+    if let Some(path) = path {
+        tx.execute(&format!("UPDATE {}.crates SET path = ? WHERE id = ?", to), params![path.to_string_lossy(), new_id])?;
+    }
+
+    for table in CRATE_TABLES {
+        let columns = copied_columns(tx, to, table)?;
+        let select = columns.iter().map(|c| if c == "crate_id" { "?1" } else { c.as_str() }).collect::<Vec<_>>().join(", ");
+        let columns = columns.join(", ");
+        tx.execute(
+            &format!("INSERT INTO {to}.{table} ({columns}) SELECT {select} FROM {from}.{table} WHERE crate_id = ?2"),
+            params![new_id, crate_id],
+        )?;
+    }
+    for (table, owner_column, owners) in [("struct_fields", "struct_id", "structs"), ("enum_variants", "enum_id", "enums")] {
+        let columns = copied_columns(tx, to, table)?.join(", ");
+        tx.execute(
+            &format!(
+                "INSERT INTO {to}.{table} ({columns}) SELECT {columns} FROM {from}.{table}
+                 WHERE {owner_column} IN (SELECT id FROM {from}.{owners} WHERE crate_id = ?)"
+            ),
+            [crate_id],
+        )?;
+    }
+    Ok(())
+}
+
+/// A `dependencies` row read from `name, req, kind, optional, target, alias` at `first`
+fn dependency_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<DependencyInfo> {
+    let unset_if_empty = |value: String| Some(value).filter(|v| !v.is_empty());
+    Ok(DependencyInfo {
+        name: row.get(first)?,
+        req: row.get(first + 1)?,
+        kind: row.get(first + 2)?,
+        optional: row.get(first + 3)?,
+        target: unset_if_empty(row.get(first + 4)?),
+        alias: unset_if_empty(row.get(first + 5)?),
+    })
+}
+
+/// Columns of a table, leaving out rowid aliases (`id INTEGER PRIMARY KEY`) so the
+/// destination assigns its own
+fn copied_columns(tx: &rusqlite::Transaction, schema: &str, table: &str) -> Result<Vec<String>> {
+    let mut stmt = tx.prepare(&format!("PRAGMA {}.table_info({})", schema, table))?;
+    let columns = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, i64>(5)?))
+    })?.collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(columns
+        .into_iter()
+        .filter(|(name, column_type, pk)| !(name == "id" && *pk == 1 && column_type.eq_ignore_ascii_case("INTEGER")))
+        .map(|(name, _, _)| name)
+        .collect())
+}
+
+fn delete_crate_rows(tx: &rusqlite::Transaction, crate_id: i64) -> Result<()> {
+    tx.execute("DELETE FROM struct_fields WHERE struct_id IN (SELECT id FROM structs WHERE crate_id = ?)", [crate_id])?;
+    tx.execute("DELETE FROM enum_variants WHERE enum_id IN (SELECT id FROM enums WHERE crate_id = ?)", [crate_id])?;
+    for table in CRATE_TABLES {
+        tx.execute(&format!("DELETE FROM {} WHERE crate_id = ?", table), [crate_id])?;
+    }
+    Ok(())
+}
+
+/// Delete the rows `add_crate` replaces: files not in `reused` and their items,
+/// the items' paths, and the crate-level rows. Embeddings are left for `add_crate` to prune.
+fn delete_replaced_rows(tx: &rusqlite::Transaction, crate_id: i64, reused: &[&str]) -> Result<()> {
+    let reused = serde_json::to_string(reused)?;
+    let replaced = "crate_id = ?1 AND file NOT IN (SELECT value FROM json_each(?2))";
+    tx.execute(
+        &format!("DELETE FROM struct_fields WHERE struct_id IN (SELECT id FROM structs WHERE {})", replaced),
+        params![crate_id, reused],
+    )?;
+    tx.execute(
+        &format!("DELETE FROM enum_variants WHERE enum_id IN (SELECT id FROM enums WHERE {})", replaced),
+        params![crate_id, reused],
+    )?;
+    for table in FILE_TABLES {
+        tx.execute(&format!("DELETE FROM {} WHERE {}", table, replaced), params![crate_id, reused])?;
+    }
+    tx.execute(
+        "DELETE FROM files WHERE crate_id = ?1 AND path NOT IN (SELECT value FROM json_each(?2))",
+        params![crate_id, reused],
+    )?;
+
+    let kept_items = FILE_TABLES
+        .iter()
+        .filter(|t| **t != "item_search")
+        .map(|t| format!("SELECT id FROM {} WHERE crate_id = ?1", t))
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ");
+    tx.execute(
+        &format!("DELETE FROM item_paths WHERE crate_id = ?1 AND item_id NOT IN ({})", kept_items),
+        [crate_id],
+    )?;
+
+    for table in CRATE_TABLES {
+        if !["embeddings", "item_paths", "files"].contains(table) && !FILE_TABLES.contains(table) {
+            tx.execute(&format!("DELETE FROM {} WHERE crate_id = ?", table), [crate_id])?;
+        }
+    }
+    Ok(())
+}
+
+/// Split `name-1.2.3` at the first `-` followed by a valid semver version, so
+/// hyphenated names (`serde-json-1.0.0`) and pre-releases (`foo-1.0.0-beta.1`) work
+pub fn split_crate_key(key: &str) -> (&str, Option<semver::Version>) {
+    for (i, _) in key.match_indices('-') {
+        if let Ok(version) = semver::Version::parse(&key[i + 1..]) {
+            return (&key[..i], Some(version));
+        }
+    }
+    (key, None)
+}
+
+/// One `item_search` row before insertion
+struct SearchRow<'a> {
+    id: &'a str,
+    item_type: &'static str,
+    name: String,
+    signature: String,
+    fields: String,
+    docs: Option<&'a str>,
+    file: &'a str,
+    line: usize,
+}
+
+fn search_rows(items: &CrateItems) -> Vec<SearchRow<'_>> {
+    let mut rows = Vec::new();
+
+    for f in &items.functions {
+        rows.push(SearchRow {
+            id: &f.id, item_type: "function", name: f.name.clone(), signature: f.signature.clone(),
+            fields: String::new(), docs: f.docs.as_deref(), file: &f.file, line: f.line,
+        });
+    }
+    for s in &items.structs {
+        let fields: Vec<&str> = s.fields.iter().map(|f| f.name.as_str()).collect();
+        rows.push(SearchRow {
+            id: &s.id, item_type: "struct", name: s.name.clone(), signature: format!("struct {}", s.name),
+            fields: fields.join(" "), docs: s.docs.as_deref(), file: &s.file, line: s.line,
+        });
+    }
+    for e in &items.enums {
+        let variants: Vec<&str> = e.variants.iter().map(|v| v.name.as_str()).collect();
+        rows.push(SearchRow {
+            id: &e.id, item_type: "enum", name: e.name.clone(), signature: format!("enum {}", e.name),
+            fields: variants.join(" "), docs: e.docs.as_deref(), file: &e.file, line: e.line,
+        });
+    }
+    for t in &items.traits {
+        rows.push(SearchRow {
+            id: &t.id, item_type: "trait", name: t.name.clone(), signature: format!("trait {}", t.name),
+            fields: String::new(), docs: t.docs.as_deref(), file: &t.file, line: t.line,
+        });
+    }
+    for m in &items.macros {
+        rows.push(SearchRow {
+            id: &m.id, item_type: "macro", name: m.name.clone(), signature: format!("{} macro {}", m.kind, m.name),
+            fields: String::new(), docs: m.docs.as_deref(), file: &m.file, line: m.line,
+        });
+    }
+    for t in &items.type_aliases {
+        rows.push(SearchRow {
+            id: &t.id, item_type: "type_alias", name: t.name.clone(),
+            signature: format!("type {} = {}", t.name, t.type_str),
+            fields: String::new(), docs: t.docs.as_deref(), file: &t.file, line: t.line,
+        });
+    }
+    for c in &items.constants {
+        rows.push(SearchRow {
+            id: &c.id, item_type: "constant", name: c.name.clone(),
+            signature: format!("{} {}: {}", c.kind, c.name, c.type_str),
+            fields: String::new(), docs: c.docs.as_deref(), file: &c.file, line: c.line,
+        });
+    }
+    for i in &items.impls {
+        let signature = match &i.trait_name {
+            Some(trait_name) => format!("impl {} for {}", trait_name, i.self_type),
+            None => format!("impl {}", i.self_type),
+        };
+        rows.push(SearchRow {
+            id: &i.id, item_type: "impl", name: i.self_type.clone(), signature,
+            fields: String::new(), docs: None, file: &i.file, line: i.line,
+        });
+    }
+
+    rows
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// SQLite `file:` URI for a path, percent-encoded so `?`, `#` and `%` in directory
+/// names aren't read as URI syntax
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file:");
+    let path = path.as_os_str().as_encoded_bytes();
+    // Windows drive letters need a leading slash: file:/C:/...
+    if cfg!(windows) && path.get(1) == Some(&b':') {
+        uri.push('/');
+    }
+    for &b in path {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(b as char),
+            b'\\' if cfg!(windows) => uri.push('/'),
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+/// Compress file contents for the `files` table
+pub fn pack_contents(text: &str) -> Result<Vec<u8>> {
+    zstd::encode_all(text.as_bytes(), CONTENTS_COMPRESSION_LEVEL).context("Failed to compress file contents")
+}
+
+pub fn unpack_contents(packed: &[u8]) -> Result<String> {
+    let bytes = zstd::decode_all(packed).context("Failed to decompress file contents")?;
+    String::from_utf8(bytes).context("Stored file contents are not UTF-8")
+}
+
 pub fn index_dir() -> PathBuf {
-    dirs::home_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join(INDEX_DIR)
+    config::get().index_dir()
 }
 
 pub fn db_path() -> PathBuf {
@@ -956,3 +2393,546 @@ pub fn crates_dir() -> PathBuf {
 pub fn crate_path(name: &str, version: &str) -> PathBuf {
     crates_dir().join(format!("{}-{}", name, version))
 }
+
+fn crate_lock_file(key: &str) -> PathBuf {
+    index_dir().join("locks").join(format!("{}.lock", key))
+}
+
+fn create_crate_lock_file(key: &str) -> Result<File> {
+    let lock_path = crate_lock_file(key);
+    std::fs::create_dir_all(index_dir().join("locks"))?;
+    File::create(&lock_path).with_context(|| format!("Failed to create lock file {:?}", lock_path))
+}
+
+/// Take an exclusive lock for creating a crate's source directory, blocking while
+/// another process holds it. The lock is released when the returned file is dropped.
+pub fn lock_crate_path(key: &str) -> Result<File> {
+    let file = create_crate_lock_file(key)?;
+    file.lock()
+        .with_context(|| format!("Failed to lock {:?}", crate_lock_file(key)))?;
+    Ok(file)
+}
+
+/// Like `lock_crate_path`, but `None` instead of waiting when another process holds the lock
+pub fn try_lock_crate_path(key: &str) -> Result<Option<File>> {
+    let file = create_crate_lock_file(key)?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(std::fs::TryLockError::WouldBlock) => Ok(None),
+        Err(std::fs::TryLockError::Error(e)) => {
+            Err(e).with_context(|| format!("Failed to lock {:?}", crate_lock_file(key)))
+        }
+    }
+}
+
+/// How long ago a crate's source directory was last locked, i.e. last fetched; `None`
+/// if it never was. The lock only covers extraction, so a recent one may mean the
+/// crate is still being indexed.
+pub fn crate_lock_age(key: &str) -> Option<Duration> {
+    let modified = std::fs::metadata(crate_lock_file(key)).and_then(|m| m.modified()).ok()?;
+    modified.elapsed().ok()
+}
+
+/// Test fixture: a small crate (a library, a binary and an integration test) indexed
+/// with its file contents, its sources deleted afterwards. `add_files` can put more
+/// files into the crate directory before it is indexed.
+#[cfg(test)]
+pub fn demo_index(add_files: impl FnOnce(&Path)) -> (tempfile::TempDir, Database) {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::open_at(&tmp.path().join("index.db")).unwrap();
+    let crate_dir = tmp.path().join("demo-0.1.0");
+    std::fs::create_dir_all(crate_dir.join("src/bin")).unwrap();
+    std::fs::create_dir_all(crate_dir.join("tests")).unwrap();
+    std::fs::write(crate_dir.join("Cargo.toml"), "[package]\nname = \"demo\"\n").unwrap();
+    std::fs::write(crate_dir.join("src/lib.rs"), "/// Parses.\npub fn parse() {}\n").unwrap();
+    std::fs::write(crate_dir.join("src/bin/tool.rs"), "fn main() {}\n").unwrap();
+    std::fs::write(crate_dir.join("tests/parse.rs"), "#[test]\nfn parses() { demo::parse() }\n").unwrap();
+    add_files(&crate_dir);
+
+    let result = crate::indexer::index_crate(&crate_dir, "demo-0.1.0").unwrap();
+    db.add_crate("demo-0.1.0", &crate_dir, &result.items, &[]).unwrap();
+    std::fs::remove_dir_all(&crate_dir).unwrap();
+    (tmp, db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNVERSIONED_FIXTURE: &str = include_str!("../tests/fixtures/index-unversioned.sql");
+
+    fn user_version(db: &Database) -> usize {
+        db.conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_upgrade_unversioned_database() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("index.db");
+        let crate_dir = tmp.path().join("demo-0.1.0");
+        std::fs::create_dir_all(crate_dir.join("src")).unwrap();
+        std::fs::write(crate_dir.join("Cargo.toml"), "[package]\nname = \"demo\"\nversion = \"0.1.0\"\nlicense = \"MIT\"\n").unwrap();
+        std::fs::write(crate_dir.join("src/lib.rs"), "pub fn new_name() {}\n").unwrap();
+
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(UNVERSIONED_FIXTURE).unwrap();
+        conn.execute("UPDATE crates SET path = ?", [crate_dir.to_string_lossy()]).unwrap();
+        drop(conn);
+
+        let db = Database::open_at(&db_path).unwrap();
+        assert_eq!(user_version(&db), MIGRATIONS.len());
+
+        // Existing rows survive the upgrade, and the crate is flagged for re-indexing
+        let structs = db.get_structs("demo-0.1.0").unwrap();
+        assert_eq!(structs[0].fields[0].name, "verbose");
+        assert_eq!(db.get_cached_latest_version("demo", u64::MAX).unwrap().as_deref(), Some("0.1.0"));
+        assert_eq!(db.stale_crate_keys().unwrap(), vec!["demo-0.1.0"]);
+        assert!(db.get_metadata("demo-0.1.0").unwrap().is_none());
+
+        assert!(crate::indexer::reindex_if_stale(&db, "demo-0.1.0").unwrap());
+        assert!(!db.is_stale("demo-0.1.0").unwrap());
+        assert_eq!(db.get_crate_id("demo-0.1.0").unwrap(), Some(1));
+        let names: Vec<String> = db.get_functions("demo-0.1.0").unwrap().into_iter().map(|f| f.name).collect();
+        assert_eq!(names, vec!["new_name"]);
+        assert!(db.get_structs("demo-0.1.0").unwrap().is_empty());
+        assert_eq!(db.get_metadata("demo-0.1.0").unwrap().unwrap().license.as_deref(), Some("MIT"));
+
+        // Reopening is a no-op
+        drop(db);
+        let db = Database::open_at(&db_path).unwrap();
+        assert_eq!(user_version(&db), MIGRATIONS.len());
+    }
+
+    fn function(id: &str, name: &str, signature: &str, docs: Option<&str>) -> FunctionInfo {
+        FunctionInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            file: "src/lib.rs".to_string(),
+            line: 1,
+            end_line: None,
+            signature: signature.to_string(),
+            docs: docs.map(String::from),
+            type_signature: None,
+        }
+    }
+
+    #[test]
+    fn test_full_text_search() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Database::open_at(&tmp.path().join("index.db")).unwrap();
+
+        let mut items = CrateItems::default();
+        items.functions.push(function("00000001", "from_str", "pub fn from_str(s: &str) -> Result<Value>", Some("Deserialize a value from a string of JSON text.")));
+        items.functions.push(function("00000002", "to_writer", "pub fn to_writer<W: Write>(writer: W, value: &T)", Some("Serialize the given value as JSON into the writer.")));
+        items.structs.push(StructInfo {
+            id: "00000003".to_string(),
+            name: "Deserializer".to_string(),
+            file: "src/de.rs".to_string(),
+            line: 10,
+            end_line: None,
+            visibility: "pub".to_string(),
+            fields: vec![FieldInfo {
+                name: "remaining_depth".to_string(),
+                type_str: "u8".to_string(),
+                visibility: "".to_string(),
+                docs: None,
+            }],
+            docs: None,
+        });
+        db.add_crate("json-1.0.0", Path::new("/tmp/json"), &items, &[]).unwrap();
+
+        let mut other = CrateItems::default();
+        other.functions.push(function("00000004", "from_str", "pub fn from_str(s: &str) -> Result<Doc>", None));
+        db.add_crate("yaml-0.9.0", Path::new("/tmp/yaml"), &other, &[]).unwrap();
+
+        let search = |query: &str, crates: Option<&[String]>, kind: Option<&str>| -> Vec<String> {
+            let fts = crate::search::fts_query(query).unwrap();
+            db.search_items(&fts, crates, kind, 10).unwrap().into_iter().map(|h| h.id).collect()
+        };
+
+        assert_eq!(search("from_str", None, None).len(), 2);
+        assert_eq!(search("from_str", Some(&["yaml-0.9.0".to_string()]), None), vec!["00000004"]);
+        assert_eq!(search("deserial*", None, None)[0], "00000003"); // name match ranks above docs
+        assert_eq!(search("deserial*", None, Some("function")), vec!["00000001"]);
+        assert_eq!(search("remaining_depth", None, None), vec!["00000003"]);
+        assert_eq!(search("\"json into the\"", None, None), vec!["00000002"]);
+        assert!(search("\"into json\"", None, None).is_empty());
+        assert_eq!(search("Result::<Value>", None, None), vec!["00000001"]);
+
+        let hit = &db.search_items("\"writer\"", None, None, 10).unwrap()[0];
+        assert!(hit.snippet.contains("**writer**"), "{}", hit.snippet);
+
+        // Re-indexing replaces the rows instead of duplicating them
+        db.add_crate("json-1.0.0", Path::new("/tmp/json"), &items, &[]).unwrap();
+        assert_eq!(search("from_str", None, None).len(), 2);
+        assert!(crate::search::fts_query(" * \"\" ").is_none());
+    }
+
+    #[test]
+    fn test_remove_crate_and_orphans() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Database::open_at(&tmp.path().join("index.db")).unwrap();
+
+        let mut items = CrateItems::default();
+        items.functions.push(function("00000001", "parse", "pub fn parse()", None));
+        db.add_crate("demo-0.1.0", Path::new("/tmp/demo"), &items, &[]).unwrap();
+        items.functions[0].id = "00000002".to_string();
+        db.add_crate("demo-0.2.0", Path::new("/tmp/demo2"), &items, &[]).unwrap();
+
+        let crates = db.list_crates().unwrap();
+        assert_eq!(crates.iter().map(|c| c.key.as_str()).collect::<Vec<_>>(), ["demo-0.1.0", "demo-0.2.0"]);
+        assert_eq!(crates[0].item_count, 1);
+        assert!(!crates[0].has_embeddings);
+        assert!(crates[0].fetched_at.is_some());
+
+        assert!(db.remove_crate("demo-0.1.0").unwrap());
+        assert!(!db.remove_crate("demo-0.1.0").unwrap());
+        assert_eq!(db.list_crate_keys().unwrap(), ["demo-0.2.0"]);
+        assert_eq!(db.delete_orphaned_rows().unwrap(), 0);
+
+        // Foreign keys cascade to the regular tables, but not to the full-text index
+        db.conn.execute("DELETE FROM crates", []).unwrap();
+        assert_eq!(db.delete_orphaned_rows().unwrap(), 1);
+        db.compact().unwrap();
+    }
+
+    #[test]
+    fn test_incremental_reindex() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Database::open_at(&tmp.path().join("index.db")).unwrap();
+        let crate_dir = tmp.path().join("demo-0.1.0");
+        std::fs::create_dir_all(crate_dir.join("src")).unwrap();
+        std::fs::write(crate_dir.join("src/lib.rs"), "pub mod util;\n/// Parses.\npub fn parse() {}\npub fn old() {}\n").unwrap();
+        std::fs::write(crate_dir.join("src/util.rs"), "pub struct Helper;\n").unwrap();
+
+        let index = |db: &Database| {
+            let result = crate::indexer::index_crate_incremental(db, &crate_dir, "demo-0.1.0").unwrap();
+            db.add_crate("demo-0.1.0", &crate_dir, &result.items, &result.reexported_crates).unwrap();
+            result.items
+        };
+        let id = |name: &str| db.resolve_item_path("demo-0.1.0", name).unwrap();
+        assert_eq!(index(&db).files.len(), 2);
+
+        let crate_id = db.get_crate_id("demo-0.1.0").unwrap().unwrap();
+        let embedded: Vec<_> = crate::embeddings::pending_items(&db, "demo-0.1.0").unwrap()
+            .into_iter()
+            .map(|(id, item_type, text)| (id, item_type, vec![0u8; 4], text))
+            .collect();
+        assert_eq!(embedded.len(), 3);
+        db.save_embeddings(crate_id, &embedded).unwrap();
+        let (parse_id, helper_id) = (id("parse"), id("util::Helper"));
+
+        // Unchanged files are not parsed again, and their items and embeddings stay
+        let items = index(&db);
+        assert!(items.files.iter().all(|f| f.reused));
+        assert!(items.paths.is_empty());
+        assert_eq!(db.get_functions("demo-0.1.0").unwrap().len(), 2);
+        assert!(crate::embeddings::pending_items(&db, "demo-0.1.0").unwrap().is_empty());
+
+        // Editing lib.rs re-parses only it; `parse` keeps its embedding unless its text changed
+        std::fs::write(crate_dir.join("src/lib.rs"), "pub mod util;\n/// Parses input.\npub fn parse() {}\npub fn new() {}\n").unwrap();
+        let items = index(&db);
+        assert_eq!(items.files.iter().filter(|f| !f.reused).map(|f| f.path.as_str()).collect::<Vec<_>>(), ["src/lib.rs"]);
+        assert_eq!(id("util::Helper"), helper_id);
+        assert_eq!(id("parse"), parse_id);
+        assert!(db.resolve_item_path("demo-0.1.0", "old").is_err());
+        assert_eq!(db.search_items("Helper", None, None, 10).unwrap().len(), 1);
+        // The trigram index covers reused files as well as the re-parsed one
+        let matches = crate::search::search_regex(&db, "demo-0.1.0", r"Helper|fn new", &Default::default()).unwrap();
+        assert_eq!(matches.iter().map(|m| m.file.as_str()).collect::<Vec<_>>(), ["src/lib.rs", "src/util.rs"]);
+
+        let embedded = db.get_embedded_texts("demo-0.1.0").unwrap();
+        assert_eq!(embedded.len(), 2);
+        assert!(embedded.contains_key(&helper_id));
+        let pending: Vec<String> = crate::embeddings::pending_items(&db, "demo-0.1.0").unwrap()
+            .into_iter()
+            .map(|(_, _, text)| text)
+            .collect();
+        assert_eq!(pending, ["fn parse(). Parses input.", "fn new()"]);
+    }
+
+    #[test]
+    fn test_files_readable_without_sources() {
+        let (_tmp, db) = demo_index(|crate_dir| {
+            std::fs::write(crate_dir.join("README.md"), "# Demo\n").unwrap();
+            std::fs::write(crate_dir.join("CHANGELOG.md"), "# 0.1.0\n").unwrap();
+            std::fs::write(crate_dir.join("logo.png"), [0x89, b'P', b'N', b'G', 0xff]).unwrap();
+            // Build output and version control data are left out, a `target` module isn't
+            for dir in ["target/debug", ".git", "src/target"] {
+                std::fs::create_dir_all(crate_dir.join(dir)).unwrap();
+            }
+            std::fs::write(crate_dir.join("target/debug/out.rs"), "fn generated() {}\n").unwrap();
+            std::fs::write(crate_dir.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+            std::fs::write(crate_dir.join("src/target/mod.rs"), "pub struct Triple;\n").unwrap();
+        });
+
+        let files: Vec<_> = db.list_files("demo-0.1.0").unwrap().into_iter()
+            .map(|f| (f.path, f.lines, f.language, f.target))
+            .collect();
+        let s = |v: &str| Some(v.to_string());
+        assert_eq!(files, [
+            ("CHANGELOG.md".to_string(), 1, s("markdown"), None),
+            ("Cargo.toml".to_string(), 2, s("toml"), None),
+            ("README.md".to_string(), 1, s("markdown"), None),
+            ("logo.png".to_string(), 0, None, None),
+            ("src/bin/tool.rs".to_string(), 1, s("rust"), s("bin")),
+            ("src/lib.rs".to_string(), 2, s("rust"), s("lib")),
+            ("src/target/mod.rs".to_string(), 1, s("rust"), s("lib")),
+            ("tests/parse.rs".to_string(), 2, s("rust"), s("test")),
+        ]);
+
+        // Only sources, manifests and the README have their contents stored
+        assert_eq!(db.read_file("demo-0.1.0", "Cargo.toml").unwrap().as_deref(), Some("[package]\nname = \"demo\"\n"));
+        assert_eq!(db.read_file("demo-0.1.0", "README.md").unwrap().as_deref(), Some("# Demo\n"));
+        assert!(db.read_file("demo-0.1.0", "CHANGELOG.md").unwrap().is_none());
+        assert!(db.read_file("demo-0.1.0", "logo.png").unwrap().is_none());
+        assert!(db.read_file("demo-0.1.0", "missing.rs").unwrap().is_none());
+        let matches = crate::search::search_regex(&db, "demo-0.1.0", r"parse\(", &Default::default()).unwrap();
+        assert_eq!(matches.iter().map(|m| (m.file.as_str(), m.line)).collect::<Vec<_>>(), [("src/lib.rs", 2), ("tests/parse.rs", 2)]);
+    }
+
+    #[test]
+    fn test_dependency_declarations() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Database::open_at(&tmp.path().join("index.db")).unwrap();
+        let crate_dir = tmp.path().join("demo-0.1.0");
+        std::fs::create_dir_all(&crate_dir).unwrap();
+        std::fs::write(crate_dir.join("Cargo.toml"), r#"
+            [package]
+            name = "demo"
+
+            [dependencies]
+            serde = "1"
+            serde_old = { package = "serde", version = "0.9" }
+            winapi = "0.2"
+
+            [dev-dependencies]
+            serde = { version = "1", features = ["derive"] }
+
+            [target.'cfg(windows)'.dependencies]
+            winapi = { version = "0.3", optional = true }
+        "#).unwrap();
+
+        let items = CrateItems { dependencies: crate::indexer::parse_manifest_dependencies(&crate_dir), ..Default::default() };
+        db.add_crate("demo-0.1.0", &crate_dir, &items, &[]).unwrap();
+
+        let deps: Vec<String> = db.get_dependencies("demo-0.1.0").unwrap().iter()
+            .map(|d| format!("{} {}{}", d.name, d.req, d.flags()))
+            .collect();
+        // A crate can depend on one package as dev and normal dependency, under two
+        // names and with a different version per platform
+        assert_eq!(deps, [
+            "serde 1 [dev]",
+            "serde 1",
+            "serde 0.9 [as serde_old]",
+            "winapi 0.2",
+            "winapi 0.3 [cfg(windows), optional]",
+        ]);
+        assert_eq!(db.get_dependents("winapi").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_package_summary() {
+        let meta = PackageMetadata {
+            description: Some("A demo crate".to_string()),
+            documentation: Some("https://docs.rs/demo".to_string()),
+            keywords: vec!["demo".to_string(), "test".to_string()],
+            ..Default::default()
+        };
+        let deps = [DependencyInfo {
+            name: "serde".to_string(),
+            req: "1".to_string(),
+            kind: "dev".to_string(),
+            optional: false,
+            target: None,
+            alias: None,
+        }];
+        let summary = meta.summary("demo-0.1.0", &deps);
+        assert!(summary.starts_with("demo-0.1.0\n  A demo crate\n\n"));
+        assert!(summary.contains("\nLicense:       -\n"));
+        assert!(summary.contains("\nDocumentation: https://docs.rs/demo\n"));
+        assert!(summary.contains("\nKeywords:      demo, test\n"));
+        assert!(summary.ends_with("\nDependencies:\n  serde 1 [dev]\n"));
+    }
+
+    #[test]
+    fn test_journal_mode_set_on_creation() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("index.db");
+        let journal_mode = |db: &Database| -> String {
+            db.conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap()
+        };
+
+        let db = Database::open_at(&db_path).unwrap();
+        assert_eq!(journal_mode(&db), "wal");
+        db.conn.query_row("PRAGMA journal_mode = DELETE", [], |_| Ok(())).unwrap();
+        drop(db);
+
+        // Opening an up-to-date database leaves its journal mode alone
+        let db = Database::open_at(&db_path).unwrap();
+        assert_eq!(journal_mode(&db), "delete");
+    }
+
+    #[test]
+    fn test_read_during_write() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("index.db");
+        let writer = Database::open_at(&db_path).unwrap();
+        let reader = Database::open_at(&db_path).unwrap();
+        writer.add_crate("demo-0.1.0", Path::new("/tmp/demo"), &CrateItems::default(), &[]).unwrap();
+
+        let tx = Transaction::new_unchecked(&writer.conn, TransactionBehavior::Immediate).unwrap();
+        tx.execute("DELETE FROM crates", []).unwrap();
+        // The reader sees the last committed state instead of failing with SQLITE_BUSY
+        assert_eq!(reader.list_crate_keys().unwrap(), ["demo-0.1.0"]);
+        tx.commit().unwrap();
+        assert!(reader.list_crate_keys().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_item_selectors() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Database::open_at(&tmp.path().join("index.db")).unwrap();
+
+        let path = |id: &str, kind: &str, path: &str| ItemPath { id: id.to_string(), kind: kind.to_string(), path: path.to_string() };
+        let items = CrateItems {
+            paths: vec![
+                path("aaaa000000000001", "struct", "de::Deserializer"),
+                path("aaaa000000000002", "impl", "de::Deserializer"),
+                path("aaab000000000003", "function", "de::Deserializer::new"),
+                path("bbbb000000000004", "function", "ser::new"),
+            ],
+            ..Default::default()
+        };
+        db.add_crate("demo-0.1.0", Path::new("/tmp/demo"), &items, &[]).unwrap();
+
+        assert_eq!(db.resolve_item_id("aaab").unwrap(), "aaab000000000003");
+        assert_eq!(db.resolve_item_id("AAAA000000000002").unwrap(), "aaaa000000000002");
+        assert!(db.resolve_item_id("aaaa").unwrap_err().to_string().contains("matches several items"));
+        assert!(db.resolve_item_id("aa").is_err());
+        assert!(db.resolve_item_id("cccc").is_err());
+
+        assert_eq!(db.resolve_item_path("demo-0.1.0", "de::Deserializer").unwrap(), "aaaa000000000001");
+        assert_eq!(db.resolve_item_path("demo-0.1.0", "Deserializer").unwrap(), "aaaa000000000001");
+        assert_eq!(db.resolve_item_path("demo-0.1.0", "Deserializer::new").unwrap(), "aaab000000000003");
+        assert!(db.resolve_item_path("demo-0.1.0", "new").is_err()); // two suffix matches
+        assert!(db.resolve_item_path("demo-0.1.0", "deserializer").is_err());
+
+        // An ID already used by another crate is reported as a collision
+        let err = db.add_crate("other-0.1.0", Path::new("/tmp/other"), &items, &[]).unwrap_err();
+        assert!(err.to_string().contains("collision"));
+    }
+
+    #[test]
+    fn test_open_shared_with_uri_characters() {
+        let tmp = tempfile::tempdir().unwrap();
+        let shared_dir = tmp.path().join("team #1?v=2 100%");
+        Database::open_at(&shared_dir.join(DB_FILE)).unwrap();
+        assert!(Database::open_shared(&shared_dir).is_ok());
+        assert_eq!(file_uri(Path::new("/a b/#1?%")), "file:/a%20b/%231%3F%25");
+    }
+
+    #[test]
+    fn test_shared_index_fall_through() {
+        let tmp = tempfile::tempdir().unwrap();
+        let shared_dir = tmp.path().join("shared");
+        let items = |id: &str, name: &str| CrateItems {
+            functions: vec![function(id, name, &format!("pub fn {}()", name), None)],
+            ..Default::default()
+        };
+
+        let team = Database::open_at(&shared_dir.join(DB_FILE)).unwrap();
+        team.add_crate("team-1.0.0", Path::new("/elsewhere/crates/team-1.0.0"), &items("00000001", "shared_fn"), &[]).unwrap();
+        team.add_crate("both-1.0.0", Path::new("/elsewhere/crates/both-1.0.0"), &items("00000002", "old_fn"), &[]).unwrap();
+        drop(team);
+
+        let mut db = Database::open_at(&tmp.path().join("local").join(DB_FILE)).unwrap();
+        db.add_crate("both-1.0.0", Path::new("/tmp/both"), &items("00000003", "new_fn"), &[]).unwrap();
+        db.shared.push(Database::open_shared(&shared_dir).unwrap());
+
+        let mut keys = db.list_crate_keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, ["both-1.0.0", "team-1.0.0"]);
+        assert_eq!(db.local_crate_keys().unwrap(), ["both-1.0.0"]);
+        assert_eq!(db.find_crate_key("team").unwrap().as_deref(), Some("team-1.0.0"));
+
+        // Shared crates are read from the shared index, with sources relocated under it
+        assert_eq!(db.get_functions("team-1.0.0").unwrap()[0].name, "shared_fn");
+        assert_eq!(db.get_crate_path("team-1.0.0").unwrap().unwrap(), shared_dir.join("crates/team-1.0.0"));
+        assert!(db.get_function_by_id("00000001").unwrap().is_some());
+
+        // The writable index shadows the shared copy of a crate
+        assert_eq!(db.get_functions("both-1.0.0").unwrap()[0].name, "new_fn");
+        let fts = crate::search::fts_query("fn").unwrap();
+        let mut found: Vec<String> = db.search_items(&fts, None, None, 10).unwrap().into_iter().map(|h| h.name).collect();
+        found.sort();
+        assert_eq!(found, ["new_fn", "shared_fn"]);
+        assert!(db.list_crates().unwrap().iter().any(|c| c.key == "team-1.0.0" && c.shared));
+    }
+
+    #[test]
+    fn test_export_import_crates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ci = Database::open_at(&tmp.path().join("ci.db")).unwrap();
+        let items = CrateItems {
+            functions: vec![function("00000001", "parse", "pub fn parse()", None)],
+            structs: vec![StructInfo {
+                id: "00000002".to_string(),
+                name: "Config".to_string(),
+                file: "src/lib.rs".to_string(),
+                line: 3,
+                end_line: None,
+                visibility: "pub".to_string(),
+                fields: vec![FieldInfo { name: "depth".to_string(), type_str: "u8".to_string(), visibility: "pub".to_string(), docs: None }],
+                docs: None,
+            }],
+            dependencies: vec![DependencyInfo {
+                name: "serde".to_string(),
+                req: "^1".to_string(),
+                kind: "normal".to_string(),
+                optional: false,
+                target: None,
+                alias: None,
+            }],
+            ..Default::default()
+        };
+        ci.add_crate("demo-0.1.0", Path::new("/ci/crates/demo-0.1.0"), &items, &["serde".to_string()]).unwrap();
+        ci.add_crate("other-0.1.0", Path::new("/ci/crates/other-0.1.0"), &CrateItems::default(), &[]).unwrap();
+
+        let bundle = tmp.path().join("bundle.db");
+        ci.export_crates(&["demo-0.1.0".to_string()], &bundle).unwrap();
+
+        let local = Database::open_at(&tmp.path().join("local.db")).unwrap();
+        local.add_crate("unrelated-1.0.0", Path::new("/tmp/unrelated"), &CrateItems::default(), &[]).unwrap();
+        for _ in 0..2 {
+            // Importing twice replaces the first copy
+            local.import_crate(&bundle, "demo-0.1.0", Some(Path::new("/home/dev/crates/demo-0.1.0"))).unwrap();
+        }
+
+        let mut keys = local.list_crate_keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, ["demo-0.1.0", "unrelated-1.0.0"]);
+        assert_eq!(local.get_crate_path("demo-0.1.0").unwrap().unwrap(), Path::new("/home/dev/crates/demo-0.1.0"));
+        assert_eq!(local.get_functions("demo-0.1.0").unwrap()[0].name, "parse");
+        assert_eq!(local.get_structs("demo-0.1.0").unwrap()[0].fields[0].name, "depth");
+        assert_eq!(local.get_dependencies("demo-0.1.0").unwrap()[0].name, "serde");
+        assert_eq!(local.get_reexports("demo-0.1.0").unwrap(), ["serde"]);
+        let fts = crate::search::fts_query("parse").unwrap();
+        assert_eq!(local.search_items(&fts, None, None, 10).unwrap().len(), 1);
+        assert!(local.import_crate(&bundle, "other-0.1.0", Some(Path::new("/tmp/other"))).is_err());
+    }
+
+    #[test]
+    fn test_refuse_newer_schema() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("index.db");
+
+        let db = Database::open_at(&db_path).unwrap();
+        assert_eq!(user_version(&db), MIGRATIONS.len());
+        db.conn.execute_batch(&format!("PRAGMA user_version = {}", MIGRATIONS.len() + 1)).unwrap();
+        drop(db);
+
+        let err = Database::open_at(&db_path).err().unwrap();
+        assert!(err.to_string().contains("schema version"));
+    }
+}
@@ -0,0 +1,531 @@
+//! Structural search: item patterns such as `fn $name(..) -> Result<$_, io::Error>`
+//! or `impl From<$t> for $_`, matched against the syntax trees built by the indexer.
+//!
+//! A pattern names a kind of item and the parts to compare. Each part is matched
+//! token by token, where `$name` stands for one identifier, type or expression
+//! (the same name must match the same tokens everywhere), `$_` for any one of them
+//! and `..` for any tokens at all. Parts left out of the pattern match anything;
+//! attributes, visibility and where clauses are never compared.
+
+use anyhow::Result;
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::collections::BTreeMap;
+use syn::{Fields, ItemEnum, ItemImpl, ItemStruct, ItemTrait, ItemType, ReturnType, Signature};
+
+const FORMS: &str = "fn NAME(PARAMS) -> TYPE, impl TRAIT for TYPE, impl TYPE, struct NAME { FIELDS }, \
+    enum NAME { VARIANTS }, trait NAME, type NAME = TYPE, const NAME: TYPE or static NAME: TYPE";
+
+/// Tokens bound to each named metavariable
+pub type Bindings = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    /// `$name`, or `$_` without a name
+    Var(Option<String>),
+    /// `..`
+    Rest,
+    Group(Delimiter, Vec<Tok>),
+    Punct(char),
+    /// Identifier or literal
+    Word(String),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct FnQualifiers {
+    is_const: bool,
+    is_async: bool,
+    is_unsafe: bool,
+}
+
+/// A parsed structural pattern
+#[derive(Debug)]
+pub struct Pattern {
+    form: Form,
+}
+
+#[derive(Debug)]
+enum Form {
+    Fn { qualifiers: FnQualifiers, name: Tok, generics: Option<Vec<Tok>>, params: Vec<Tok>, output: Option<Vec<Tok>> },
+    Impl { generics: Option<Vec<Tok>>, trait_: Option<Vec<Tok>>, self_ty: Vec<Tok> },
+    Struct { name: Tok, generics: Option<Vec<Tok>>, fields: Option<Tok> },
+    Enum { name: Tok, generics: Option<Vec<Tok>>, variants: Option<Vec<Tok>> },
+    Trait { name: Tok, generics: Option<Vec<Tok>> },
+    TypeAlias { name: Tok, generics: Option<Vec<Tok>>, ty: Option<Vec<Tok>> },
+    Const { is_static: bool, name: Tok, ty: Option<Vec<Tok>> },
+}
+
+/// An item matching a pattern
+#[derive(Debug)]
+pub struct StructuralMatch {
+    pub id: String,
+    pub kind: String,
+    pub file: String,
+    pub line: usize,
+    pub end_line: Option<usize>,
+    /// First line of the item's source
+    pub text: String,
+    pub bindings: Bindings,
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Pattern> {
+        let stream: TokenStream = pattern
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid pattern '{}': {}", pattern, e))?;
+        let toks = convert(stream);
+        let mut rest = toks.as_slice();
+        if let [head @ .., Tok::Punct(';')] = rest {
+            rest = head;
+        }
+
+        let mut qualifiers = FnQualifiers::default();
+        loop {
+            match rest {
+                [Tok::Word(w), Tok::Word(next), ..] if w == "const" && (next == "fn" || next == "async" || next == "unsafe") => {
+                    qualifiers.is_const = true
+                }
+                [Tok::Word(w), ..] if w == "async" => qualifiers.is_async = true,
+                [Tok::Word(w), ..] if w == "unsafe" => qualifiers.is_unsafe = true,
+                _ => break,
+            }
+            rest = &rest[1..];
+        }
+
+        let (keyword, rest) = match rest {
+            [Tok::Word(keyword), rest @ ..] => (keyword.as_str(), rest),
+            _ => anyhow::bail!("Pattern must be one of: {}", FORMS),
+        };
+        let form = match keyword {
+            "fn" => {
+                let (name, rest) = split_name(rest)?;
+                let (generics, rest) = split_generics(rest);
+                let (params, rest) = match rest {
+                    [Tok::Group(Delimiter::Parenthesis, params), rest @ ..] => (params.clone(), rest),
+                    _ => anyhow::bail!("Expected parameters in parentheses after the function name, e.g. `fn $name(..)`"),
+                };
+                let output = match rest {
+                    [] => None,
+                    [Tok::Punct('-'), Tok::Punct('>'), ty @ ..] if !ty.is_empty() => Some(ty.to_vec()),
+                    _ => anyhow::bail!("Expected `-> TYPE` or nothing after the parameters"),
+                };
+                Form::Fn { qualifiers, name, generics, params, output }
+            }
+            "impl" => {
+                let (generics, rest) = split_generics(rest);
+                let (trait_, self_ty) = match top_level_position(rest, |t| *t == Tok::Word("for".to_string())) {
+                    Some(i) => (Some(rest[..i].to_vec()), rest[i + 1..].to_vec()),
+                    None => (None, rest.to_vec()),
+                };
+                if self_ty.is_empty() || trait_.as_ref().is_some_and(Vec::is_empty) {
+                    anyhow::bail!("Expected `impl TYPE` or `impl TRAIT for TYPE`");
+                }
+                Form::Impl { generics, trait_, self_ty }
+            }
+            "struct" => {
+                let (name, rest) = split_name(rest)?;
+                let (generics, rest) = split_generics(rest);
+                let fields = match rest {
+                    [] => None,
+                    [group @ Tok::Group(Delimiter::Brace | Delimiter::Parenthesis, _)] => Some(group.clone()),
+                    _ => anyhow::bail!("Expected `{{ FIELDS }}`, `(FIELDS)` or nothing after the struct name"),
+                };
+                Form::Struct { name, generics, fields }
+            }
+            "enum" => {
+                let (name, rest) = split_name(rest)?;
+                let (generics, rest) = split_generics(rest);
+                let variants = match rest {
+                    [] => None,
+                    [Tok::Group(Delimiter::Brace, variants)] => Some(variants.clone()),
+                    _ => anyhow::bail!("Expected `{{ VARIANTS }}` or nothing after the enum name"),
+                };
+                Form::Enum { name, generics, variants }
+            }
+            "trait" => {
+                let (name, rest) = split_name(rest)?;
+                let (generics, rest) = split_generics(rest);
+                if !rest.is_empty() {
+                    anyhow::bail!("Expected nothing after `trait NAME`");
+                }
+                Form::Trait { name, generics }
+            }
+            "type" => {
+                let (name, rest) = split_name(rest)?;
+                let (generics, rest) = split_generics(rest);
+                let ty = match rest {
+                    [] => None,
+                    [Tok::Punct('='), ty @ ..] if !ty.is_empty() => Some(ty.to_vec()),
+                    _ => anyhow::bail!("Expected `= TYPE` or nothing after the type alias name"),
+                };
+                Form::TypeAlias { name, generics, ty }
+            }
+            "const" | "static" => {
+                let rest = match rest {
+                    [Tok::Word(w), rest @ ..] if w == "mut" => rest,
+                    _ => rest,
+                };
+                let (name, rest) = split_name(rest)?;
+                let ty = match rest {
+                    [] => None,
+                    [Tok::Punct(':'), ty @ ..] if !ty.is_empty() => Some(ty.to_vec()),
+                    _ => anyhow::bail!("Expected `: TYPE` or nothing after the name"),
+                };
+                Form::Const { is_static: keyword == "static", name, ty }
+            }
+            _ => anyhow::bail!("Pattern must be one of: {}", FORMS),
+        };
+
+        if !matches!(form, Form::Fn { .. }) && (qualifiers.is_async || qualifiers.is_unsafe || qualifiers.is_const) {
+            anyhow::bail!("`const`, `async` and `unsafe` only apply to `fn` patterns");
+        }
+        Ok(Pattern { form })
+    }
+
+    /// Identifiers and literals every file with a match must contain
+    pub fn literals(&self) -> Vec<String> {
+        fn collect(toks: &[Tok], words: &mut Vec<String>) {
+            for tok in toks {
+                match tok {
+                    Tok::Word(w) => words.push(w.clone()),
+                    Tok::Group(_, inner) => collect(inner, words),
+                    _ => {}
+                }
+            }
+        }
+        let mut words = Vec::new();
+        let parts: Vec<&[Tok]> = match &self.form {
+            Form::Fn { name, generics, params, output, .. } => {
+                vec![std::slice::from_ref(name), generics.as_deref().unwrap_or_default(), params, output.as_deref().unwrap_or_default()]
+            }
+            Form::Impl { generics, trait_, self_ty } => {
+                vec![generics.as_deref().unwrap_or_default(), trait_.as_deref().unwrap_or_default(), self_ty]
+            }
+            Form::Struct { name, generics, fields } => {
+                vec![std::slice::from_ref(name), generics.as_deref().unwrap_or_default(), fields.as_slice()]
+            }
+            Form::Enum { name, generics, variants } => {
+                vec![std::slice::from_ref(name), generics.as_deref().unwrap_or_default(), variants.as_deref().unwrap_or_default()]
+            }
+            Form::Trait { name, generics } => vec![std::slice::from_ref(name), generics.as_deref().unwrap_or_default()],
+            Form::TypeAlias { name, generics, ty } => {
+                vec![std::slice::from_ref(name), generics.as_deref().unwrap_or_default(), ty.as_deref().unwrap_or_default()]
+            }
+            Form::Const { name, ty, .. } => vec![std::slice::from_ref(name), ty.as_deref().unwrap_or_default()],
+        };
+        for part in parts {
+            collect(part, &mut words);
+        }
+        words
+    }
+
+    pub fn match_fn(&self, sig: &Signature) -> Option<Bindings> {
+        let Form::Fn { qualifiers, name, generics, params, output } = &self.form else { return None };
+        if (qualifiers.is_const && sig.constness.is_none())
+            || (qualifiers.is_async && sig.asyncness.is_none())
+            || (qualifiers.is_unsafe && sig.unsafety.is_none())
+        {
+            return None;
+        }
+        let mut bindings = Bindings::new();
+        let returned = match &sig.output {
+            ReturnType::Default => quote::quote!(()),
+            ReturnType::Type(_, ty) => ty.to_token_stream(),
+        };
+        (match_name(name, &sig.ident, &mut bindings)
+            && match_optional(generics.as_deref(), &sig.generics, &mut bindings)
+            && match_part(params, &sig.inputs, &mut bindings)
+            && match_optional(output.as_deref(), &returned, &mut bindings))
+        .then_some(bindings)
+    }
+
+    pub fn match_impl(&self, item: &ItemImpl) -> Option<Bindings> {
+        let Form::Impl { generics, trait_, self_ty } = &self.form else { return None };
+        let mut bindings = Bindings::new();
+        let trait_matches = match (trait_, &item.trait_) {
+            (None, None) => true,
+            (Some(pattern), Some((negative, path, _))) => {
+                match_part(pattern, &quote::quote!(#negative #path), &mut bindings)
+            }
+            _ => false,
+        };
+        (trait_matches
+            && match_optional(generics.as_deref(), &item.generics, &mut bindings)
+            && match_part(self_ty, &item.self_ty, &mut bindings))
+        .then_some(bindings)
+    }
+
+    pub fn match_struct(&self, item: &ItemStruct) -> Option<Bindings> {
+        let Form::Struct { name, generics, fields } = &self.form else { return None };
+        let mut bindings = Bindings::new();
+        let fields_match = match (fields, &item.fields) {
+            (None, _) => true,
+            (Some(pattern), Fields::Named(_) | Fields::Unnamed(_)) => {
+                match_part(std::slice::from_ref(pattern), &item.fields, &mut bindings)
+            }
+            (Some(_), Fields::Unit) => false,
+        };
+        (fields_match
+            && match_name(name, &item.ident, &mut bindings)
+            && match_optional(generics.as_deref(), &item.generics, &mut bindings))
+        .then_some(bindings)
+    }
+
+    pub fn match_enum(&self, item: &ItemEnum) -> Option<Bindings> {
+        let Form::Enum { name, generics, variants } = &self.form else { return None };
+        let mut bindings = Bindings::new();
+        (match_name(name, &item.ident, &mut bindings)
+            && match_optional(generics.as_deref(), &item.generics, &mut bindings)
+            && match_optional(variants.as_deref(), &item.variants, &mut bindings))
+        .then_some(bindings)
+    }
+
+    pub fn match_trait(&self, item: &ItemTrait) -> Option<Bindings> {
+        let Form::Trait { name, generics } = &self.form else { return None };
+        let mut bindings = Bindings::new();
+        (match_name(name, &item.ident, &mut bindings)
+            && match_optional(generics.as_deref(), &item.generics, &mut bindings))
+        .then_some(bindings)
+    }
+
+    pub fn match_type_alias(&self, item: &ItemType) -> Option<Bindings> {
+        let Form::TypeAlias { name, generics, ty } = &self.form else { return None };
+        let mut bindings = Bindings::new();
+        (match_name(name, &item.ident, &mut bindings)
+            && match_optional(generics.as_deref(), &item.generics, &mut bindings)
+            && match_optional(ty.as_deref(), &item.ty, &mut bindings))
+        .then_some(bindings)
+    }
+
+    pub fn match_const(&self, is_static: bool, ident: &syn::Ident, ty: &syn::Type) -> Option<Bindings> {
+        let Form::Const { is_static: wanted, name, ty: pattern } = &self.form else { return None };
+        let mut bindings = Bindings::new();
+        (*wanted == is_static
+            && match_name(name, ident, &mut bindings)
+            && match_optional(pattern.as_deref(), ty, &mut bindings))
+        .then_some(bindings)
+    }
+}
+
+fn convert(stream: TokenStream) -> Vec<Tok> {
+    let mut toks = Vec::new();
+    let mut trees = stream.into_iter().peekable();
+    while let Some(tree) = trees.next() {
+        match tree {
+            TokenTree::Punct(p) if p.as_char() == '$' => match trees.peek() {
+                Some(TokenTree::Ident(ident)) => {
+                    let name = ident.to_string();
+                    toks.push(Tok::Var((name != "_").then_some(name)));
+                    trees.next();
+                }
+                _ => toks.push(Tok::Punct('$')),
+            },
+            TokenTree::Punct(p) if p.as_char() == '.' && p.spacing() == Spacing::Joint => match trees.peek() {
+                Some(TokenTree::Punct(next)) if next.as_char() == '.' => {
+                    toks.push(Tok::Rest);
+                    trees.next();
+                }
+                _ => toks.push(Tok::Punct('.')),
+            },
+            TokenTree::Punct(p) => toks.push(Tok::Punct(p.as_char())),
+            TokenTree::Group(g) => toks.push(Tok::Group(g.delimiter(), convert(g.stream()))),
+            TokenTree::Ident(i) => toks.push(Tok::Word(i.to_string())),
+            TokenTree::Literal(l) => toks.push(Tok::Word(l.to_string())),
+        }
+    }
+    toks
+}
+
+fn split_name(toks: &[Tok]) -> Result<(Tok, &[Tok])> {
+    match toks {
+        [name @ (Tok::Word(_) | Tok::Var(_)), rest @ ..] => Ok((name.clone(), rest)),
+        _ => anyhow::bail!("Expected a name or `$var` in the pattern"),
+    }
+}
+
+/// Split off a leading `<...>`
+fn split_generics(toks: &[Tok]) -> (Option<Vec<Tok>>, &[Tok]) {
+    if toks.first() != Some(&Tok::Punct('<')) {
+        return (None, toks);
+    }
+    let mut depth = 0;
+    for (i, tok) in toks.iter().enumerate() {
+        match tok {
+            Tok::Punct('<') => depth += 1,
+            Tok::Punct('>') if i > 0 && toks[i - 1] != Tok::Punct('-') => {
+                depth -= 1;
+                if depth == 0 {
+                    return (Some(toks[..=i].to_vec()), &toks[i + 1..]);
+                }
+            }
+            _ => {}
+        }
+    }
+    (None, toks)
+}
+
+/// Position of the first token outside angle brackets satisfying `predicate`
+fn top_level_position(toks: &[Tok], predicate: impl Fn(&Tok) -> bool) -> Option<usize> {
+    let mut depth = 0;
+    for (i, tok) in toks.iter().enumerate() {
+        match tok {
+            Tok::Punct('<') => depth += 1,
+            Tok::Punct('>') if i > 0 && toks[i - 1] != Tok::Punct('-') => depth -= 1,
+            _ if depth == 0 && predicate(tok) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn match_name(name: &Tok, ident: &syn::Ident, bindings: &mut Bindings) -> bool {
+    match_part(std::slice::from_ref(name), ident, bindings)
+}
+
+fn match_optional(pattern: Option<&[Tok]>, target: &impl ToTokens, bindings: &mut Bindings) -> bool {
+    pattern.is_none_or(|pattern| match_part(pattern, target, bindings))
+}
+
+fn match_part(pattern: &[Tok], target: &impl ToTokens, bindings: &mut Bindings) -> bool {
+    let trees: Vec<TokenTree> = target.to_token_stream().into_iter().collect();
+    attempt(bindings, |b| match_seq(pattern, &trees, b))
+}
+
+/// Run `f` on a copy of the bindings, keeping its changes only if it succeeds
+fn attempt(bindings: &mut Bindings, f: impl FnOnce(&mut Bindings) -> bool) -> bool {
+    let mut trial = bindings.clone();
+    let matched = f(&mut trial);
+    if matched {
+        *bindings = trial;
+    }
+    matched
+}
+
+fn match_seq(pattern: &[Tok], target: &[TokenTree], bindings: &mut Bindings) -> bool {
+    let Some((first, rest)) = pattern.split_first() else { return target.is_empty() };
+    match first {
+        // The comma of `.., x` and `x, ..` may be missing when `..` matches nothing
+        Tok::Rest => {
+            (rest.first() == Some(&Tok::Punct(',')) && attempt(bindings, |b| match_seq(&rest[1..], target, b)))
+                || (0..=target.len()).any(|n| attempt(bindings, |b| match_seq(rest, &target[n..], b)))
+        }
+        Tok::Punct(',') if rest.first() == Some(&Tok::Rest) && attempt(bindings, |b| match_seq(rest, target, b)) => true,
+        Tok::Var(name) => (1..=target.len())
+            .take_while(|&n| !has_top_level_separator(&target[..n]))
+            .filter(|&n| angles_balanced(&target[..n]))
+            .any(|n| {
+                attempt(bindings, |b| {
+                    let text = TokenStream::from_iter(target[..n].iter().cloned()).to_string();
+                    let bound = match name {
+                        Some(name) => b.entry(name.clone()).or_insert_with(|| text.clone()) == &text,
+                        None => true,
+                    };
+                    bound && match_seq(rest, &target[n..], b)
+                })
+            }),
+        _ => match target.split_first() {
+            Some((tree, target_rest)) => match_tree(first, tree, bindings) && match_seq(rest, target_rest, bindings),
+            None => false,
+        },
+    }
+}
+
+fn match_tree(tok: &Tok, tree: &TokenTree, bindings: &mut Bindings) -> bool {
+    match (tok, tree) {
+        (Tok::Group(delimiter, inner), TokenTree::Group(group)) if *delimiter == group.delimiter() => {
+            let trees: Vec<TokenTree> = group.stream().into_iter().collect();
+            match_seq(inner, &trees, bindings)
+        }
+        (Tok::Punct(c), TokenTree::Punct(p)) => *c == p.as_char(),
+        (Tok::Word(w), TokenTree::Ident(i)) => *i == *w,
+        (Tok::Word(w), TokenTree::Literal(l)) => *w == l.to_string(),
+        _ => false,
+    }
+}
+
+/// A `,` or `;` outside angle brackets: metavariables match a single type or expression
+fn has_top_level_separator(trees: &[TokenTree]) -> bool {
+    let mut depth = 0i32;
+    for (i, tree) in trees.iter().enumerate() {
+        if let TokenTree::Punct(p) = tree {
+            match p.as_char() {
+                '<' => depth += 1,
+                '>' if !follows_joint(trees, i) => depth -= 1,
+                ',' | ';' if depth == 0 => return true,
+                _ => {}
+            }
+        }
+    }
+    false
+}
+
+fn angles_balanced(trees: &[TokenTree]) -> bool {
+    let mut depth = 0i32;
+    for (i, tree) in trees.iter().enumerate() {
+        if let TokenTree::Punct(p) = tree {
+            match p.as_char() {
+                '<' => depth += 1,
+                '>' if !follows_joint(trees, i) => depth -= 1,
+                _ => {}
+            }
+            if depth < 0 {
+                return false;
+            }
+        }
+    }
+    depth == 0
+}
+
+/// Whether the `>` at `i` is part of `->` or `=>`
+fn follows_joint(trees: &[TokenTree], i: usize) -> bool {
+    i > 0 && matches!(&trees[i - 1], TokenTree::Punct(p) if p.spacing() == Spacing::Joint && matches!(p.as_char(), '-' | '='))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sig(source: &str) -> Signature {
+        syn::parse_str::<syn::ItemFn>(source).unwrap().sig
+    }
+
+    #[test]
+    fn test_fn_patterns() {
+        let pattern = Pattern::parse("fn $name(..) -> Result<$_, io::Error>").unwrap();
+        let bindings = pattern.match_fn(&sig("pub fn read(p: &Path) -> Result<Vec<u8>, io::Error> {}")).unwrap();
+        assert_eq!(bindings.get("name").map(String::as_str), Some("read"));
+        assert!(pattern.match_fn(&sig("fn read() -> Result<(), fmt::Error> {}")).is_none());
+        assert!(pattern.match_fn(&sig("fn read() -> io::Result<()> {}")).is_none());
+
+        let pattern = Pattern::parse("async fn $_(&self, ..)").unwrap();
+        assert!(pattern.match_fn(&sig("async fn get(&self, key: &str) -> u8 {}")).is_some());
+        assert!(pattern.match_fn(&sig("async fn get(&self) {}")).is_some());
+        assert!(pattern.match_fn(&sig("fn get(&self) {}")).is_none());
+        assert!(pattern.match_fn(&sig("async fn get(self) {}")).is_none());
+
+        // A metavariable used twice must match the same tokens
+        let pattern = Pattern::parse("fn $_($a: $t, $b: $t)").unwrap();
+        assert!(pattern.match_fn(&sig("fn max(a: u32, b: u32) {}")).is_some());
+        assert!(pattern.match_fn(&sig("fn max(a: u32, b: u64) {}")).is_none());
+
+        assert!(Pattern::parse("fn $name").is_err());
+        assert!(Pattern::parse("mod foo").is_err());
+    }
+
+    #[test]
+    fn test_item_patterns() {
+        let item = |source: &str| syn::parse_str::<syn::ItemImpl>(source).unwrap();
+        let pattern = Pattern::parse("impl From<$x> for $_").unwrap();
+        let bindings = pattern.match_impl(&item("impl<T> From<Vec<T>> for Bytes {}")).unwrap();
+        assert_eq!(bindings.get("x").map(String::as_str), Some("Vec < T >"));
+        assert!(pattern.match_impl(&item("impl Bytes {}")).is_none());
+        assert!(Pattern::parse("impl Bytes").unwrap().match_impl(&item("impl Bytes {}")).is_some());
+        assert!(Pattern::parse("impl Bytes").unwrap().match_impl(&item("impl Clone for Bytes {}")).is_none());
+
+        let pattern = Pattern::parse("struct $_ { .., inner: $_, .. }").unwrap();
+        let item = |source: &str| syn::parse_str::<syn::ItemStruct>(source).unwrap();
+        assert!(pattern.match_struct(&item("pub struct Wrapper { id: u8, inner: Arc<Mutex<u8>> }")).is_some());
+        assert!(pattern.match_struct(&item("struct Unit;")).is_none());
+        assert_eq!(Pattern::parse("impl Iterator for $_<..>").unwrap().literals(), ["Iterator"]);
+    }
+}
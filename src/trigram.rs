@@ -0,0 +1,332 @@
+//! Trigram index for regex search, in the style of Google Code Search and Zoekt.
+//! Indexing records which of a crate's Rust files contain each byte trigram; a
+//! regex is reduced to a boolean query over trigrams that every file containing a
+//! match satisfies, so only those files have to be scanned.
+
+use anyhow::Result;
+use regex_syntax::hir::{Class, Hir, HirKind};
+
+/// Sets of exact strings larger than this are summarized as trigram queries
+const MAX_EXACT_STRINGS: usize = 16;
+/// Character classes larger than this (e.g. `\w`) place no constraint on files
+const MAX_CLASS_CHARS: usize = 4;
+
+/// Three bytes packed big-endian
+pub type Trigram = u32;
+
+/// Distinct trigrams of a file's bytes, sorted
+pub fn trigrams(bytes: &[u8]) -> Vec<Trigram> {
+    let mut trigrams: Vec<Trigram> = bytes
+        .windows(3)
+        .map(|w| (w[0] as u32) << 16 | (w[1] as u32) << 8 | w[2] as u32)
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+/// Posting list (sorted file positions) as delta-encoded LEB128 varints
+pub fn encode_postings(docs: &[u32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(docs.len());
+    let mut prev = 0;
+    for &doc in docs {
+        let mut delta = doc - prev;
+        prev = doc;
+        loop {
+            let byte = (delta & 0x7f) as u8;
+            delta >>= 7;
+            if delta == 0 {
+                bytes.push(byte);
+                break;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+    bytes
+}
+
+pub fn decode_postings(bytes: &[u8]) -> Vec<u32> {
+    let mut docs = Vec::new();
+    let (mut prev, mut value, mut shift) = (0u32, 0u32, 0);
+    for &byte in bytes {
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            prev += value;
+            docs.push(prev);
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+        }
+    }
+    docs
+}
+
+/// Condition on the trigrams of a file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// Any file may match
+    All,
+    Trigram(Trigram),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+impl Query {
+    /// Query satisfied by every file containing a match of `pattern`. Patterns that
+    /// don't parse or have no extractable literals give `All`.
+    pub fn from_regex(pattern: &str) -> Query {
+        match regex_syntax::parse(pattern) {
+            Ok(hir) => analyze(&hir).into_query(),
+            Err(_) => Query::All,
+        }
+    }
+
+    /// Query satisfied by files containing all of `strings`
+    pub fn all_of(strings: &[String]) -> Query {
+        strings.iter().fold(Query::All, |query, s| query.and(Query::any_of(&[s.as_bytes().to_vec()])))
+    }
+
+    /// Positions of the files that satisfy the query, given the posting list of a
+    /// trigram; `None` means every file
+    pub fn eval(&self, postings: &mut dyn FnMut(Trigram) -> Result<Vec<u32>>) -> Result<Option<Vec<u32>>> {
+        match self {
+            Query::All => Ok(None),
+            Query::Trigram(t) => postings(*t).map(Some),
+            Query::And(queries) => {
+                let mut docs: Option<Vec<u32>> = None;
+                for query in queries {
+                    if let Some(matching) = query.eval(postings)? {
+                        let narrowed = match docs {
+                            Some(docs) => intersect(&docs, &matching),
+                            None => matching,
+                        };
+                        if narrowed.is_empty() {
+                            return Ok(Some(narrowed));
+                        }
+                        docs = Some(narrowed);
+                    }
+                }
+                Ok(docs)
+            }
+            Query::Or(queries) => {
+                let mut docs = Vec::new();
+                for query in queries {
+                    match query.eval(postings)? {
+                        Some(matching) => docs = union(&docs, &matching),
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(docs))
+            }
+        }
+    }
+
+    fn and(self, other: Query) -> Query {
+        match (self, other) {
+            (Query::All, q) | (q, Query::All) => q,
+            (Query::And(mut a), Query::And(b)) => {
+                a.extend(b);
+                Query::And(a)
+            }
+            (Query::And(mut a), q) | (q, Query::And(mut a)) => {
+                a.push(q);
+                Query::And(a)
+            }
+            (a, b) => Query::And(vec![a, b]),
+        }
+    }
+
+    fn or(self, other: Query) -> Query {
+        match (self, other) {
+            (Query::All, _) | (_, Query::All) => Query::All,
+            (Query::Or(mut a), Query::Or(b)) => {
+                a.extend(b);
+                Query::Or(a)
+            }
+            (Query::Or(mut a), q) | (q, Query::Or(mut a)) => {
+                a.push(q);
+                Query::Or(a)
+            }
+            (a, b) => Query::Or(vec![a, b]),
+        }
+    }
+
+    /// Files must contain one of `strings`
+    fn any_of(strings: &[Vec<u8>]) -> Query {
+        strings.iter().fold(None, |query: Option<Query>, s| {
+            let required = trigrams(s).into_iter().fold(Query::All, |q, t| q.and(Query::Trigram(t)));
+            Some(match query {
+                Some(query) => query.or(required),
+                None => required,
+            })
+        }).unwrap_or(Query::All)
+    }
+}
+
+/// What is known about the strings a sub-expression matches
+struct Info {
+    /// Every string it can match, when there are few
+    exact: Option<Vec<Vec<u8>>>,
+    /// Condition on files containing a match, beyond `exact`
+    query: Query,
+}
+
+impl Info {
+    fn exact(strings: Vec<Vec<u8>>) -> Info {
+        Info { exact: Some(strings), query: Query::All }
+    }
+
+    fn anything() -> Info {
+        Info { exact: None, query: Query::All }
+    }
+
+    fn into_query(self) -> Query {
+        match self.exact {
+            Some(strings) => self.query.and(Query::any_of(&strings)),
+            None => self.query,
+        }
+    }
+}
+
+fn analyze(hir: &Hir) -> Info {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => Info::exact(vec![Vec::new()]),
+        HirKind::Literal(literal) => Info::exact(vec![literal.0.to_vec()]),
+        HirKind::Class(class) => match class_strings(class) {
+            Some(strings) => Info::exact(strings),
+            None => Info::anything(),
+        },
+        HirKind::Capture(capture) => analyze(&capture.sub),
+        HirKind::Repetition(repetition) if repetition.min > 0 => {
+            Info { exact: None, query: analyze(&repetition.sub).into_query() }
+        }
+        HirKind::Repetition(_) => Info::anything(),
+        HirKind::Concat(subs) => {
+            // Cross exact strings while the set stays small; once it would grow too
+            // large, the run so far becomes a query and a new run starts
+            let mut run: Option<Vec<Vec<u8>>> = Some(vec![Vec::new()]);
+            let mut query = Query::All;
+            let mut complete = true;
+            for sub in subs {
+                let info = analyze(sub);
+                match (run.take(), info.exact) {
+                    (Some(prefixes), Some(suffixes)) if prefixes.len() * suffixes.len() <= MAX_EXACT_STRINGS => {
+                        run = Some(prefixes.iter().flat_map(|p| suffixes.iter().map(move |s| [p.as_slice(), s].concat())).collect());
+                    }
+                    (prefixes, suffixes) => {
+                        complete = false;
+                        if let Some(prefixes) = prefixes {
+                            query = query.and(Query::any_of(&prefixes));
+                        }
+                        query = query.and(info.query);
+                        run = suffixes;
+                    }
+                }
+            }
+            match run {
+                Some(strings) if complete => Info::exact(strings),
+                Some(strings) => Info { exact: None, query: query.and(Query::any_of(&strings)) },
+                None => Info { exact: None, query },
+            }
+        }
+        HirKind::Alternation(subs) => {
+            let infos: Vec<Info> = subs.iter().map(analyze).collect();
+            let exact_count: Option<usize> = infos.iter().map(|i| i.exact.as_ref().map(Vec::len)).sum();
+            if exact_count.is_some_and(|n| n <= MAX_EXACT_STRINGS) {
+                Info::exact(infos.into_iter().flat_map(|i| i.exact.unwrap_or_default()).collect())
+            } else {
+                let query = infos.into_iter().map(Info::into_query).reduce(Query::or).unwrap_or(Query::All);
+                Info { exact: None, query }
+            }
+        }
+    }
+}
+
+/// UTF-8 (or raw byte) strings of a small character class
+fn class_strings(class: &Class) -> Option<Vec<Vec<u8>>> {
+    match class {
+        Class::Unicode(class) => {
+            let count: u32 = class.ranges().iter().map(|r| r.end() as u32 - r.start() as u32 + 1).sum();
+            if count as usize > MAX_CLASS_CHARS {
+                return None;
+            }
+            Some(
+                class.ranges().iter()
+                    .flat_map(|r| r.start()..=r.end())
+                    .map(|c| c.to_string().into_bytes())
+                    .collect(),
+            )
+        }
+        Class::Bytes(class) => {
+            let count: usize = class.ranges().iter().map(|r| r.end() as usize - r.start() as usize + 1).sum();
+            if count > MAX_CLASS_CHARS {
+                return None;
+            }
+            Some(class.ranges().iter().flat_map(|r| r.start()..=r.end()).map(|b| vec![b]).collect())
+        }
+    }
+}
+
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (mut i, mut j, mut out) = (0, 0, Vec::new());
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out
+}
+
+fn union(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out: Vec<u32> = a.iter().chain(b).copied().collect();
+    out.sort_unstable();
+    out.dedup();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t(s: &str) -> Query {
+        let b = s.as_bytes();
+        Query::Trigram((b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32)
+    }
+
+    #[test]
+    fn test_regex_queries() {
+        assert_eq!(Query::from_regex("impl"), Query::And(vec![t("imp"), t("mpl")]));
+        assert_eq!(Query::from_regex(r"fn\s+new"), t("new"));
+        assert_eq!(Query::from_regex("foo|bar"), Query::Or(vec![t("foo"), t("bar")]));
+        assert_eq!(Query::from_regex("(Vec|Box)<u8>"), Query::from_regex("Vec<u8>|Box<u8>"));
+        assert_eq!(Query::from_regex(r"\w+\(\)"), Query::All);
+        assert_eq!(Query::from_regex("ab.*"), Query::All);
+        assert_eq!(Query::from_regex("(unclosed"), Query::All);
+
+        // Case-insensitive literals expand to a bounded set of spellings
+        let Query::Or(spellings) = Query::from_regex("(?i)drop") else { panic!("expected alternatives") };
+        assert_eq!(spellings.len(), 16);
+    }
+
+    #[test]
+    fn test_eval_and_postings() {
+        let docs = [vec![0, 3, 130, 20000], vec![3, 7, 20000]];
+        for d in &docs {
+            assert_eq!(&decode_postings(&encode_postings(d)), d);
+        }
+
+        let mut postings = |t: Trigram| Ok(if t == 1 { docs[0].clone() } else { docs[1].clone() });
+        let both = Query::And(vec![Query::Trigram(1), Query::Trigram(2)]);
+        assert_eq!(both.eval(&mut postings).unwrap(), Some(vec![3, 20000]));
+        let either = Query::Or(vec![Query::Trigram(1), Query::Trigram(2)]);
+        assert_eq!(either.eval(&mut postings).unwrap(), Some(vec![0, 3, 7, 130, 20000]));
+        assert_eq!(Query::Or(vec![Query::Trigram(1), Query::All]).eval(&mut postings).unwrap(), None);
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CHECKSUM_FILE: &str = ".cargo-checksum.json";
+
+/// A crate directory produced by `cargo vendor`
+#[derive(Debug)]
+pub struct VendoredCrate {
+    pub name: String,
+    pub version: String,
+    pub path: PathBuf,
+}
+
+impl VendoredCrate {
+    pub fn key(&self) -> String {
+        format!("{}-{}", self.name, self.version)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChecksumFile {
+    files: BTreeMap<String, String>,
+}
+
+/// List vendored crates. Directory names may be `name` or `name-version`, so
+/// the real name and version are read from each crate's `Cargo.toml`.
+pub fn scan_vendor_dir(vendor_dir: &Path) -> Result<Vec<VendoredCrate>> {
+    let entries = fs::read_dir(vendor_dir)
+        .with_context(|| format!("Failed to read vendor directory {:?}", vendor_dir))?;
+
+    let mut crates = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.join("Cargo.toml").exists() {
+            continue;
+        }
+        match read_package(&path) {
+            Ok((name, version)) => crates.push(VendoredCrate { name, version, path }),
+            Err(e) => eprintln!("Warning: Skipping {:?}: {}", path, e),
+        }
+    }
+
+    crates.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version)));
+    Ok(crates)
+}
+
+fn read_package(crate_dir: &Path) -> Result<(String, String)> {
+    let content = fs::read_to_string(crate_dir.join("Cargo.toml"))?;
+    let toml: toml::Table = content.parse().context("Invalid Cargo.toml")?;
+    let package = toml
+        .get("package")
+        .ok_or_else(|| anyhow::anyhow!("Cargo.toml has no [package] section"))?;
+
+    let name = package.get("name").and_then(|n| n.as_str());
+    let version = package.get("version").and_then(|v| v.as_str());
+    match (name, version) {
+        (Some(name), Some(version)) => Ok((name.to_string(), version.to_string())),
+        _ => bail!("Cargo.toml is missing package name or version"),
+    }
+}
+
+/// Check every file listed in `.cargo-checksum.json` against its SHA-256
+pub fn verify_checksums(crate_dir: &Path) -> Result<()> {
+    let checksum_path = crate_dir.join(CHECKSUM_FILE);
+    let content = fs::read_to_string(&checksum_path)
+        .with_context(|| format!("Missing {}", CHECKSUM_FILE))?;
+    let checksums: ChecksumFile = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid {}", CHECKSUM_FILE))?;
+
+    for (file, expected) in &checksums.files {
+        let bytes = fs::read(crate_dir.join(file))
+            .with_context(|| format!("Checksummed file '{}' is missing", file))?;
+        let actual = hex::encode(Sha256::digest(&bytes));
+        if !actual.eq_ignore_ascii_case(expected) {
+            bail!("Checksum mismatch for '{}'", file);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_crate(vendor: &Path, dir: &str, name: &str, version: &str) -> PathBuf {
+        let path = vendor.join(dir);
+        fs::create_dir_all(path.join("src")).unwrap();
+        let manifest = format!("[package]\nname = \"{}\"\nversion = \"{}\"\n", name, version);
+        let lib = "pub fn vendored() {}\n";
+        fs::write(path.join("Cargo.toml"), &manifest).unwrap();
+        fs::write(path.join("src/lib.rs"), lib).unwrap();
+        let checksums = serde_json::json!({
+            "files": {
+                "Cargo.toml": hex::encode(Sha256::digest(manifest.as_bytes())),
+                "src/lib.rs": hex::encode(Sha256::digest(lib.as_bytes())),
+            },
+            "package": "0000",
+        });
+        fs::write(path.join(CHECKSUM_FILE), checksums.to_string()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_scan_and_verify() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_crate(tmp.path(), "syn", "syn", "2.0.114");
+        let old_syn = write_crate(tmp.path(), "syn-1.0.109", "syn", "1.0.109");
+
+        let crates = scan_vendor_dir(tmp.path()).unwrap();
+        let keys: Vec<String> = crates.iter().map(|c| c.key()).collect();
+        assert_eq!(keys, vec!["syn-1.0.109", "syn-2.0.114"]);
+        for c in &crates {
+            verify_checksums(&c.path).unwrap();
+        }
+
+        fs::write(old_syn.join("src/lib.rs"), "pub fn tampered() {}\n").unwrap();
+        let err = verify_checksums(&old_syn).unwrap_err();
+        assert!(err.to_string().contains("src/lib.rs"));
+    }
+}
@@ -0,0 +1,200 @@
+-- An index.db as written before schema versioning (PRAGMA user_version = 0),
+-- with one indexed crate. Used by the migration tests in src/storage.rs.
+
+CREATE TABLE IF NOT EXISTS crates (
+    id INTEGER PRIMARY KEY,
+    key TEXT UNIQUE NOT NULL,
+    path TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS functions (
+    id TEXT PRIMARY KEY,
+    crate_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    file TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    end_line INTEGER,
+    signature TEXT NOT NULL,
+    docs TEXT,
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS structs (
+    id TEXT PRIMARY KEY,
+    crate_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    file TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    end_line INTEGER,
+    visibility TEXT NOT NULL,
+    docs TEXT,
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS struct_fields (
+    id INTEGER PRIMARY KEY,
+    struct_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    type_str TEXT NOT NULL,
+    visibility TEXT NOT NULL,
+    docs TEXT,
+    FOREIGN KEY (struct_id) REFERENCES structs(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS enums (
+    id TEXT PRIMARY KEY,
+    crate_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    file TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    end_line INTEGER,
+    visibility TEXT NOT NULL,
+    docs TEXT,
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS enum_variants (
+    id INTEGER PRIMARY KEY,
+    enum_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    fields TEXT,
+    docs TEXT,
+    FOREIGN KEY (enum_id) REFERENCES enums(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS traits (
+    id TEXT PRIMARY KEY,
+    crate_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    file TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    end_line INTEGER,
+    visibility TEXT NOT NULL,
+    docs TEXT,
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS macros (
+    id TEXT PRIMARY KEY,
+    crate_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    file TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    end_line INTEGER,
+    kind TEXT NOT NULL,
+    docs TEXT,
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS type_aliases (
+    id TEXT PRIMARY KEY,
+    crate_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    file TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    type_str TEXT NOT NULL,
+    visibility TEXT NOT NULL,
+    docs TEXT,
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS constants (
+    id TEXT PRIMARY KEY,
+    crate_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    file TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    type_str TEXT NOT NULL,
+    visibility TEXT NOT NULL,
+    docs TEXT,
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS impls (
+    id TEXT PRIMARY KEY,
+    crate_id INTEGER NOT NULL,
+    file TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    end_line INTEGER,
+    self_type TEXT NOT NULL,
+    trait_name TEXT,
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS reexports (
+    crate_id INTEGER NOT NULL,
+    reexported_crate TEXT NOT NULL,
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE,
+    PRIMARY KEY (crate_id, reexported_crate)
+);
+
+CREATE INDEX IF NOT EXISTS idx_functions_crate ON functions(crate_id);
+CREATE INDEX IF NOT EXISTS idx_functions_name ON functions(name);
+CREATE INDEX IF NOT EXISTS idx_structs_crate ON structs(crate_id);
+CREATE INDEX IF NOT EXISTS idx_structs_name ON structs(name);
+CREATE INDEX IF NOT EXISTS idx_enums_crate ON enums(crate_id);
+CREATE INDEX IF NOT EXISTS idx_enums_name ON enums(name);
+CREATE INDEX IF NOT EXISTS idx_traits_crate ON traits(crate_id);
+CREATE INDEX IF NOT EXISTS idx_traits_name ON traits(name);
+CREATE INDEX IF NOT EXISTS idx_macros_crate ON macros(crate_id);
+CREATE INDEX IF NOT EXISTS idx_macros_name ON macros(name);
+CREATE INDEX IF NOT EXISTS idx_type_aliases_crate ON type_aliases(crate_id);
+CREATE INDEX IF NOT EXISTS idx_constants_crate ON constants(crate_id);
+CREATE INDEX IF NOT EXISTS idx_impls_crate ON impls(crate_id);
+CREATE INDEX IF NOT EXISTS idx_impls_self_type ON impls(self_type);
+CREATE INDEX IF NOT EXISTS idx_reexports_crate ON reexports(crate_id);
+
+CREATE TABLE IF NOT EXISTS dependencies (
+    crate_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    req TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    optional INTEGER NOT NULL,
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE,
+    PRIMARY KEY (crate_id, name, kind)
+);
+CREATE INDEX IF NOT EXISTS idx_dependencies_name ON dependencies(name);
+
+CREATE TABLE IF NOT EXISTS crate_metadata (
+    crate_id INTEGER PRIMARY KEY,
+    description TEXT,
+    license TEXT,
+    repository TEXT,
+    homepage TEXT,
+    documentation TEXT,
+    edition TEXT,
+    rust_version TEXT,
+    authors TEXT NOT NULL,    -- JSON array
+    keywords TEXT NOT NULL,   -- JSON array
+    categories TEXT NOT NULL, -- JSON array
+    features TEXT NOT NULL,   -- JSON object: feature -> enabled features/dependencies
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS embeddings (
+    id TEXT PRIMARY KEY,
+    item_type TEXT NOT NULL,
+    crate_id INTEGER NOT NULL,
+    embedding BLOB NOT NULL,
+    text_content TEXT NOT NULL,
+    FOREIGN KEY (crate_id) REFERENCES crates(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_embeddings_crate ON embeddings(crate_id);
+CREATE INDEX IF NOT EXISTS idx_embeddings_type ON embeddings(item_type);
+
+CREATE TABLE IF NOT EXISTS latest_versions (
+    crate_name TEXT PRIMARY KEY,
+    version TEXT NOT NULL,
+    checked_at INTEGER NOT NULL
+);
+
+INSERT INTO crates (id, key, path) VALUES (1, 'demo-0.1.0', '/nonexistent/demo-0.1.0');
+INSERT INTO functions (id, crate_id, name, file, line, end_line, signature, docs)
+    VALUES ('0a1b2c3d', 1, 'old_name', 'src/lib.rs', 1, 1, 'pub fn old_name()', NULL);
+INSERT INTO structs (id, crate_id, name, file, line, end_line, visibility, docs)
+    VALUES ('1a2b3c4d', 1, 'Config', 'src/lib.rs', 3, 5, 'pub', 'Settings');
+INSERT INTO struct_fields (struct_id, name, type_str, visibility, docs)
+    VALUES ('1a2b3c4d', 'verbose', 'bool', 'pub', NULL);
+INSERT INTO latest_versions (crate_name, version, checked_at) VALUES ('demo', '0.1.0', 0);